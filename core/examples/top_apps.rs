@@ -0,0 +1,18 @@
+//! Prints the apps used the most over the past day, a minimal frontend on top of
+//! `appusage-core`.
+
+use std::time::Duration;
+
+use appusage_core::{Database, TimeRange};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::open(&appusage_core::default_path()?)?;
+
+    let day = TimeRange::last(Duration::from_secs(24 * 60 * 60));
+    println!("{}s used in the past day", db.total(Some(day))?.as_secs());
+    for app in db.apps(Some(day))?.iter().take(10) {
+        println!("{:>8}s  {}", app.total.as_secs(), app.app_id);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,343 @@
+//! Settings shared by the daemon and the tui, read from `config.toml` in the XDG config
+//! directory (usually `~/.config/wayland-appusage/config.toml`).
+//!
+//! Every setting is optional, a missing file or setting means its default is used.
+//!
+//! ```toml
+//! idle_ms = 60000
+//! min_session_ms = 2000
+//! db_path = "/home/me/appusage.db"
+//! retention_days = 365
+//! archive_pruned = true
+//! vacuum_interval_days = 7
+//! split_on_title = false
+//! idle_on_any_seat = false
+//! idle_grace_ms = 0
+//! fold_app_id_case = true
+//! ignore = ["swaylock", "org.kde.*"]
+//! record_only = []
+//! unidentified_apps = "title"
+//! daily_total_limit = 480
+//! limit_message = "You used {app} for {used} today, your limit is {limit}."
+//! total_limit_message = "You used {app} for {used} today, your limit is {limit}."
+//!
+//! [aliases]
+//! "org.mozilla.firefox" = "firefox"
+//!
+//! [categories]
+//! Work = ["foot", "code"]
+//! Games = ["steam"]
+//!
+//! [daily_limits]
+//! steam = 120
+//!
+//! [daily_goals]
+//! anki = 15
+//!
+//! [colors]
+//! selected = "yellow"
+//! bars = "#5e81ac"
+//!
+//! [web_apps]
+//! rules = ['^kiosk-(?P<domain>.+)$']
+//!
+//! [web_apps.title_rules]
+//! firefox = ['[-—] (?P<domain>[^-—]+) — Mozilla Firefox$']
+//! ```
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::Error;
+
+/// Environment variable pointing both the daemon and the tui at another database, it takes
+/// precedence over `db_path` in the config.
+pub const DB_PATH_ENV: &str = "WAYLAND_APPUSAGE_DB";
+
+pub const DEFAULT_IDLE_MS: u32 = 30_000;
+/// Focus shorter than this is almost always just passing through while switching windows.
+pub const DEFAULT_MIN_SESSION_MS: u64 = 1000;
+/// `{app}` is `all apps` for the total limit, so this works for both kinds of limits.
+pub const DEFAULT_LIMIT_MESSAGE: &str = "You used {app} for {used} today, your limit is {limit}.";
+/// What windows that never tell which app they belong to are recorded as, see
+/// [`UnidentifiedApps`].
+pub const UNIDENTIFIED_APP_ID: &str = "unidentified";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Config {
+    /// How long without input (in ms) until the user counts as idle.
+    pub idle_ms: u32,
+    /// Sessions shorter than this (in ms) aren't recorded, 0 records everything.
+    pub min_session_ms: u64,
+    /// Database to use instead of the one in the XDG data directory.
+    pub db_path: Option<PathBuf>,
+    /// Sessions older than this many days are pruned when the daemon starts, unset keeps
+    /// them forever.
+    pub retention_days: Option<u32>,
+    /// Whether pruned sessions are summed up into a daily archive instead of being deleted
+    /// outright, which keeps all time totals intact.
+    pub archive_pruned: bool,
+    /// How often (in days) the daemon compacts the database with `VACUUM`, so space freed
+    /// by pruning is given back. Unset never does, `appusage vacuum` can still be run by hand.
+    pub vacuum_interval_days: Option<u32>,
+    /// Whether a window changing its title ends its session and starts a new one, so every
+    /// title gets its own sessions instead of the one at the end getting all of it.
+    pub split_on_title: bool,
+    /// Whether the user counts as idle as soon as one seat is, instead of once every seat
+    /// is, on systems with several seats.
+    pub idle_on_any_seat: bool,
+    /// Idle stretches shorter than this (in ms) don't end sessions, their time counts as
+    /// usage instead. 0 ends sessions as soon as the user goes idle.
+    pub idle_grace_ms: u64,
+    /// Names to show apps under instead of their app ids, by app id. Only applied when usage
+    /// is shown, the recorded app ids stay as they are.
+    pub aliases: HashMap<String, String>,
+    /// Whether app ids that only differ in case count as the same app, also when looking
+    /// them up in `aliases`.
+    pub fold_app_id_case: bool,
+    /// App ids the daemon doesn't record, a `*` matches any run of characters, like in
+    /// `org.kde.*`. Focusing them still ends the session of the app focused before.
+    pub ignore: Vec<String>,
+    /// The only app ids the daemon records if any are given, with the same patterns as
+    /// `ignore`. Takes precedence over it, an app in both is recorded.
+    pub record_only: Vec<String>,
+    /// What the usage of windows that never tell which app they belong to is recorded as.
+    pub unidentified_apps: UnidentifiedApps,
+    /// App ids in each category, for the tui to sum up usage by category.
+    pub categories: HashMap<String, Vec<String>>,
+    /// How many minutes each app may be used a day, by app id, the daemon shows a
+    /// notification once one of them is reached.
+    pub daily_limits: HashMap<String, u64>,
+    /// How many minutes all apps together may be used a day.
+    pub daily_total_limit: Option<u64>,
+    /// How many minutes each app should be used at least a day, by app id, the tui shows
+    /// for how many days in a row that worked out.
+    pub daily_goals: HashMap<String, u64>,
+    /// Notification shown when an app reaches its daily limit, `{app}`, `{used}` and
+    /// `{limit}` are replaced with the app id and the durations.
+    pub limit_message: String,
+    /// Notification shown when the daily total limit is reached, with the same placeholders.
+    pub total_limit_message: String,
+    /// Colors the tui uses for its parts.
+    pub colors: ColorConfig,
+    /// How the tui tells which site a web app or browser window belongs to.
+    pub web_apps: WebAppConfig,
+}
+
+/// Colors of parts of the tui, as names like `yellow` or hex like `#5e81ac`. Unset ones keep
+/// their default, the tui checks that the others are colors when it starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct ColorConfig {
+    /// The selected entry of the app list.
+    pub selected: Option<String>,
+    pub bars: Option<String>,
+    /// Today's bar, which stands out from the rest, cyan by default.
+    pub today: Option<String>,
+    pub borders: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Rules for grouping usage by site in the tui, regular expressions with a `domain` capture
+/// group. The tui checks that they are valid when it starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct WebAppConfig {
+    /// Matched against app ids, for browsers giving every installed web app its own. Tried
+    /// before the built in rules for Chromium based browsers.
+    pub rules: Vec<String>,
+    /// Matched against the window titles of each browser, by app id or the alias it is shown
+    /// under, so the sites opened in a browser's windows get their own entries too.
+    pub title_rules: HashMap<String, Vec<String>>,
+}
+
+/// What the daemon does with windows that never send an app id, some only send a title.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnidentifiedApps {
+    /// Records them as `unidentified:<title>`, with the title the window had when its
+    /// session ended, or as `unidentified` if it has none either.
+    #[default]
+    Title,
+    /// Records all of them as `unidentified`.
+    Combined,
+    /// Doesn't record them at all.
+    Drop,
+}
+
+impl UnidentifiedApps {
+    /// Returns the app id to record a window without one as, if it is recorded.
+    pub fn app_id(self, title: Option<&str>) -> Option<String> {
+        match (self, title) {
+            (Self::Title, Some(title)) => Some(format!("{UNIDENTIFIED_APP_ID}:{title}")),
+            (Self::Title | Self::Combined, _) => Some(UNIDENTIFIED_APP_ID.to_string()),
+            (Self::Drop, _) => None,
+        }
+    }
+}
+
+/// Whether the app id was made up for a window that didn't send one, see
+/// [`UnidentifiedApps`].
+pub fn is_unidentified(app_id: &str) -> bool {
+    app_id
+        .strip_prefix(UNIDENTIFIED_APP_ID)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            idle_ms: DEFAULT_IDLE_MS,
+            min_session_ms: DEFAULT_MIN_SESSION_MS,
+            db_path: None,
+            retention_days: None,
+            archive_pruned: true,
+            vacuum_interval_days: None,
+            split_on_title: false,
+            idle_on_any_seat: false,
+            idle_grace_ms: 0,
+            aliases: HashMap::new(),
+            fold_app_id_case: true,
+            ignore: vec![],
+            record_only: vec![],
+            unidentified_apps: UnidentifiedApps::default(),
+            categories: HashMap::new(),
+            daily_limits: HashMap::new(),
+            daily_total_limit: None,
+            daily_goals: HashMap::new(),
+            limit_message: DEFAULT_LIMIT_MESSAGE.to_string(),
+            total_limit_message: DEFAULT_LIMIT_MESSAGE.to_string(),
+            colors: ColorConfig::default(),
+            web_apps: WebAppConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file from the XDG config directory, or the defaults if there is none.
+    pub fn load() -> Result<Self, Error> {
+        let path = xdg::BaseDirectories::with_prefix("wayland-appusage")
+            .map_err(|e| Error::NoDataDir(e.to_string()))?
+            .find_config_file("config.toml");
+        match path {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("could not read {}: {e}", path.display())))?;
+        Self::parse(&text).map_err(|e| Error::Config(format!("{} is invalid: {e}", path.display())))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        if config.idle_ms == 0 {
+            return Err("idle_ms has to be more than 0".to_string());
+        }
+        if config.retention_days == Some(0) {
+            return Err("retention_days has to be more than 0".to_string());
+        }
+        if config.vacuum_interval_days == Some(0) {
+            return Err("vacuum_interval_days has to be more than 0".to_string());
+        }
+        if config.daily_total_limit == Some(0) {
+            return Err("daily_total_limit has to be more than 0".to_string());
+        }
+        if let Some((app_id, _)) = config.daily_limits.iter().find(|(_, limit)| **limit == 0) {
+            return Err(format!("the daily limit of {app_id} has to be more than 0"));
+        }
+        Ok(config)
+    }
+
+    /// Returns where the database is, the one in [`DB_PATH_ENV`] if it is set, otherwise the
+    /// configured path or the default one.
+    pub fn db_path(&self) -> Result<PathBuf, Error> {
+        self.db_path_with(std::env::var_os(DB_PATH_ENV))
+    }
+
+    fn db_path_with(&self, from_env: Option<OsString>) -> Result<PathBuf, Error> {
+        // an empty variable is most likely a mistake in a script, not a path
+        match (from_env.filter(|x| !x.is_empty()), &self.db_path) {
+            (Some(path), _) => Ok(path.into()),
+            (None, Some(path)) => Ok(path.clone()),
+            (None, None) => crate::default_path(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_settings_use_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("idle_ms = 60000").unwrap(),
+            Config {
+                idle_ms: 60_000,
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_config_is_an_error() {
+        assert!(Config::parse("idle_ms = ").is_err());
+        assert!(Config::parse("idle_ms = \"soon\"").is_err());
+        assert!(Config::parse("idle_ms = 0").is_err());
+        assert!(Config::parse("retention_days = 0").is_err());
+        assert!(Config::parse("vacuum_interval_days = 0").is_err());
+        assert!(Config::parse("[daily_limits]\nfirefox = 0").is_err());
+        assert!(Config::parse("idle_mss = 1000").is_err());
+    }
+
+    #[test]
+    fn environment_overrides_configured_db_path() {
+        let config = Config::parse("db_path = \"/data/usage.db\"").unwrap();
+        assert_eq!(
+            config.db_path_with(Some("/tmp/test.db".into())).unwrap(),
+            Path::new("/tmp/test.db")
+        );
+        assert_eq!(
+            config.db_path_with(Some("".into())).unwrap(),
+            Path::new("/data/usage.db")
+        );
+        assert_eq!(
+            config.db_path_with(None).unwrap(),
+            Path::new("/data/usage.db")
+        );
+    }
+
+    #[test]
+    fn unidentified_apps_are_named_after_their_title() {
+        let config = Config::parse("unidentified_apps = \"combined\"").unwrap();
+        assert_eq!(config.unidentified_apps, UnidentifiedApps::Combined);
+        assert!(Config::parse("unidentified_apps = \"handle\"").is_err());
+
+        let title = UnidentifiedApps::Title.app_id(Some("Untitled 1"));
+        assert_eq!(title.as_deref(), Some("unidentified:Untitled 1"));
+        assert_eq!(
+            UnidentifiedApps::Title.app_id(None).as_deref(),
+            Some("unidentified")
+        );
+        assert_eq!(UnidentifiedApps::Drop.app_id(Some("Untitled 1")), None);
+
+        assert!(is_unidentified("unidentified:Untitled 1"));
+        assert!(is_unidentified("unidentified"));
+        assert!(!is_unidentified("unidentified-app"));
+        assert!(!is_unidentified("firefox"));
+    }
+}
@@ -0,0 +1,393 @@
+//! Access to the usage database recorded by `appusage-daemon`, for building frontends like
+//! status bars, other tuis or web dashboards on top of it.
+//!
+//! Times are [`SystemTime`]s and lengths are [`Duration`]s, the millisecond columns they are
+//! stored as are an implementation detail.
+//!
+//! ```
+//! use appusage_core::{Database, TimeRange};
+//!
+//! let db = Database::open_in_memory()?;
+//! for app in db.apps(Some(TimeRange::last(std::time::Duration::from_secs(86400))))? {
+//!     println!("{}: {:?}", app.app_id, app.total);
+//! }
+//! # Ok::<(), appusage_core::Error>(())
+//! ```
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, params};
+
+pub mod config;
+pub mod maintenance;
+pub mod retention;
+pub mod schema;
+
+/// Re-exported for frontends that need queries this crate doesn't offer, the schema is only
+/// stable as far as [`schema::migrate`] goes.
+pub use rusqlite;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// There is no XDG data directory to find the database in.
+    NoDataDir(String),
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer version, which may have changed it in ways this
+    /// one doesn't understand.
+    NewerSchema {
+        found: u32,
+        supported: u32,
+    },
+    /// The config file couldn't be read or isn't valid.
+    Config(String),
+    /// A transaction is open on the connection, so it can't be vacuumed.
+    InTransaction,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoDataDir(e) => write!(f, "could not find the data directory: {e}"),
+            Error::Sqlite(e) => write!(f, "database error: {e}"),
+            Error::Config(e) => write!(f, "config error: {e}"),
+            Error::InTransaction => write!(f, "a transaction is still open on the connection"),
+            Error::NewerSchema { found, supported } => write!(
+                f,
+                "database has schema version {found} but only up to {supported} is supported, \
+                 it was written by a newer version"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NoDataDir(_)
+            | Error::NewerSchema { .. }
+            | Error::Config(_)
+            | Error::InTransaction => None,
+            Error::Sqlite(e) => Some(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+/// Returns where the daemon keeps its database by default, inside the XDG data directory.
+pub fn default_path() -> Result<PathBuf, Error> {
+    xdg::BaseDirectories::with_prefix("wayland-appusage")
+        .map_err(|e| Error::NoDataDir(e.to_string()))?
+        .place_data_file("app_usage.db")
+        .map_err(|e| Error::NoDataDir(e.to_string()))
+}
+
+/// Length of a day in milliseconds, the `day` columns of the daily tables count these from
+/// the unix epoch, starting at midnight UTC for the archive and at local midnight for the
+/// rest.
+pub const DAY_MS: u64 = 86_400_000;
+
+/// How long a connection waits for another one to finish writing before failing with
+/// "database is locked".
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prepares a connection to share the database with the daemon and other frontends.
+///
+/// This switches the database to WAL mode, so reading never blocks the daemon from writing
+/// and the other way around, and makes writes wait for each other instead of failing.
+pub fn configure(conn: &Connection) -> Result<(), Error> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // in memory databases stay in "memory" mode, which is fine since nothing else can open them
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    Ok(())
+}
+
+/// The day weeks start on in the locale used for dates, from the region of `LC_ALL`,
+/// `LC_TIME` or `LANG`, counted in days after monday.
+pub fn locale_week_start() -> u8 {
+    let locale = ["LC_ALL", "LC_TIME", "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    week_start_of(&locale)
+}
+
+/// The day weeks start on in a locale like `en_US.UTF-8`, in days after monday, which it is
+/// unless the region of the locale is known to start them on another day.
+fn week_start_of(locale: &str) -> u8 {
+    let region = locale
+        .split(['.', '@'])
+        .next()
+        .and_then(|name| name.split_once('_'))
+        .map_or("", |(_, region)| region);
+    match region {
+        "AG" | "AS" | "BD" | "BR" | "BS" | "BT" | "BW" | "BZ" | "CA" | "CN" | "CO" | "DM"
+        | "DO" | "ET" | "GT" | "GU" | "HK" | "HN" | "ID" | "IL" | "IN" | "JM" | "JP" | "KE"
+        | "KH" | "KR" | "LA" | "MH" | "MM" | "MO" | "MT" | "MX" | "MZ" | "NI" | "NP" | "PA"
+        | "PE" | "PH" | "PK" | "PR" | "PT" | "PY" | "SA" | "SG" | "SV" | "TH" | "TT" | "TW"
+        | "UM" | "US" | "VE" | "VI" | "WS" | "YE" | "ZA" | "ZW" => 6,
+        "AE" | "AF" | "BH" | "DJ" | "DZ" | "EG" | "IQ" | "IR" | "JO" | "KW" | "LY" | "OM"
+        | "QA" | "SD" | "SY" => 5,
+        _ => 0,
+    }
+}
+
+/// A span of time, sessions count towards it with the part of them that lies within it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+impl TimeRange {
+    pub fn new(start: SystemTime, end: SystemTime) -> Self {
+        Self { start, end }
+    }
+
+    /// The range ending now and reaching `length` into the past.
+    pub fn last(length: Duration) -> Self {
+        let end = SystemTime::now();
+        Self {
+            start: end.checked_sub(length).unwrap_or(UNIX_EPOCH),
+            end,
+        }
+    }
+
+    fn millis(&self) -> (u64, u64) {
+        (to_millis(self.start), to_millis(self.end))
+    }
+}
+
+/// How long an app was used in total.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AppUsage {
+    pub app_id: String,
+    pub total: Duration,
+}
+
+/// How much of a session lies within the range `?1..?2`, never more than its duration.
+const DURATION_IN_RANGE: &str = "min(duration, min(end_time, ?2) - max(start_time, ?1))";
+
+/// Sessions overlapping the range `?1..?2`.
+const OVERLAPS_RANGE: &str = "end_time > ?1 AND start_time < ?2";
+
+/// A single stretch of time an app was focused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Session {
+    pub app_id: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub duration: Duration,
+}
+
+/// A usage database, opening one migrates it to the current schema.
+pub struct Database {
+    conn: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it if it doesn't exist.
+    ///
+    /// ```no_run
+    /// let db = appusage_core::Database::open(&appusage_core::default_path()?)?;
+    /// # Ok::<(), appusage_core::Error>(())
+    /// ```
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens an empty database that only lives in memory, mostly useful for tests.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, Error> {
+        configure(&conn)?;
+        schema::migrate(&conn)?;
+        Ok(Self { conn })
+    }
+
+    /// The underlying connection, for queries this crate doesn't offer.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Returns the total usage of every app within `range`, or over all time, most used
+    /// first.
+    ///
+    /// ```
+    /// # let db = appusage_core::Database::open_in_memory()?;
+    /// db.record("firefox", std::time::SystemTime::now(), std::time::Duration::from_secs(60))?;
+    /// assert_eq!(db.apps(None)?[0].app_id, "firefox");
+    /// # Ok::<(), appusage_core::Error>(())
+    /// ```
+    pub fn apps(&self, range: Option<TimeRange>) -> Result<Vec<AppUsage>, Error> {
+        let (start_time, end_time) = range.map_or((0, i64::MAX as u64), |x| x.millis());
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT app_name, sum({DURATION_IN_RANGE}) AS total
+                FROM app_usage
+                WHERE {OVERLAPS_RANGE}
+                GROUP BY app_name
+                ORDER BY total DESC"
+        ))?;
+        let x = stmt
+            .query_map([start_time, end_time], |row| {
+                Ok(AppUsage {
+                    app_id: row.get(0)?,
+                    total: non_negative_millis(row.get(1)?),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(x)
+    }
+
+    /// Returns every session overlapping `range`, or over all time, oldest first.
+    ///
+    /// Sessions crossing the start or end of the range are cut off at it.
+    pub fn sessions(&self, range: Option<TimeRange>) -> Result<Vec<Session>, Error> {
+        let (start_time, end_time) = range.map_or((0, i64::MAX as u64), |x| x.millis());
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT app_name, max(start_time, ?1), min(end_time, ?2), {DURATION_IN_RANGE}
+                FROM app_usage
+                WHERE {OVERLAPS_RANGE}
+                ORDER BY start_time"
+        ))?;
+        let x = stmt
+            .query_map([start_time, end_time], |row| {
+                Ok(Session {
+                    app_id: row.get(0)?,
+                    start: from_millis(row.get(1)?),
+                    end: from_millis(row.get(2)?),
+                    duration: non_negative_millis(row.get(3)?),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(x)
+    }
+
+    /// Returns how long all apps combined were used within `range`, or over all time.
+    pub fn total(&self, range: Option<TimeRange>) -> Result<Duration, Error> {
+        let (start_time, end_time) = range.map_or((0, i64::MAX as u64), |x| x.millis());
+        let total = self.conn.query_row(
+            &format!(
+                "SELECT coalesce(sum({DURATION_IN_RANGE}), 0)
+                    FROM app_usage
+                    WHERE {OVERLAPS_RANGE}"
+            ),
+            [start_time, end_time],
+            |row| row.get(0),
+        )?;
+        Ok(non_negative_millis(total))
+    }
+
+    /// Records that `app_id` was used for `duration` up until `end`.
+    pub fn record(&self, app_id: &str, end: SystemTime, duration: Duration) -> Result<(), Error> {
+        let end_time = to_millis(end);
+        let duration = duration.as_millis().try_into().unwrap_or(u64::MAX);
+        self.conn.execute(
+            "INSERT INTO app_usage (app_name, start_time, end_time, duration) VALUES (?1, ?2, ?3, ?4)",
+            params![app_id, end_time.saturating_sub(duration), end_time, duration],
+        )?;
+        Ok(())
+    }
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_millis().try_into().unwrap_or(i64::MAX as u64))
+}
+
+/// Rows that end before they start come out with a negative length, they count as nothing.
+fn non_negative_millis(millis: i64) -> Duration {
+    Duration::from_millis(millis.max(0) as u64)
+}
+
+fn from_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn reading_while_another_connection_writes() {
+        let path = std::env::temp_dir().join(format!("appusage-wal-{}.db", std::process::id()));
+        let writer = Database::open(&path).unwrap();
+        let reader = Database::open(&path).unwrap();
+
+        let mode: String = reader
+            .connection()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        writer
+            .record("firefox", SystemTime::now(), Duration::from_secs(60))
+            .unwrap();
+        writer
+            .connection()
+            .execute_batch("BEGIN IMMEDIATE")
+            .unwrap();
+        writer
+            .record("foot", SystemTime::now(), Duration::from_secs(30))
+            .unwrap();
+
+        // the uncommitted write neither blocks the reader nor shows up for it
+        assert_eq!(reader.total(None).unwrap(), Duration::from_secs(60));
+
+        writer.connection().execute_batch("COMMIT").unwrap();
+        assert_eq!(reader.total(None).unwrap(), Duration::from_secs(90));
+
+        drop((writer, reader));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[test]
+    fn week_start_comes_from_the_region() {
+        assert_eq!(week_start_of("en_US.UTF-8"), 6);
+        assert_eq!(week_start_of("en_GB.UTF-8"), 0);
+        assert_eq!(week_start_of("de_DE@euro"), 0);
+        assert_eq!(week_start_of("ar_EG"), 5);
+        assert_eq!(week_start_of("C"), 0);
+        assert_eq!(week_start_of(""), 0);
+    }
+
+    #[test]
+    fn sessions_count_with_the_part_inside_a_range() {
+        let db = Database::open_in_memory().unwrap();
+        // 10:00 to 10:30, of which only the last 10 minutes fall into the range
+        db.record(
+            "firefox",
+            from_millis(37_800_000),
+            Duration::from_secs(30 * 60),
+        )
+        .unwrap();
+        let range = TimeRange::new(from_millis(37_200_000), from_millis(40_000_000));
+
+        assert_eq!(db.total(Some(range)).unwrap(), Duration::from_secs(10 * 60));
+        assert_eq!(
+            db.apps(Some(range)).unwrap()[0].total,
+            Duration::from_secs(10 * 60)
+        );
+        let sessions = db.sessions(Some(range)).unwrap();
+        assert_eq!(sessions[0].start, from_millis(37_200_000));
+        assert_eq!(sessions[0].duration, Duration::from_secs(10 * 60));
+    }
+}
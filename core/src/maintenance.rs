@@ -0,0 +1,89 @@
+//! Gives space freed by pruning back to the file system and keeps the query planner's
+//! statistics up to date.
+//!
+//! Deleting rows only marks their pages as free, the file keeps its size until it is
+//! rebuilt with `VACUUM`. That rewrites the whole database, so it is only worth doing every
+//! once in a while.
+
+use rusqlite::Connection;
+
+use crate::Error;
+
+/// Size of the database file before and after maintenance, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sizes {
+    pub before: u64,
+    pub after: u64,
+}
+
+/// Rebuilds the main database of the connection with `VACUUM` and runs `PRAGMA optimize`.
+///
+/// `VACUUM` can't run inside a transaction, so this fails with [`Error::InTransaction`]
+/// without touching anything if one is open on the connection. Other connections writing at
+/// the same time are waited for like any other write.
+pub fn run(conn: &Connection) -> Result<Sizes, Error> {
+    if !conn.is_autocommit() {
+        return Err(Error::InTransaction);
+    }
+    let before = file_size(conn)?;
+
+    conn.execute_batch("VACUUM")?;
+    // in WAL mode the rebuilt database ends up in the WAL first, the file only shrinks once
+    // it is written back
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    conn.execute_batch("PRAGMA optimize")?;
+
+    Ok(Sizes {
+        before,
+        after: file_size(conn)?,
+    })
+}
+
+/// Size of the main database file, not counting the WAL.
+fn file_size(conn: &Connection) -> Result<u64, Error> {
+    let size = conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+
+    #[test]
+    fn vacuum_shrinks_after_deleting() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.connection();
+
+        // nothing to do on an empty database still works
+        let sizes = run(conn).unwrap();
+        assert!(sizes.after <= sizes.before);
+
+        conn.execute(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+            INSERT INTO app_usage (app_name, title, start_time, end_time, duration)
+                SELECT 'firefox', printf('%.500c', 'x'), i, i + 1, 1 FROM n",
+            [],
+        )
+        .unwrap();
+        conn.execute("DELETE FROM app_usage", []).unwrap();
+
+        let sizes = run(conn).unwrap();
+        assert!(sizes.after < sizes.before, "{sizes:?}");
+    }
+
+    #[test]
+    fn open_transactions_are_left_alone() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.connection();
+        let tx = conn.unchecked_transaction().unwrap();
+
+        assert!(matches!(run(conn), Err(Error::InTransaction)));
+        tx.commit().unwrap();
+        assert!(run(conn).is_ok());
+    }
+}
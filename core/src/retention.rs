@@ -0,0 +1,110 @@
+//! Deletes old sessions so the database doesn't grow forever.
+//!
+//! Pruned sessions can be archived into `app_usage_archive` first, which keeps how long each
+//! app was used on each day, so totals over all time stay the same even though the single
+//! sessions are gone.
+
+use std::time::{Duration, SystemTime};
+
+use rusqlite::Connection;
+
+use crate::{DAY_MS, Error, to_millis};
+
+/// Returns the time (in unix ms) that lies `days` days in the past.
+pub fn cutoff(days: u32) -> u64 {
+    let age = Duration::from_millis(u64::from(days) * DAY_MS);
+    to_millis(
+        SystemTime::now()
+            .checked_sub(age)
+            .unwrap_or(SystemTime::UNIX_EPOCH),
+    )
+}
+
+/// Deletes every session that ended before `cutoff_ms`, returning how many were deleted.
+///
+/// With `archive` set, the usage of the deleted sessions is first added to
+/// `app_usage_archive`. Either way this happens in a single transaction, so nothing is lost
+/// if it fails halfway through.
+pub fn prune_older_than(conn: &Connection, cutoff_ms: u64, archive: bool) -> Result<usize, Error> {
+    let tx = conn.unchecked_transaction()?;
+    if archive {
+        tx.execute(
+            "INSERT INTO app_usage_archive (app_name, day, duration)
+                SELECT app_name, start_time / ?2, sum(duration)
+                FROM app_usage
+                WHERE end_time < ?1
+                GROUP BY app_name, start_time / ?2
+                ON CONFLICT (app_name, day) DO UPDATE SET duration = duration + excluded.duration",
+            [cutoff_ms, DAY_MS],
+        )?;
+    }
+    let deleted = tx.execute("DELETE FROM app_usage WHERE end_time < ?1", [cutoff_ms])?;
+    tx.commit()?;
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+
+    fn insert(db: &Database, app_name: &str, start_time: u64, duration: u64) {
+        db.connection()
+            .execute(
+                "INSERT INTO app_usage (app_name, start_time, end_time, duration) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![app_name, start_time, start_time + duration, duration],
+            )
+            .unwrap();
+    }
+
+    fn archived(db: &Database) -> Vec<(String, u64, u64)> {
+        let mut stmt = db
+            .connection()
+            .prepare("SELECT app_name, day, duration FROM app_usage_archive ORDER BY app_name, day")
+            .unwrap();
+        let x = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        x
+    }
+
+    #[test]
+    fn archiving_keeps_all_time_totals() {
+        let db = Database::open_in_memory().unwrap();
+        insert(&db, "firefox", 1000, 1000);
+        insert(&db, "firefox", 5000, 2000);
+        insert(&db, "firefox", DAY_MS + 1000, 3000);
+        insert(&db, "foot", DAY_MS * 10, 4000);
+
+        assert_eq!(
+            prune_older_than(db.connection(), DAY_MS * 2, true).unwrap(),
+            3
+        );
+        insert(&db, "firefox", 0, 500);
+        assert_eq!(
+            prune_older_than(db.connection(), DAY_MS * 2, true).unwrap(),
+            1
+        );
+
+        assert_eq!(
+            archived(&db),
+            vec![
+                ("firefox".to_string(), 0, 3500),
+                ("firefox".to_string(), 1, 3000),
+            ]
+        );
+        assert_eq!(db.total(None).unwrap(), Duration::from_millis(4000));
+    }
+
+    #[test]
+    fn pruning_without_archive_deletes() {
+        let db = Database::open_in_memory().unwrap();
+        insert(&db, "firefox", 1000, 1000);
+
+        assert_eq!(prune_older_than(db.connection(), DAY_MS, false).unwrap(), 1);
+        assert_eq!(archived(&db), vec![]);
+        assert_eq!(db.total(None).unwrap(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,380 @@
+//! The tables of the usage database, shared by the daemon writing it and everything reading
+//! it.
+//!
+//! The schema is versioned through the single row of `schema_version`, and [`migrate`] brings
+//! a database up to [`VERSION`] by applying every step in [`MIGRATIONS`] it hasn't seen yet.
+//! Databases from before versioning have no such table and count as version 0.
+
+use rusqlite::Connection;
+
+use crate::Error;
+
+/// Steps that each bring the schema one version further, the first one leads to version 1.
+///
+/// Steps are only ever appended, a database that already went through a step never sees it
+/// again, so changing one doesn't affect existing databases.
+const MIGRATIONS: &[&str] = &[
+    // the tables as they were before versioning, they may already exist
+    "CREATE TABLE IF NOT EXISTS app_usage (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        app_name TEXT NOT NULL,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER NOT NULL,
+        duration INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS window_counts (
+        time INTEGER NOT NULL,
+        count INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS daemon_runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS app_tags (
+        app_name TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (app_name, tag)
+    );",
+    // nearly every query filters on a time range, often for a single app
+    "CREATE INDEX IF NOT EXISTS app_usage_start_time ON app_usage (start_time);
+    CREATE INDEX IF NOT EXISTS app_usage_app_name_start_time ON app_usage (app_name, start_time);",
+    // usage of pruned sessions, summed up per app and day, see `retention`
+    "CREATE TABLE app_usage_archive (
+        app_name TEXT NOT NULL,
+        day INTEGER NOT NULL,
+        duration INTEGER NOT NULL,
+        PRIMARY KEY (app_name, day)
+    );",
+    // usage summed up per app and local day (counted from the epoch, like the times were UTC),
+    // so long ranges don't have to go through every session, kept in sync with app_usage by
+    // triggers no matter who writes it. Sessions count towards the day they started on
+    "CREATE TABLE app_usage_daily (
+        app_name TEXT NOT NULL,
+        day INTEGER NOT NULL,
+        duration INTEGER NOT NULL,
+        PRIMARY KEY (app_name, day)
+    );
+    INSERT INTO app_usage_daily (app_name, day, duration)
+        SELECT app_name,
+            CAST(strftime('%s', start_time / 1000, 'unixepoch', 'localtime') AS INTEGER) / 86400
+                AS day,
+            sum(duration)
+        FROM app_usage
+        GROUP BY app_name, day;
+    CREATE TRIGGER app_usage_daily_insert AFTER INSERT ON app_usage BEGIN
+        INSERT INTO app_usage_daily (app_name, day, duration)
+            VALUES (
+                new.app_name,
+                CAST(strftime('%s', new.start_time / 1000, 'unixepoch', 'localtime') AS INTEGER)
+                    / 86400,
+                new.duration
+            )
+            ON CONFLICT (app_name, day) DO UPDATE SET duration = duration + excluded.duration;
+    END;
+    CREATE TRIGGER app_usage_daily_delete AFTER DELETE ON app_usage BEGIN
+        UPDATE app_usage_daily SET duration = duration - old.duration
+            WHERE app_name = old.app_name
+                AND day = CAST(strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                    AS INTEGER) / 86400;
+        DELETE FROM app_usage_daily
+            WHERE app_name = old.app_name
+                AND day = CAST(strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                    AS INTEGER) / 86400
+                AND duration = 0;
+    END;
+    CREATE TRIGGER app_usage_daily_update AFTER UPDATE OF app_name, start_time, duration
+        ON app_usage BEGIN
+        UPDATE app_usage_daily SET duration = duration - old.duration
+            WHERE app_name = old.app_name
+                AND day = CAST(strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                    AS INTEGER) / 86400;
+        DELETE FROM app_usage_daily
+            WHERE app_name = old.app_name
+                AND day = CAST(strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                    AS INTEGER) / 86400
+                AND duration = 0;
+        INSERT INTO app_usage_daily (app_name, day, duration)
+            VALUES (
+                new.app_name,
+                CAST(strftime('%s', new.start_time / 1000, 'unixepoch', 'localtime') AS INTEGER)
+                    / 86400,
+                new.duration
+            )
+            ON CONFLICT (app_name, day) DO UPDATE SET duration = duration + excluded.duration;
+    END;",
+    // the window title when a session ended, unknown for older sessions and some backends
+    "ALTER TABLE app_usage ADD COLUMN title TEXT;",
+    // the output (monitor) the window was on when a session ended, when it is known
+    "ALTER TABLE app_usage ADD COLUMN output TEXT;",
+    // sessions that started before a range but ended within it count towards it as well
+    "CREATE INDEX IF NOT EXISTS app_usage_end_time ON app_usage (end_time);",
+    // the UTC offset (in seconds) of the local time at the start and end of a session, so it
+    // stays on the day it happened on after the clocks changed, unknown for older sessions.
+    // The daily rollup goes by it as well from now on, which changes nothing for the sessions
+    // already in there since they have none
+    "ALTER TABLE app_usage ADD COLUMN start_utc_offset INTEGER;
+    ALTER TABLE app_usage ADD COLUMN end_utc_offset INTEGER;
+    DROP TRIGGER app_usage_daily_insert;
+    DROP TRIGGER app_usage_daily_delete;
+    DROP TRIGGER app_usage_daily_update;
+    CREATE TRIGGER app_usage_daily_insert AFTER INSERT ON app_usage BEGIN
+        INSERT INTO app_usage_daily (app_name, day, duration)
+            VALUES (
+                new.app_name,
+                (new.start_time + 1000 * coalesce(
+                    new.start_utc_offset,
+                    strftime('%s', new.start_time / 1000, 'unixepoch', 'localtime')
+                        - new.start_time / 1000
+                )) / 86400000,
+                new.duration
+            )
+            ON CONFLICT (app_name, day) DO UPDATE SET duration = duration + excluded.duration;
+    END;
+    CREATE TRIGGER app_usage_daily_delete AFTER DELETE ON app_usage BEGIN
+        UPDATE app_usage_daily SET duration = duration - old.duration
+            WHERE app_name = old.app_name
+                AND day = (old.start_time + 1000 * coalesce(
+                    old.start_utc_offset,
+                    strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                        - old.start_time / 1000
+                )) / 86400000;
+        DELETE FROM app_usage_daily
+            WHERE app_name = old.app_name
+                AND day = (old.start_time + 1000 * coalesce(
+                    old.start_utc_offset,
+                    strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                        - old.start_time / 1000
+                )) / 86400000
+                AND duration = 0;
+    END;
+    CREATE TRIGGER app_usage_daily_update
+        AFTER UPDATE OF app_name, start_time, duration, start_utc_offset ON app_usage BEGIN
+        UPDATE app_usage_daily SET duration = duration - old.duration
+            WHERE app_name = old.app_name
+                AND day = (old.start_time + 1000 * coalesce(
+                    old.start_utc_offset,
+                    strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                        - old.start_time / 1000
+                )) / 86400000;
+        DELETE FROM app_usage_daily
+            WHERE app_name = old.app_name
+                AND day = (old.start_time + 1000 * coalesce(
+                    old.start_utc_offset,
+                    strftime('%s', old.start_time / 1000, 'unixepoch', 'localtime')
+                        - old.start_time / 1000
+                )) / 86400000
+                AND duration = 0;
+        INSERT INTO app_usage_daily (app_name, day, duration)
+            VALUES (
+                new.app_name,
+                (new.start_time + 1000 * coalesce(
+                    new.start_utc_offset,
+                    strftime('%s', new.start_time / 1000, 'unixepoch', 'localtime')
+                        - new.start_time / 1000
+                )) / 86400000,
+                new.duration
+            )
+            ON CONFLICT (app_name, day) DO UPDATE SET duration = duration + excluded.duration;
+    END;",
+    // how often focus moved from one window to another, per local day counted from the epoch
+    "CREATE TABLE focus_switches (
+        day INTEGER PRIMARY KEY,
+        count INTEGER NOT NULL
+    );",
+];
+
+/// The schema version this build writes and understands.
+pub const VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Returns the schema version of the database, 0 if it predates versioning or is empty.
+pub fn version(conn: &Connection) -> Result<u32, rusqlite::Error> {
+    let has_table = conn.query_row(
+        "SELECT exists(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get::<_, bool>(0),
+    )?;
+    if !has_table {
+        return Ok(0);
+    }
+
+    conn.query_row(
+        "SELECT coalesce(max(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Brings the database up to [`VERSION`], creating it from scratch if it is empty.
+///
+/// Every missing step is applied in a single transaction, so a crash halfway through leaves
+/// the database at the version it had before. Databases written by a newer version are left
+/// alone and produce [`Error::NewerSchema`].
+pub fn migrate(conn: &Connection) -> Result<(), Error> {
+    conn.execute("PRAGMA foreign_keys = ON", ())?;
+
+    let tx = conn.unchecked_transaction()?;
+    let current = version(&tx)?;
+    if current > VERSION {
+        return Err(Error::NewerSchema {
+            found: current,
+            supported: VERSION,
+        });
+    }
+    if current == VERSION {
+        return Ok(());
+    }
+
+    for step in &MIGRATIONS[current as usize..] {
+        tx.execute_batch(step)?;
+    }
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);
+        DELETE FROM schema_version;",
+    )?;
+    tx.execute(
+        "INSERT INTO schema_version (version) VALUES (?1)",
+        [VERSION],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_database_is_upgraded() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE app_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                app_name TEXT NOT NULL,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER NOT NULL,
+                duration INTEGER NOT NULL
+            );
+            INSERT INTO app_usage (app_name, start_time, end_time, duration)
+                VALUES ('firefox', 0, 1000, 1000);",
+        )
+        .unwrap();
+        assert_eq!(version(&conn).unwrap(), 0);
+
+        migrate(&conn).unwrap();
+        assert_eq!(version(&conn).unwrap(), VERSION);
+        let rows: u64 = conn
+            .query_row("SELECT count(*) FROM app_usage", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+
+        // running it again changes nothing
+        migrate(&conn).unwrap();
+        assert_eq!(version(&conn).unwrap(), VERSION);
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn.execute("UPDATE schema_version SET version = ?1", [VERSION + 1])
+            .unwrap();
+
+        assert!(matches!(migrate(&conn), Err(Error::NewerSchema { .. })));
+    }
+
+    #[test]
+    fn daily_rollup_matches_sessions() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE app_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                app_name TEXT NOT NULL,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER NOT NULL,
+                duration INTEGER NOT NULL
+            );
+            INSERT INTO app_usage (app_name, start_time, end_time, duration)
+                VALUES ('firefox', 1000, 2000, 1000);",
+        )
+        .unwrap();
+        migrate(&conn).unwrap();
+
+        // rows from before the rollup existed get backfilled, later ones are kept in sync
+        conn.execute_batch(
+            "INSERT INTO app_usage (app_name, start_time, end_time, duration) VALUES
+                ('firefox', 86400000, 86402000, 2000),
+                ('foot', 5000, 9000, 4000),
+                ('foot', 86399000, 86401000, 2000);
+            UPDATE app_usage SET duration = 3000 WHERE app_name = 'foot' AND start_time = 5000;
+            DELETE FROM app_usage WHERE app_name = 'firefox' AND start_time = 1000;",
+        )
+        .unwrap();
+
+        let query = |sql| {
+            let mut stmt = conn.prepare(sql).unwrap();
+            let x = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, u64>(1)?,
+                        row.get::<_, u64>(2)?,
+                    ))
+                })
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            x
+        };
+        let raw = query(
+            "SELECT app_name,
+                    CAST(strftime('%s', start_time / 1000, 'unixepoch', 'localtime') AS INTEGER)
+                        / 86400 AS day,
+                    sum(duration)
+                FROM app_usage
+                GROUP BY app_name, day
+                ORDER BY app_name, day",
+        );
+        assert_eq!(
+            query("SELECT app_name, day, duration FROM app_usage_daily ORDER BY app_name, day"),
+            raw
+        );
+        // which days these are depends on the time zone the test runs in
+        assert_eq!(
+            query(
+                "SELECT app_name, count(*), sum(duration) FROM app_usage_daily
+                    GROUP BY app_name ORDER BY app_name"
+            ),
+            vec![
+                ("firefox".to_string(), 1, 2000),
+                ("foot".to_string(), 1, 5000),
+            ]
+        );
+    }
+
+    #[test]
+    fn daily_rollup_goes_by_the_stored_offset() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+
+        // 23:30 UTC is already the next day an hour east, 00:30 UTC still the previous one an
+        // hour west
+        conn.execute_batch(
+            "INSERT INTO app_usage (app_name, start_time, end_time, duration, start_utc_offset)
+                VALUES ('firefox', 84600000, 84660000, 60000, 3600),
+                    ('foot', 88200000, 88260000, 60000, -3600);",
+        )
+        .unwrap();
+        let days = conn
+            .prepare("SELECT app_name, day FROM app_usage_daily ORDER BY app_name")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(days, [("firefox".to_string(), 1), ("foot".to_string(), 0)]);
+    }
+}
@@ -3,84 +3,840 @@ use std::{
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use calloop::{
+    LoopHandle, RegistrationToken,
+    timer::{TimeoutAction, Timer},
+};
 use rusqlite::params;
 use tracing::{debug, info, trace, warn};
 use wayland_client::{
-    Dispatch, event_created_child,
-    protocol::{wl_registry, wl_seat::WlSeat},
+    Dispatch, DispatchError, EventQueue, Proxy, QueueHandle,
+    backend::ObjectId,
+    event_created_child,
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_registry,
+        wl_seat::WlSeat,
+    },
 };
-use wayland_protocols::ext::idle_notify::v1::client::{
-    ext_idle_notification_v1::ExtIdleNotificationV1, ext_idle_notifier_v1::ExtIdleNotifierV1,
+use wayland_protocols::ext::{
+    foreign_toplevel_list::v1::client::{
+        ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+    },
+    idle_notify::v1::client::{
+        ext_idle_notification_v1::ExtIdleNotificationV1, ext_idle_notifier_v1::ExtIdleNotifierV1,
+    },
 };
 use wayland_protocols_wlr::foreign_toplevel::v1::client::{
     zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
     zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
 };
 
+use crate::{
+    config::Config,
+    idle::SeatIdleness,
+    ignore::IgnoreList,
+    limits::Limits,
+    tracker::{Session, Tracker},
+};
+
+/// Identifies a window in the tracker, whichever backend reported it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WindowKey {
+    /// A toplevel handle of one of the wayland protocols.
+    Wayland(ObjectId),
+    /// GNOME Shell only tells which app is focused, so its windows are told apart by app id.
+    #[cfg(feature = "gnome")]
+    Gnome(String),
+    /// A container id from sway's IPC.
+    Sway(i64),
+    /// The KWin script only reports the active window too, so these are app ids as well.
+    #[cfg(feature = "kwin")]
+    Kwin(String),
+}
+
+/// Where the daemon learns about open windows from, whichever the compositor implements.
+#[derive(Debug)]
+pub enum ToplevelBackend {
+    /// wlr-foreign-toplevel-management, which reports which window is focused.
+    Wlr(ZwlrForeignToplevelManagerV1),
+    /// ext-foreign-toplevel-list, which only reports which windows exist. Windows never count
+    /// as focused with it, so only the window count can be recorded.
+    ExtList(ExtForeignToplevelListV1),
+}
+
 #[derive(Debug)]
 pub struct AppState {
     pub idle_notifier: Option<ExtIdleNotifierV1>,
-    pub toplevel_manager: Option<ZwlrForeignToplevelManagerV1>,
-    pub seats: Vec<WlSeat>,
-    toplevels: HashMap<ZwlrForeignToplevelHandleV1, ToplevelInfo>,
+    pub toplevel_backend: Option<ToplevelBackend>,
+    /// Registry name and version of each toplevel global, one of them gets bound once all
+    /// globals are known.
+    wlr_toplevel_global: Option<(u32, u32)>,
+    ext_toplevel_list_global: Option<(u32, u32)>,
+    /// Seats along with their registry name.
+    pub seats: Vec<(u32, WlSeat)>,
+    /// Outputs along with their registry name.
+    outputs: Vec<(u32, WlOutput)>,
+    /// Names of the outputs, like `DP-1`, once the compositor told them.
+    output_names: HashMap<ObjectId, String>,
+    /// An idle notification for each seat, by the registry name of the seat.
+    idle_notifications: HashMap<u32, ExtIdleNotificationV1>,
+    seat_idleness: SeatIdleness,
+    /// Set once idle notifications were first created, after that seats get one as soon as
+    /// they appear and a missing one is replaced as soon as possible.
+    idle_notification_armed: bool,
+    /// Windows of whichever backend is in use.
+    tracker: Tracker<WindowKey>,
     db_connection: rusqlite::Connection,
+    /// Whether to record the number of open windows whenever it changes.
+    track_window_count: bool,
+    last_window_count: Option<usize>,
+    /// How long without input until the user counts as idle.
+    idle_timeout_ms: u32,
+    /// Sessions shorter than this aren't recorded.
+    min_session: Duration,
+    /// Apps whose sessions aren't recorded.
+    ignored: IgnoreList,
+    /// Daily usage limits to notify about, if any are set.
+    limits: Option<Limits>,
+    /// Sessions waiting to be written, see [`FLUSH_ROWS`].
+    pending: Vec<Session>,
+    /// The timer that writes the pending sessions, if one is running.
+    flush_timer: Option<RegistrationToken>,
+    /// The window focus last moved to, see [`AppState::count_switch`].
+    last_focused: Option<WindowKey>,
+    /// Focus switches waiting to be written along with the pending sessions.
+    pending_switches: u64,
+    /// Log sessions instead of writing them, and write nothing else either.
+    no_write: bool,
+    /// Used to start the timers of the focus grace period, set once the event loop exists.
+    loop_handle: Option<LoopHandle<'static, AppState>>,
+    /// The event loop source of the wayland connection, removed when the connection fails.
+    wayland_source: Option<RegistrationToken>,
+    /// Told about focus changes, if the D-Bus service is running.
+    #[cfg(feature = "dbus")]
+    dbus: Option<crate::dbus::Service>,
+    /// Where the metrics server looks up the focused app, if it is running.
+    #[cfg(feature = "metrics")]
+    metrics_focused: Option<crate::metrics::Focused>,
+}
+
+/// Sessions are written in a single transaction once this many are waiting, or once the
+/// first of them waited for [`FLUSH_INTERVAL`], so quickly switching windows doesn't sync the
+/// database to disk for every switch.
+const FLUSH_ROWS: usize = 32;
+/// How long frontends may have to wait to see a session after it ended.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for the compositor to announce the globals the daemon needs, some only
+/// advertise them a little after the first roundtrip.
+pub const GLOBALS_TIMEOUT: Duration = Duration::from_secs(2);
+pub const GLOBALS_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Converts to milliseconds, rounding to the nearest one instead of truncating.
+///
+/// Durations too long to fit are clamped, which would take a few hundred million years.
+pub fn round_millis(duration: Duration) -> u64 {
+    let millis = (duration.as_nanos() + 500_000) / 1_000_000;
+    u64::try_from(millis).unwrap_or_else(|_| {
+        warn!("{duration:?} doesn't fit in milliseconds, clamping it");
+        u64::MAX
+    })
 }
 
-#[derive(Debug, Clone, Default)]
-struct ToplevelInfo {
-    app_id: Option<String>,
-    focused_since: Option<Instant>,
-    state: Option<Vec<zwlr_foreign_toplevel_handle_v1::State>>,
+fn insert_usage(conn: &rusqlite::Connection, session: &Session) -> Result<usize, rusqlite::Error> {
+    // the start comes from the wall clock when the session began rather than from the end
+    // minus the duration, which would move it forward by however long the system was
+    // suspended in between. The end follows from the duration instead of the clock at the
+    // time of writing, so the row spans neither a suspend nor an idle the session ended at.
+    let start_time = round_millis(session.start.duration_since(UNIX_EPOCH).unwrap_or_default());
+    let duration = round_millis(session.duration);
+    let end_time = start_time.saturating_add(duration);
+
+    // sqlite knows the local time zone, including when it changes to and from daylight
+    // saving time
+    let inserted = conn.execute(
+        "INSERT INTO app_usage (app_name, title, output, start_time, end_time, duration, start_utc_offset, end_utc_offset)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6,
+                CAST(strftime('%s', ?4 / 1000, 'unixepoch', 'localtime') AS INTEGER) - ?4 / 1000,
+                CAST(strftime('%s', ?5 / 1000, 'unixepoch', 'localtime') AS INTEGER) - ?5 / 1000)",
+        params![
+            session.app_id,
+            session.title,
+            session.output,
+            start_time,
+            end_time,
+            duration
+        ],
+    )?;
+
+    if let Err(e) = check_day_total(conn, start_time, duration) {
+        warn!("failed to check daily total: {e}");
+    }
+
+    Ok(inserted)
 }
 
-fn insert_usage(
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Warns if the session that was just inserted pushed the total of its (local) day past 24
+/// hours, which can only happen if some usage got counted twice.
+///
+/// Only the insert that crosses the limit warns, so this logs at most once per day.
+fn check_day_total(
     conn: &rusqlite::Connection,
-    app_name: String,
-    end_time: SystemTime,
-    duration: Duration,
-) -> Result<usize, rusqlite::Error> {
-    let start_time = (end_time - duration).duration_since(UNIX_EPOCH).unwrap();
+    start_time: u64,
+    duration: u64,
+) -> Result<(), rusqlite::Error> {
+    let (day, total) = conn.query_row(
+        "SELECT date(?1 / 1000, 'unixepoch', 'localtime'), coalesce(sum(duration), 0)
+            FROM app_usage
+            WHERE start_time >= strftime('%s', date(?1 / 1000, 'unixepoch', 'localtime'), 'utc') * 1000
+              AND start_time < strftime('%s', date(?1 / 1000, 'unixepoch', 'localtime'), '+1 day', 'utc') * 1000",
+        [start_time],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)),
+    )?;
 
+    if total > DAY_MILLIS && total.saturating_sub(duration) <= DAY_MILLIS {
+        warn!(
+            "recorded {:.1}h of usage on {day}, which is more than a day has, some usage was probably counted twice",
+            total as f64 / 3_600_000.0
+        );
+    }
+
+    Ok(())
+}
+
+fn insert_window_count(
+    conn: &rusqlite::Connection,
+    time: SystemTime,
+    count: usize,
+) -> Result<usize, rusqlite::Error> {
     conn.execute(
-        "INSERT INTO app_usage (app_name, start_time, end_time, duration) VALUES (?1, ?2, ?3, ?4)",
+        "INSERT INTO window_counts (time, count) VALUES (?1, ?2)",
         params![
-            app_name,
-            start_time.as_millis() as u64,
-            end_time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
-            duration.as_millis() as u64,
+            round_millis(time.duration_since(UNIX_EPOCH).unwrap()),
+            count as u64,
         ],
     )
 }
 
+/// Adds focus switches to the count of the current local day.
+fn insert_focus_switches(
+    conn: &rusqlite::Connection,
+    count: u64,
+) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO focus_switches (day, count)
+            VALUES (CAST(strftime('%s', 'now', 'localtime') AS INTEGER) / 86400, ?1)
+            ON CONFLICT (day) DO UPDATE SET count = count + excluded.count",
+        params![count],
+    )
+}
+
 impl AppState {
-    pub fn new() -> anyhow::Result<AppState> {
-        let db_path = xdg::BaseDirectories::with_prefix("wayland-appusage")?
-            .place_data_file("app_usage.db")?;
-        let database_connection = rusqlite::Connection::open(db_path)?;
+    pub fn new(
+        db_connection: rusqlite::Connection,
+        track_window_count: bool,
+        focus_grace: Duration,
+        config: &Config,
+    ) -> AppState {
+        let limits = Limits::load(config, &db_connection);
+        Self {
+            idle_notifier: None,
+            toplevel_backend: None,
+            wlr_toplevel_global: None,
+            ext_toplevel_list_global: None,
+            seats: vec![],
+            outputs: vec![],
+            output_names: HashMap::new(),
+            idle_notifications: HashMap::new(),
+            seat_idleness: SeatIdleness::new(config.idle_on_any_seat),
+            idle_notification_armed: false,
+            tracker: Tracker::new(focus_grace)
+                .split_on_title(config.split_on_title)
+                .unidentified_apps(config.unidentified_apps)
+                .idle_grace(Duration::from_millis(config.idle_grace_ms)),
+            db_connection,
+            track_window_count,
+            last_window_count: None,
+            idle_timeout_ms: config.idle_ms,
+            min_session: Duration::from_millis(config.min_session_ms),
+            ignored: IgnoreList::from_config(config),
+            limits,
+            pending: vec![],
+            last_focused: None,
+            pending_switches: 0,
+            no_write: false,
+            flush_timer: None,
+            loop_handle: None,
+            wayland_source: None,
+            #[cfg(feature = "dbus")]
+            dbus: None,
+            #[cfg(feature = "metrics")]
+            metrics_focused: None,
+        }
+    }
 
-        database_connection.execute("PRAGMA foreign_keys = ON", ())?;
+    pub fn set_no_write(&mut self, no_write: bool) {
+        self.no_write = no_write;
+        // nothing gets recorded, so there is no usage to hold against the limits either
+        if no_write {
+            self.limits = None;
+        }
+    }
 
-        database_connection.execute(
-            "CREATE TABLE IF NOT EXISTS app_usage (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                app_name TEXT NOT NULL,
-                start_time INTEGER NOT NULL,
-                end_time INTEGER NOT NULL,
-                duration INTEGER NOT NULL
-            )",
-            (),
-        )?;
+    pub fn set_loop_handle(&mut self, loop_handle: LoopHandle<'static, AppState>) {
+        self.loop_handle = Some(loop_handle);
+    }
 
-        Ok(Self {
-            idle_notifier: None,
-            toplevel_manager: None,
-            seats: vec![],
-            toplevels: HashMap::new(),
-            db_connection: database_connection,
-        })
+    pub fn set_wayland_source(&mut self, token: RegistrationToken) {
+        self.wayland_source = Some(token);
+    }
+
+    #[cfg(feature = "dbus")]
+    pub fn set_dbus(&mut self, service: crate::dbus::Service) {
+        self.dbus = Some(service);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, focused: crate::metrics::Focused) {
+        self.metrics_focused = Some(focused);
+    }
+
+    /// Drops everything that came from the wayland connection after it failed, so a new one
+    /// can start from scratch.
+    ///
+    /// Running sessions are written first, the toplevels they belong to may be gone by the
+    /// time the compositor is back.
+    pub fn forget_wayland(&mut self) {
+        let flushed = self.flush_sessions();
+        debug!("flushed {flushed} active sessions");
+
+        if let (Some(loop_handle), Some(token)) = (&self.loop_handle, self.wayland_source.take()) {
+            loop_handle.remove(token);
+        }
+
+        for session in self
+            .tracker
+            .close_matching(Instant::now(), |key| matches!(key, WindowKey::Wayland(_)))
+        {
+            self.record(session);
+        }
+        self.sample_window_count();
+
+        self.idle_notifier = None;
+        self.toplevel_backend = None;
+        self.wlr_toplevel_global = None;
+        self.ext_toplevel_list_global = None;
+        self.seats.clear();
+        self.outputs.clear();
+        self.output_names.clear();
+        self.idle_notifications.clear();
+        self.idle_notification_armed = false;
+        // the new notifications start out not idle, so nothing would resume otherwise
+        if self.seat_idleness.is_idle() {
+            self.restart_focus_timers();
+        }
+        self.seat_idleness.clear();
+    }
+
+    pub fn db_connection(&self) -> &rusqlite::Connection {
+        &self.db_connection
+    }
+
+    /// Does roundtrips until the compositor announced a toplevel global and the idle notifier,
+    /// whichever of them are asked for, or until [`GLOBALS_TIMEOUT`] passed. Whatever is
+    /// still missing then is left for the caller to deal with.
+    ///
+    /// This sleeps in between, so it must not be called from within the event loop.
+    pub fn wait_for_globals(
+        &mut self,
+        queue: &mut EventQueue<Self>,
+        toplevels: bool,
+        idle_notifier: bool,
+    ) -> Result<(), DispatchError> {
+        let deadline = Instant::now() + GLOBALS_TIMEOUT;
+        loop {
+            queue.roundtrip(self)?;
+
+            let Some(missing) = self.missing_global(toplevels, idle_notifier) else {
+                return Ok(());
+            };
+            if Instant::now() >= deadline {
+                debug!("compositor announced no {missing} after {GLOBALS_TIMEOUT:?}");
+                return Ok(());
+            }
+            std::thread::sleep(GLOBALS_RETRY_INTERVAL);
+        }
+    }
+
+    /// Names the first of the asked for globals the compositor didn't announce yet, if any.
+    pub fn missing_global(&self, toplevels: bool, idle_notifier: bool) -> Option<&'static str> {
+        if toplevels
+            && self.wlr_toplevel_global.is_none()
+            && self.ext_toplevel_list_global.is_none()
+        {
+            Some("toplevel protocol")
+        } else if idle_notifier && self.idle_notifier.is_none() {
+            Some("idle notifier")
+        } else {
+            None
+        }
+    }
+
+    /// Binds the toplevel global that tells the most, wlr over ext, so windows aren't
+    /// reported twice by compositors implementing both.
+    pub fn bind_toplevel_backend(
+        &mut self,
+        registry: &wl_registry::WlRegistry,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        self.toplevel_backend = match (self.wlr_toplevel_global, self.ext_toplevel_list_global) {
+            (Some((name, version)), _) => Some(ToplevelBackend::Wlr(registry.bind(
+                name,
+                version,
+                qhandle,
+                (),
+            ))),
+            (None, Some((name, version))) => Some(ToplevelBackend::ExtList(registry.bind(
+                name,
+                version,
+                qhandle,
+                (),
+            ))),
+            (None, None) => None,
+        };
+    }
+
+    /// Whether the compositor announced wlr-foreign-toplevel-management, the only toplevel
+    /// protocol that says which window is focused.
+    pub fn has_wlr_toplevels(&self) -> bool {
+        self.wlr_toplevel_global.is_some()
+    }
+
+    /// Creates an idle notification on every seat that doesn't have a working one yet.
+    ///
+    /// A new notification counts its seat as not idle, when it replaces one that went away
+    /// any Resumed event that one would have sent is lost, so this resumes if needed.
+    pub fn ensure_idle_notifications(&mut self, qhandle: &QueueHandle<Self>) {
+        let Some(notifier) = &self.idle_notifier else {
+            warn!("no idle notifier available, idle time will be counted as usage");
+            return;
+        };
+        if self.seats.is_empty() {
+            warn!("no seat yet, idle time will be counted as usage until one appears");
+        }
+
+        let mut changes = vec![];
+        for (seat_name, seat) in &self.seats {
+            if self
+                .idle_notifications
+                .get(seat_name)
+                .is_some_and(|notification| notification.is_alive())
+            {
+                continue;
+            }
+
+            let notification =
+                notifier.get_idle_notification(self.idle_timeout_ms, seat, qhandle, *seat_name);
+            if self
+                .idle_notifications
+                .insert(*seat_name, notification)
+                .is_some()
+            {
+                info!("idle notification went away, registered a new one on seat {seat_name}");
+            }
+            changes.extend(self.seat_idleness.set(*seat_name, false));
+        }
+        self.idle_notification_armed = true;
+
+        for idle in changes {
+            self.idle_changed(idle);
+        }
+    }
+
+    /// How many seats are watched for idleness.
+    pub fn idle_seat_count(&self) -> usize {
+        self.seat_idleness.len()
+    }
+
+    fn seat_idle(&mut self, seat_name: u32, idle: bool) {
+        if let Some(idle) = self.seat_idleness.set(seat_name, idle) {
+            self.idle_changed(idle);
+        }
+    }
+
+    fn idle_changed(&mut self, idle: bool) {
+        if idle {
+            self.idled();
+        } else {
+            self.resumed();
+        }
+    }
+
+    /// Starts counting every activated toplevel that isn't being counted already, recording
+    /// the sessions that were kept going through an idle stretch longer than the grace period.
+    fn restart_focus_timers(&mut self) {
+        for session in self.tracker.resumed(Instant::now()) {
+            self.record(session);
+        }
+    }
+
+    /// Starts tracking the window if it isn't already, and sets its app id if it is known.
+    pub fn add_window(&mut self, key: WindowKey, app_id: Option<String>) {
+        match app_id {
+            Some(app_id) => self.tracker.set_app_id(key, app_id),
+            None => self.tracker.add(key),
+        }
+    }
+
+    /// Updates the window's title, recording the session that ended if title changes split
+    /// sessions.
+    pub fn set_title(&mut self, key: WindowKey, title: String) {
+        if let Some(session) = self.tracker.set_title(key, title, Instant::now()) {
+            self.record(session);
+        }
+    }
+
+    /// Notes that the toplevel entered or left the output, outputs whose name isn't known
+    /// are ignored.
+    fn toplevel_output(&mut self, key: WindowKey, output: &WlOutput, entered: bool) {
+        let Some(name) = self.output_names.get(&output.id()) else {
+            debug!("toplevel moved to an output without a name");
+            return;
+        };
+        if entered {
+            self.tracker.enter_output(key, name.clone());
+        } else {
+            self.tracker.leave_output(key, name);
+        }
+    }
+
+    /// Updates whether the window is focused, recording the session that ended if it lost
+    /// focus. Every backend reports focus changes through this.
+    pub fn set_focus(&mut self, key: WindowKey, is_active: bool) {
+        let was_active = self.tracker.is_active(&key);
+        if let Some(session) = self
+            .tracker
+            .set_active(key.clone(), is_active, Instant::now())
+        {
+            self.record(session);
+        }
+
+        #[cfg(feature = "dbus")]
+        if let (Some(dbus), Some(app_id)) = (&self.dbus, self.tracker.app_id(&key)) {
+            if is_active && !was_active {
+                dbus.focus_changed(app_id);
+            } else if !is_active {
+                dbus.focus_lost(app_id);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(focused) = &self.metrics_focused {
+            let app_id = self.tracker.app_id(&key).map(str::to_string);
+            let mut focused = focused.lock().unwrap();
+            if is_active {
+                *focused = app_id;
+            } else if *focused == app_id {
+                *focused = None;
+            }
+        }
+
+        if is_active && !was_active {
+            self.count_switch(&key);
+            if !self.tracker.grace().is_zero() {
+                self.start_grace_timer(key);
+            }
+        }
+    }
+
+    /// Counts focus moving to a window other than the one it was on last as a switch.
+    ///
+    /// Focus coming back to the same window, like after it went to no window at all in
+    /// between, isn't one, and neither is the first window focused after the daemon started.
+    /// Windows of ignored apps count like any other, since the user did switch to them.
+    fn count_switch(&mut self, key: &WindowKey) {
+        if self.last_focused.as_ref().is_some_and(|x| x != key) {
+            self.pending_switches += 1;
+            self.schedule_flush();
+        }
+        self.last_focused = Some(key.clone());
+    }
+
+    /// Stops tracking the window, recording its session if it was still being counted.
+    pub fn close_window(&mut self, key: &WindowKey) {
+        // a window can be closed while focused without ever being deactivated
+        #[cfg(feature = "dbus")]
+        if let (Some(dbus), Some(app_id), true) = (
+            &self.dbus,
+            self.tracker.app_id(key),
+            self.tracker.is_active(key),
+        ) {
+            dbus.focus_lost(app_id);
+        }
+        #[cfg(feature = "metrics")]
+        if let (Some(focused), true) = (&self.metrics_focused, self.tracker.is_active(key)) {
+            let mut focused = focused.lock().unwrap();
+            if focused.as_deref() == self.tracker.app_id(key) {
+                *focused = None;
+            }
+        }
+
+        if let Some(session) = self.tracker.close(key, Instant::now()) {
+            self.record(session);
+        }
+    }
+
+    /// Ends every running session, since the user went idle.
+    pub fn idled(&mut self) {
+        for session in self.tracker.idled(Instant::now()) {
+            self.record(session);
+        }
+        #[cfg(feature = "dbus")]
+        if let Some(dbus) = &self.dbus {
+            dbus.clear_focus();
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(focused) = &self.metrics_focused {
+            *focused.lock().unwrap() = None;
+        }
+        // nothing new comes in for a while now
+        self.write_pending();
+    }
+
+    /// Starts counting again after the user came back from being idle.
+    pub fn resumed(&mut self) {
+        debug!("resumed");
+        self.restart_focus_timers();
+        #[cfg(feature = "dbus")]
+        if let (Some(dbus), Some(app_id)) = (&self.dbus, self.tracker.active_app_id()) {
+            dbus.focus_changed(app_id);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(focused) = &self.metrics_focused {
+            *focused.lock().unwrap() = self.tracker.active_app_id().map(str::to_string);
+        }
+    }
+
+    /// Records every session that is running right now without ending it, returning how many
+    /// were written.
+    ///
+    /// Sessions that already ended and were still waiting to be written are written as well.
+    pub fn flush_sessions(&mut self) -> usize {
+        let sessions = self.tracker.flush(Instant::now());
+        let count = sessions.len();
+        // these are only slices of sessions that keep going, so they are written even if
+        // they are short
+        for session in sessions {
+            self.insert(session);
+        }
+        self.write_pending();
+        count
+    }
+
+    /// Starts counting the toplevel once it stayed focused for the grace period.
+    fn start_grace_timer(&mut self, key: WindowKey) {
+        let (Some(loop_handle), Some(activated_at)) =
+            (&self.loop_handle, self.tracker.activated_at(&key))
+        else {
+            return;
+        };
+
+        // nothing needs cancelling if focus leaves early, the tracker ignores confirmations
+        // for activations that already ended
+        let timer_key = key.clone();
+        if let Err(e) = loop_handle.insert_source(
+            Timer::from_duration(self.tracker.grace()),
+            move |_, _, state| {
+                state
+                    .tracker
+                    .confirm_focus(&timer_key, activated_at, Instant::now());
+                TimeoutAction::Drop
+            },
+        ) {
+            warn!("failed to start focus grace timer, counting right away: {e}");
+            self.tracker
+                .confirm_focus(&key, activated_at, Instant::now());
+        }
+    }
+
+    /// Writes a finished session to the database, unless it is shorter than the minimum
+    /// session length.
+    fn record(&mut self, session: Session) {
+        if session.duration < self.min_session {
+            trace!(
+                "dropping session of {} shorter than {:?}: {:?}",
+                session.app_id, self.min_session, session.duration
+            );
+            return;
+        }
+        if self.ignored.is_ignored(&session.app_id) {
+            debug!(
+                "dropping session of ignored app {}: {:?}",
+                session.app_id, session.duration
+            );
+            return;
+        }
+        self.insert(session);
+    }
+
+    /// Queues a session to be written with the next batch.
+    fn insert(&mut self, session: Session) {
+        self.pending.push(session);
+        if self.pending.len() >= FLUSH_ROWS || !self.schedule_flush() {
+            self.write_pending();
+        }
+    }
+
+    /// Writes the pending sessions once [`FLUSH_INTERVAL`] passed, unless that is already
+    /// going to happen, returning false if the timer failed to start.
+    ///
+    /// Without an event loop they wait for the next full batch or for the daemon to exit.
+    fn schedule_flush(&mut self) -> bool {
+        let Some(loop_handle) = &self.loop_handle else {
+            return true;
+        };
+        if self.flush_timer.is_some() {
+            return true;
+        }
+
+        match loop_handle.insert_source(Timer::from_duration(FLUSH_INTERVAL), |_, _, state| {
+            // the timer is dropped by returning, so there is nothing left to remove
+            state.flush_timer = None;
+            state.write_pending();
+            TimeoutAction::Drop
+        }) {
+            Ok(token) => {
+                self.flush_timer = Some(token);
+                true
+            }
+            Err(e) => {
+                warn!("failed to start flush timer: {e}");
+                false
+            }
+        }
+    }
+
+    /// Writes every pending session, and the focus switches counted since the last write, in
+    /// a single transaction.
+    ///
+    /// A session that fails to insert is dropped without affecting the others. If the
+    /// transaction as a whole fails, everything is kept to be written again later.
+    pub fn write_pending(&mut self) {
+        if let (Some(loop_handle), Some(token)) = (&self.loop_handle, self.flush_timer.take()) {
+            loop_handle.remove(token);
+        }
+        if self.pending.is_empty() && self.pending_switches == 0 {
+            return;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let switches = std::mem::take(&mut self.pending_switches);
+
+        let tx = match self.db_connection.unchecked_transaction() {
+            Ok(tx) => tx,
+            Err(e) => {
+                warn!("failed to start writing {} sessions: {e}", pending.len());
+                self.keep_pending(pending, switches);
+                return;
+            }
+        };
+        let mut written = Vec::with_capacity(pending.len());
+        for session in pending {
+            if self.no_write {
+                info!(
+                    "would record {} for {:?}, titled {:?} on {:?}",
+                    session.app_id, session.duration, session.title, session.output
+                );
+                written.push(session);
+                continue;
+            }
+            match insert_usage(&tx, &session) {
+                Ok(_) => written.push(session),
+                Err(e) => warn!("db insert failed: {e}"),
+            }
+        }
+        if switches > 0 && !self.no_write {
+            if let Err(e) = insert_focus_switches(&tx, switches) {
+                warn!("failed to count {switches} focus switches: {e}");
+            }
+        }
+        if let Err(e) = tx.commit() {
+            warn!("failed to write {} sessions: {e}", written.len());
+            self.keep_pending(written, switches);
+            return;
+        }
+        trace!("wrote {} sessions", written.len());
+
+        if let Some(limits) = &mut self.limits {
+            for session in written {
+                limits.record(&self.db_connection, &session.app_id, session.duration);
+            }
+        }
+    }
+
+    /// Puts sessions and focus switches that couldn't be written back in front of the pending
+    /// ones, trying again once the flush timer fires.
+    fn keep_pending(&mut self, mut sessions: Vec<Session>, switches: u64) {
+        sessions.append(&mut self.pending);
+        self.pending = sessions;
+        self.pending_switches += switches;
+        self.schedule_flush();
+    }
+
+    /// Records the number of open windows if it changed since the last sample.
+    ///
+    /// This is called once a toplevel finished sending a batch of changes (`done`) and when
+    /// one is closed, so a row is only written when a window actually appears or disappears.
+    pub fn sample_window_count(&mut self) {
+        let count = self.tracker.len();
+        if !self.track_window_count || self.last_window_count == Some(count) {
+            return;
+        }
+
+        trace!("window count changed to {count}");
+        if self.no_write {
+            self.last_window_count = Some(count);
+            return;
+        }
+        if let Err(e) = insert_window_count(&self.db_connection, SystemTime::now(), count) {
+            warn!("db insert failed: {e}");
+        }
+        self.last_window_count = Some(count);
     }
 }
 
+impl Drop for AppState {
+    /// Writes what is still pending when the daemon exits, also when it panicked.
+    fn drop(&mut self) {
+        self.write_pending();
+    }
+}
+
+/// Decodes the state array of a wlr toplevel.
+///
+/// Values this doesn't know, from a newer protocol version, are skipped, as are bytes left
+/// over after the last whole value, so a buggy compositor can't take the daemon down.
+fn parse_states(raw: &[u8]) -> Vec<zwlr_foreign_toplevel_handle_v1::State> {
+    let chunks = raw.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        warn!(
+            "toplevel state has {} bytes, which isn't a whole number of states, ignoring the rest",
+            raw.len()
+        );
+    }
+
+    chunks
+        .filter_map(|chunk| {
+            let raw_value = u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            zwlr_foreign_toplevel_handle_v1::State::try_from(raw_value)
+                .inspect_err(|_| warn!("ignoring unknown toplevel state {raw_value}"))
+                .ok()
+        })
+        .collect()
+}
+
 impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
     fn event(
         state: &mut Self,
@@ -91,6 +847,27 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
         qhandle: &wayland_client::QueueHandle<Self>,
     ) {
         trace!("event: {:?}", event);
+        if let wl_registry::Event::GlobalRemove { name } = event {
+            state.seats.retain(|(seat_name, _)| *seat_name != name);
+            if let Some(index) = state
+                .outputs
+                .iter()
+                .position(|(output_name, _)| *output_name == name)
+            {
+                let (_, output) = state.outputs.remove(index);
+                state.output_names.remove(&output.id());
+            }
+
+            // the notification can't fire anymore once its seat is gone
+            if let Some(notification) = state.idle_notifications.remove(&name) {
+                notification.destroy();
+                if let Some(idle) = state.seat_idleness.remove(name) {
+                    state.idle_changed(idle);
+                }
+            }
+            return;
+        }
+
         if let wl_registry::Event::Global {
             name,
             interface,
@@ -104,16 +881,22 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
                 }
                 "wl_seat" => {
                     let seat = proxy.bind::<WlSeat, _, _>(name, version, qhandle, ());
-                    state.seats.push(seat);
+                    state.seats.push((name, seat));
+                    // seats from the initial roundtrip are armed all at once afterwards
+                    if state.idle_notification_armed {
+                        state.ensure_idle_notifications(qhandle);
+                    }
+                }
+                // names were only added in version 4
+                "wl_output" if version >= 4 => {
+                    let output = proxy.bind::<WlOutput, _, _>(name, version.min(4), qhandle, ());
+                    state.outputs.push((name, output));
                 }
                 "zwlr_foreign_toplevel_manager_v1" => {
-                    state.toplevel_manager =
-                        Some(proxy.bind::<ZwlrForeignToplevelManagerV1, _, _>(
-                            name,
-                            version,
-                            qhandle,
-                            (),
-                        ));
+                    state.wlr_toplevel_global = Some((name, version));
+                }
+                "ext_foreign_toplevel_list_v1" => {
+                    state.ext_toplevel_list_global = Some((name, version));
                 }
                 _ => (),
             }
@@ -131,132 +914,74 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for AppState {
         _qhandle: &wayland_client::QueueHandle<Self>,
     ) {
         trace!("toplevel handle event: {:?}", event);
-        let item = app_state.toplevels.entry(proxy.clone()).or_default();
+        let key = WindowKey::Wayland(proxy.id());
+        app_state.add_window(key.clone(), None);
 
         use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::Event;
         match event {
-            Event::AppId { app_id } => item.app_id = Some(app_id),
+            Event::AppId { app_id } => app_state.add_window(key, Some(app_id)),
+            Event::Title { title } => app_state.set_title(key, title),
+            Event::OutputEnter { output } => app_state.toplevel_output(key, &output, true),
+            Event::OutputLeave { output } => app_state.toplevel_output(key, &output, false),
             Event::State { state } => {
-                let new_state = state
-                    .chunks_exact(4)
-                    .map(|chunk| {
-                        let raw_value = u32::from_ne_bytes(chunk.try_into().unwrap());
-                        zwlr_foreign_toplevel_handle_v1::State::try_from(raw_value).unwrap()
-                    })
-                    .collect::<Vec<_>>();
-
-                let was_active = item.state.as_ref().is_some_and(|state| {
-                    state.contains(&zwlr_foreign_toplevel_handle_v1::State::Activated)
-                });
-
+                let new_state = parse_states(&state);
                 let is_active =
                     new_state.contains(&zwlr_foreign_toplevel_handle_v1::State::Activated);
+                app_state.set_focus(key, is_active);
+            }
+            Event::Closed => {
+                app_state.close_window(&key);
+                app_state.sample_window_count();
+            }
+            Event::Done => app_state.sample_window_count(),
+            _ => (),
+        }
+    }
+}
 
-                // became inactive
-                if was_active && !is_active {
-                    debug!("became inactive:{:?}", item.app_id);
-                    // log time since became active
-                    // remove activate time from toplevel info
-                    if let Some(focused_since) = item.focused_since {
-                        if let Some(ref app_id) = item.app_id {
-                            let duration = Instant::now().duration_since(focused_since);
-                            let now = SystemTime::now();
-                            if let Err(e) = insert_usage(
-                                &app_state.db_connection,
-                                app_id.to_string(),
-                                now,
-                                duration,
-                            ) {
-                                warn!("db insert failed: {e}");
-                            }
-                        }
-                    }
-                    item.focused_since = None;
-                }
-
-                // became active
-                if is_active && !was_active {
-                    debug!("became active: {:?}", item.app_id);
-                    item.focused_since = Some(Instant::now());
-                }
+// the ext list never reports focus, so its toplevels are only there to be counted
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for AppState {
+    fn event(
+        app_state: &mut Self,
+        proxy: &ExtForeignToplevelHandleV1,
+        event: <ExtForeignToplevelHandleV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &wayland_client::Connection,
+        _qhandle: &wayland_client::QueueHandle<Self>,
+    ) {
+        trace!("ext toplevel handle event: {:?}", event);
+        let key = WindowKey::Wayland(proxy.id());
+        app_state.add_window(key.clone(), None);
 
-                item.state = Some(new_state);
-            }
+        use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::Event;
+        match event {
+            Event::AppId { app_id } => app_state.add_window(key, Some(app_id)),
             Event::Closed => {
-                let is_active = item.state.as_ref().is_some_and(|state| {
-                    state.contains(&zwlr_foreign_toplevel_handle_v1::State::Activated)
-                });
-
-                if is_active {
-                    debug!("active client destroyed: {:?}", item);
-                    if let Some(focused_since) = item.focused_since {
-                        if let Some(ref app_id) = item.app_id {
-                            let duration = Instant::now().duration_since(focused_since);
-                            let now = SystemTime::now();
-                            if let Err(e) = insert_usage(
-                                &app_state.db_connection,
-                                app_id.to_string(),
-                                now,
-                                duration,
-                            ) {
-                                warn!("db insert failed: {e}");
-                            }
-                        }
-                    }
-                }
-                app_state.toplevels.remove(&proxy.clone());
+                app_state.close_window(&key);
+                proxy.destroy();
+                app_state.sample_window_count();
             }
+            Event::Done => app_state.sample_window_count(),
             _ => (),
         }
     }
 }
 
-impl Dispatch<ExtIdleNotificationV1, ()> for AppState {
+// the user data is the registry name of the seat
+impl Dispatch<ExtIdleNotificationV1, u32> for AppState {
     fn event(
         state: &mut Self,
         _proxy: &ExtIdleNotificationV1,
         event: <ExtIdleNotificationV1 as wayland_client::Proxy>::Event,
-        _data: &(),
+        seat_name: &u32,
         _conn: &wayland_client::Connection,
         _qhandle: &wayland_client::QueueHandle<Self>,
     ) {
         trace!("idle notification event: {:?}", event);
         use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::Event;
         match event {
-            Event::Idled => {
-                // log active time, reset active_since number
-                for toplevel in state
-                    .toplevels
-                    .values_mut()
-                    .filter(|toplevel| toplevel.focused_since.is_some())
-                {
-                    debug!(
-                        "idleing, logging active duration for toplevel: {:?}",
-                        toplevel.app_id
-                    );
-                    if let Some(ref app_id) = toplevel.app_id {
-                        let duration =
-                            Instant::now().duration_since(toplevel.focused_since.unwrap());
-                        let now = SystemTime::now();
-                        if let Err(e) =
-                            insert_usage(&state.db_connection, app_id.to_string(), now, duration)
-                        {
-                            warn!("db insert failed: {e}");
-                        }
-                    }
-                    toplevel.focused_since = None;
-                }
-            }
-            Event::Resumed => {
-                debug!("resumed");
-                for toplevel in state.toplevels.values_mut().filter(|toplevel| {
-                    toplevel.state.as_ref().is_some_and(|state| {
-                        state.contains(&zwlr_foreign_toplevel_handle_v1::State::Activated)
-                    })
-                }) {
-                    toplevel.focused_since = Some(Instant::now());
-                }
-            }
+            Event::Idled => state.seat_idle(*seat_name, true),
+            Event::Resumed => state.seat_idle(*seat_name, false),
             _ => unreachable!(),
         }
     }
@@ -285,6 +1010,21 @@ impl Dispatch<WlSeat, ()> for AppState {
     ) {
     }
 }
+impl Dispatch<WlOutput, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        proxy: &WlOutput,
+        event: <WlOutput as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &wayland_client::Connection,
+        _qhandle: &wayland_client::QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            trace!("output {} is named {name}", proxy.id());
+            state.output_names.insert(proxy.id(), name);
+        }
+    }
+}
 impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppState {
     fn event(
         _state: &mut Self,
@@ -300,3 +1040,381 @@ impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppState {
         _ => (ZwlrForeignToplevelHandleV1, ())
     ]);
 }
+impl Dispatch<ExtForeignToplevelListV1, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtForeignToplevelListV1,
+        _event: <ExtForeignToplevelListV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &wayland_client::Connection,
+        _qhandle: &wayland_client::QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(AppState, ExtForeignToplevelListV1, [
+        _ => (ExtForeignToplevelHandleV1, ())
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use calloop::EventLoop;
+
+    use super::*;
+
+    #[test]
+    fn millis_are_rounded() {
+        assert_eq!(round_millis(Duration::from_micros(1_499)), 1);
+        assert_eq!(round_millis(Duration::from_micros(1_500)), 2);
+        assert_eq!(round_millis(Duration::from_micros(999)), 1);
+        assert_eq!(round_millis(Duration::from_micros(499)), 0);
+    }
+
+    #[test]
+    fn huge_durations_are_clamped() {
+        assert_eq!(round_millis(Duration::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn malformed_states_are_skipped() {
+        use zwlr_foreign_toplevel_handle_v1::State;
+
+        let raw = [2_u32, 1000, 0]
+            .iter()
+            .flat_map(|x| x.to_ne_bytes())
+            .chain([1, 0])
+            .collect::<Vec<_>>();
+        assert_eq!(parse_states(&raw), vec![State::Activated, State::Maximized]);
+        assert_eq!(parse_states(&[2, 0, 0]), vec![]);
+    }
+
+    #[test]
+    fn inserted_times_come_from_the_wall_clock() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE app_usage (
+                id INTEGER PRIMARY KEY,
+                app_name TEXT NOT NULL,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER NOT NULL,
+                duration INTEGER NOT NULL,
+                title TEXT,
+                output TEXT,
+                start_utc_offset INTEGER,
+                end_utc_offset INTEGER
+            )",
+            [],
+        )
+        .unwrap();
+
+        let session = Session {
+            app_id: "firefox".to_string(),
+            title: Some("Inbox".to_string()),
+            output: Some("DP-1".to_string()),
+            start: UNIX_EPOCH + Duration::from_micros(5_000_400),
+            duration: Duration::from_micros(2_700),
+        };
+        insert_usage(&conn, &session).unwrap();
+
+        let row = conn
+            .query_row(
+                "SELECT start_time, end_time, duration, title FROM app_usage",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, u64>(1)?,
+                        row.get::<_, u64>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(row, (5_000, 5_003, 3, "Inbox".to_string()));
+    }
+
+    #[test]
+    fn sessions_ended_by_a_long_idle_only_span_their_duration() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut tracker = Tracker::default().idle_grace(Duration::from_secs(120));
+        let t0 = Instant::now();
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+
+        // the session ends when the user went idle, not when they came back
+        tracker.idled(t0 + Duration::from_secs(60));
+        let sessions = tracker.resumed(t0 + Duration::from_secs(600));
+        assert_eq!(sessions.len(), 1);
+        insert_usage(&conn, &sessions[0]).unwrap();
+
+        let (start_time, end_time, duration) = conn
+            .query_row(
+                "SELECT start_time, end_time, duration FROM app_usage",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, u64>(1)?,
+                        row.get::<_, u64>(2)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(duration, 60_000);
+        assert_eq!(end_time - start_time, duration);
+    }
+
+    #[test]
+    fn sessions_are_written_in_batches() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut state = AppState::new(conn, false, Duration::ZERO, &Config::default());
+        let count = |state: &AppState| {
+            state
+                .db_connection()
+                .query_row("SELECT count(*) FROM app_usage", [], |row| {
+                    row.get::<_, usize>(0)
+                })
+                .unwrap()
+        };
+        let session = Session {
+            app_id: "firefox".to_string(),
+            title: None,
+            output: None,
+            start: SystemTime::now(),
+            duration: Duration::from_secs(5),
+        };
+
+        for _ in 0..FLUSH_ROWS - 1 {
+            state.record(session.clone());
+        }
+        assert_eq!(count(&state), 0);
+        state.record(session.clone());
+        assert_eq!(count(&state), FLUSH_ROWS);
+
+        state.record(session);
+        state.write_pending();
+        assert_eq!(count(&state), FLUSH_ROWS + 1);
+    }
+
+    #[test]
+    fn sessions_are_kept_when_writing_fails() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut state = AppState::new(conn, false, Duration::ZERO, &Config::default());
+        state.record(Session {
+            app_id: "firefox".to_string(),
+            title: None,
+            output: None,
+            start: SystemTime::now(),
+            duration: Duration::from_secs(5),
+        });
+
+        // a transaction that is already open keeps the batch from starting its own
+        state.db_connection().execute_batch("BEGIN").unwrap();
+        state.write_pending();
+        state.db_connection().execute_batch("ROLLBACK").unwrap();
+        assert_eq!(state.pending.len(), 1);
+
+        state.write_pending();
+        let rows = state
+            .db_connection()
+            .query_row("SELECT count(*) FROM app_usage", [], |row| {
+                row.get::<_, usize>(0)
+            })
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert!(state.pending.is_empty());
+    }
+
+    /// App ids of every written session, in the order they were written.
+    fn written_apps(state: &AppState) -> Vec<String> {
+        state
+            .db_connection()
+            .prepare("SELECT app_name FROM app_usage ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn grace_timers_fire_on_the_event_loop() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut config = Config::default();
+        config.min_session_ms = 0;
+        let mut event_loop: EventLoop<'static, AppState> = EventLoop::try_new().unwrap();
+        let mut state = AppState::new(conn, false, Duration::from_millis(20), &config);
+        state.set_loop_handle(event_loop.handle());
+        let firefox = WindowKey::Sway(1);
+        let foot = WindowKey::Sway(2);
+        state.add_window(firefox.clone(), Some("firefox".to_string()));
+        state.add_window(foot.clone(), Some("foot".to_string()));
+
+        // foot loses focus before its grace period is over, so only firefox is counted
+        state.set_focus(foot.clone(), true);
+        state.set_focus(foot.clone(), false);
+        state.set_focus(firefox.clone(), true);
+        for _ in 0..5 {
+            event_loop
+                .dispatch(Duration::from_millis(20), &mut state)
+                .unwrap();
+        }
+        state.close_window(&foot);
+        state.close_window(&firefox);
+        state.write_pending();
+
+        assert_eq!(written_apps(&state), ["firefox"]);
+    }
+
+    #[test]
+    fn idling_ends_sessions_and_resuming_starts_new_ones() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut config = Config::default();
+        config.min_session_ms = 0;
+        let event_loop: EventLoop<'static, AppState> = EventLoop::try_new().unwrap();
+        let mut state = AppState::new(conn, false, Duration::ZERO, &config);
+        state.set_loop_handle(event_loop.handle());
+        let firefox = WindowKey::Sway(1);
+        state.add_window(firefox.clone(), Some("firefox".to_string()));
+        state.set_focus(firefox.clone(), true);
+
+        // going idle writes right away instead of waiting for the flush timer
+        state.idled();
+        assert_eq!(written_apps(&state), ["firefox"]);
+
+        // firefox is still focused, so it counts again once the user is back
+        state.resumed();
+        state.close_window(&firefox);
+        state.write_pending();
+        assert_eq!(written_apps(&state), ["firefox", "firefox"]);
+    }
+
+    #[test]
+    fn short_sessions_are_dropped_but_flushed_slices_are_not() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut config = Config::default();
+        config.min_session_ms = 60_000;
+        let mut state = AppState::new(conn, false, Duration::ZERO, &config);
+        let firefox = WindowKey::Sway(1);
+        let foot = WindowKey::Sway(2);
+        let mpv = WindowKey::Sway(3);
+        state.add_window(firefox.clone(), Some("firefox".to_string()));
+        state.add_window(foot.clone(), Some("foot".to_string()));
+        state.add_window(mpv.clone(), Some("mpv".to_string()));
+
+        // closed while focused
+        state.set_focus(firefox.clone(), true);
+        state.close_window(&firefox);
+
+        // a slice of a session that keeps going is written however short it is, what is
+        // left of it once focus moves on isn't
+        state.set_focus(mpv.clone(), true);
+        assert_eq!(state.flush_sessions(), 1);
+        state.set_focus(mpv, false);
+
+        // ended by going idle
+        state.set_focus(foot, true);
+        state.idled();
+        state.write_pending();
+
+        assert_eq!(written_apps(&state), ["mpv"]);
+    }
+
+    #[test]
+    fn ignored_apps_are_not_written() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut config = Config::default();
+        config.ignore = vec!["swaylock".to_string()];
+        let mut state = AppState::new(conn, false, Duration::ZERO, &config);
+        for app_id in ["swaylock", "firefox"] {
+            state.record(Session {
+                app_id: app_id.to_string(),
+                title: None,
+                output: None,
+                start: SystemTime::now(),
+                duration: Duration::from_secs(5),
+            });
+        }
+        state.write_pending();
+
+        let apps = state
+            .db_connection()
+            .prepare("SELECT app_name FROM app_usage")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(apps, ["firefox"]);
+    }
+
+    #[test]
+    fn nothing_is_written_with_no_write() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut state = AppState::new(conn, true, Duration::ZERO, &Config::default());
+        state.set_no_write(true);
+        for key in [WindowKey::Sway(1), WindowKey::Sway(2)] {
+            state.add_window(key.clone(), Some("foot".to_string()));
+            state.set_focus(key, true);
+        }
+        state.record(Session {
+            app_id: "firefox".to_string(),
+            title: None,
+            output: None,
+            start: SystemTime::now(),
+            duration: Duration::from_secs(5),
+        });
+        state.sample_window_count();
+        state.write_pending();
+
+        for table in ["app_usage", "window_counts", "focus_switches"] {
+            let rows = state
+                .db_connection()
+                .query_row(&format!("SELECT count(*) FROM {table}"), [], |row| {
+                    row.get::<_, usize>(0)
+                })
+                .unwrap();
+            assert_eq!(rows, 0, "{table}");
+        }
+    }
+
+    #[test]
+    fn focus_switches_are_counted_per_day() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        let mut state = AppState::new(conn, false, Duration::ZERO, &Config::default());
+        let firefox = WindowKey::Sway(1);
+        let foot = WindowKey::Sway(2);
+        state.add_window(firefox.clone(), Some("firefox".to_string()));
+        state.add_window(foot.clone(), Some("foot".to_string()));
+
+        // the first focus isn't a switch, and neither is coming back to the same window
+        state.set_focus(firefox.clone(), true);
+        state.set_focus(firefox.clone(), false);
+        state.set_focus(firefox.clone(), true);
+        state.set_focus(firefox.clone(), false);
+        state.set_focus(foot.clone(), true);
+        state.set_focus(foot, false);
+        state.set_focus(firefox, true);
+        state.write_pending();
+        state.write_pending();
+
+        let counts = state
+            .db_connection()
+            .prepare("SELECT count FROM focus_switches")
+            .unwrap()
+            .query_map([], |row| row.get::<_, u64>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(counts, [2]);
+    }
+}
@@ -0,0 +1,25 @@
+//! Loads the shared config file, with the environment taking precedence over it.
+
+use std::env;
+
+pub use appusage_core::config::Config;
+use tracing::warn;
+
+/// Loads `config.toml`, then applies `WAYLAND_APPUSAGE_IDLE_MS` on top of it if it is set.
+///
+/// A malformed config file is an error, an invalid environment variable only a warning.
+pub fn load() -> anyhow::Result<Config> {
+    let mut config = Config::load()?;
+
+    if let Ok(value) = env::var("WAYLAND_APPUSAGE_IDLE_MS") {
+        match value.trim().parse::<u32>() {
+            Ok(ms) if ms > 0 => config.idle_ms = ms,
+            _ => warn!(
+                "WAYLAND_APPUSAGE_IDLE_MS must be a positive number of milliseconds, got {value:?}, using {}",
+                config.idle_ms
+            ),
+        }
+    }
+
+    Ok(config)
+}
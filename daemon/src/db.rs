@@ -0,0 +1,282 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, bail};
+use rusqlite::{Connection, ErrorCode, OpenFlags, params_from_iter, types::Value};
+use tracing::{error, info, warn};
+
+/// Files sqlite may keep next to the database, these have to move together with it.
+const SIDECAR_SUFFIXES: [&str; 3] = ["-journal", "-wal", "-shm"];
+
+/// Opens (and if needed creates) the usage database at `path`.
+///
+/// If the file is corrupt this fails with an explanation, unless `recover` is set, in which
+/// case the corrupt file is moved aside, a fresh database is created in its place and every
+/// row that can still be read from the old file is copied over.
+pub fn open(path: &Path, recover: bool) -> anyhow::Result<Connection> {
+    if let Some(problem) = check_integrity(path)? {
+        if !recover {
+            bail!(
+                "database at {} is corrupt ({problem}), rerun with --recover to move it aside \
+                 and salvage what is still readable",
+                path.display()
+            );
+        }
+
+        error!("database at {} is corrupt: {problem}", path.display());
+        let moved_to = move_aside(path)?;
+        warn!("moved corrupt database to {}", moved_to.display());
+
+        let conn = create(path)?;
+        match salvage(&moved_to, &conn) {
+            Ok((copied, lost)) => info!("recovered {copied} rows, {lost} were unreadable"),
+            Err(e) => warn!("could not salvage any rows: {e}"),
+        }
+        return Ok(conn);
+    }
+
+    create(path)
+}
+
+/// Opens an empty database that only lives in memory, standing in for the real one when
+/// nothing may be written, so that one is neither created nor migrated.
+pub fn open_scratch() -> anyhow::Result<Connection> {
+    let conn = Connection::open_in_memory()?;
+    appusage_core::schema::migrate(&conn)?;
+    Ok(conn)
+}
+
+fn create(path: &Path) -> anyhow::Result<Connection> {
+    let conn = Connection::open(path)?;
+
+    appusage_core::configure(&conn)?;
+    appusage_core::schema::migrate(&conn)?;
+
+    Ok(conn)
+}
+
+fn is_corruption(e: &rusqlite::Error) -> bool {
+    e.sqlite_error_code()
+        .is_some_and(|code| matches!(code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase))
+}
+
+/// Returns a description of the problem if the database at `path` is corrupt.
+///
+/// This only runs a quick check, which skips comparing the indexes with their tables, so
+/// starting up stays fast on large databases. A missing file is fine, it will just get
+/// created.
+fn check_integrity(path: &Path) -> anyhow::Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let result = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|conn| conn.query_row("PRAGMA quick_check", [], |row| row.get::<_, String>(0)));
+
+    match result {
+        Ok(status) if status == "ok" => Ok(None),
+        Ok(status) => Ok(Some(status)),
+        Err(e) if is_corruption(&e) => Ok(Some(e.to_string())),
+        Err(e) => Err(e).context("failed to check database integrity"),
+    }
+}
+
+/// Renames the database at `path` (and its sidecar files) to `<name>.corrupt-<unix time>`.
+fn move_aside(path: &Path) -> anyhow::Result<PathBuf> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut moved_to = path.as_os_str().to_owned();
+    moved_to.push(format!(".corrupt-{now}"));
+    let moved_to = PathBuf::from(moved_to);
+
+    fs::rename(path, &moved_to)
+        .with_context(|| format!("failed to move {} aside", path.display()))?;
+
+    for suffix in SIDECAR_SUFFIXES {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let mut sidecar_moved_to = moved_to.as_os_str().to_owned();
+        sidecar_moved_to.push(suffix);
+
+        if Path::new(&sidecar).exists() {
+            fs::rename(&sidecar, &sidecar_moved_to).with_context(|| {
+                format!("failed to move {} aside", Path::new(&sidecar).display())
+            })?;
+        }
+    }
+
+    Ok(moved_to)
+}
+
+/// Copies every readable row from the corrupt database at `from` into `to`.
+///
+/// Every table of the current schema is copied with the columns the old database has for it,
+/// apart from the daily rollup, which the triggers on `app_usage` rebuild as sessions come in.
+/// Returns how many rows were copied and how many could not be read. Reading a table stops at
+/// the first error that leaves the rest of it unreachable, a table that can't be read at all
+/// is skipped.
+fn salvage(from: &Path, to: &Connection) -> anyhow::Result<(usize, usize)> {
+    let old = Connection::open_with_flags(from, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let tables = to
+        .prepare(
+            "SELECT name FROM sqlite_master
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+                AND name NOT IN ('schema_version', 'app_usage_daily')",
+        )?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tx = to.unchecked_transaction()?;
+    let (mut copied, mut lost) = (0, 0);
+    for table in tables {
+        match salvage_table(&old, &tx, &table) {
+            Ok((table_copied, table_lost)) => {
+                copied += table_copied;
+                lost += table_lost;
+            }
+            Err(e) => warn!("could not salvage {table}: {e}"),
+        }
+    }
+    tx.commit()?;
+
+    Ok((copied, lost))
+}
+
+/// Copies the readable rows of `table` from `old` into `to`, see [`salvage`].
+fn salvage_table(
+    old: &Connection,
+    to: &Connection,
+    table: &str,
+) -> rusqlite::Result<(usize, usize)> {
+    let old_columns = table_columns(old, table)?;
+    let columns = table_columns(to, table)?
+        .into_iter()
+        .filter(|column| old_columns.contains(column))
+        .map(|column| format!("\"{column}\""))
+        .collect::<Vec<_>>();
+    if columns.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let columns = columns.join(", ");
+    let mut stmt = old.prepare(&format!("SELECT {columns} FROM \"{table}\""))?;
+    let column_count = stmt.column_count();
+    let placeholders = vec!["?"; column_count].join(", ");
+    let mut insert = to.prepare(&format!(
+        "INSERT INTO \"{table}\" ({columns}) VALUES ({placeholders})"
+    ))?;
+    let mut rows = stmt.query([])?;
+
+    let (mut copied, mut lost) = (0, 0);
+    loop {
+        let row = match rows.next() {
+            Ok(Some(row)) => row,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("stopped salvaging {table}: {e}");
+                break;
+            }
+        };
+
+        let values = (0..column_count)
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<Result<Vec<_>, _>>();
+
+        match values {
+            Ok(values) => {
+                insert.execute(params_from_iter(values))?;
+                copied += 1;
+            }
+            Err(_) => lost += 1,
+        }
+    }
+
+    Ok((copied, lost))
+}
+
+/// Returns the names of the columns of `table`, none if there is no such table.
+fn table_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("SELECT name FROM pragma_table_info(?1)")?
+        .query_map([table], |row| row.get(0))?
+        .collect()
+}
+
+/// Compacts the database, logging how much smaller it got.
+pub fn vacuum(conn: &Connection) {
+    match appusage_core::maintenance::run(conn) {
+        Ok(sizes) => info!(
+            "vacuumed database, {} bytes before, {} after",
+            sizes.before, sizes.after
+        ),
+        Err(e) => warn!("Failed to vacuum database: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn salvaging_copies_every_table() {
+        let dir = std::env::temp_dir().join(format!("appusage-salvage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (from, to) = (dir.join("corrupt.db"), dir.join("fresh.db"));
+        let _ = fs::remove_file(&from);
+        let _ = fs::remove_file(&to);
+
+        let old = create(&from).unwrap();
+        old.execute_batch(
+            "INSERT INTO app_usage (app_name, start_time, end_time, duration)
+                VALUES ('firefox', 1000, 4000, 3000);
+            INSERT INTO daemon_runs (start_time, end_time) VALUES (0, 5000);
+            INSERT INTO app_tags (app_name, tag) VALUES ('firefox', 'browser');
+            INSERT INTO window_counts (time, count) VALUES (2000, 3);",
+        )
+        .unwrap();
+        drop(old);
+
+        let conn = create(&to).unwrap();
+        assert_eq!(salvage(&from, &conn).unwrap(), (4, 0));
+
+        for table in ["app_usage", "daemon_runs", "app_tags", "window_counts"] {
+            let count: i64 = conn
+                .query_row(&format!("SELECT count(*) FROM {table}"), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(count, 1, "{table}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn salvaging_keeps_every_column() {
+        let dir = std::env::temp_dir().join(format!("appusage-columns-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (from, to) = (dir.join("corrupt.db"), dir.join("fresh.db"));
+        let _ = fs::remove_file(&from);
+        let _ = fs::remove_file(&to);
+
+        let old = create(&from).unwrap();
+        old.execute(
+            "INSERT INTO app_usage (app_name, start_time, end_time, duration, title)
+                VALUES ('firefox', 1000, 4000, 3000, 'Inbox')",
+            [],
+        )
+        .unwrap();
+        drop(old);
+
+        let conn = create(&to).unwrap();
+        salvage(&from, &conn).unwrap();
+
+        let title: String = conn
+            .query_row("SELECT title FROM app_usage", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(title, "Inbox");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
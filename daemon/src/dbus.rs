@@ -0,0 +1,238 @@
+//! Answers questions about the recorded usage over D-Bus, for status bars and scripts that
+//! don't want to read the database themselves.
+//!
+//! The service is `org.wayland_appusage.Daemon` on the session bus, with the interface
+//! `org.wayland_appusage.Usage` at `/org/wayland_appusage/Usage`:
+//!
+//! - `TodayTotal() -> t`: milliseconds of usage since local midnight.
+//! - `TopApps(s range, u n) -> a(st)`: the `n` most used apps and their milliseconds, `range`
+//!   is one of `today`, `week`, `month` or `all`. Weeks and months follow the calendar like
+//!   in the tui, `week` starts on the locale's first day of the week.
+//! - `CurrentFocus() -> (st)`: the focused app and when it gained focus, in milliseconds
+//!   since the unix epoch, or an empty app id if none is.
+//! - `FocusChanged(s app_id)`: sent whenever an app gains focus.
+//!
+//! Only built with the `dbus` feature.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use appusage_core::{Database, TimeRange};
+use tracing::warn;
+use zbus::fdo;
+
+const SERVICE_NAME: &str = "org.wayland_appusage.Daemon";
+const OBJECT_PATH: &str = "/org/wayland_appusage/Usage";
+const INTERFACE_NAME: &str = "org.wayland_appusage.Usage";
+
+/// The app that is focused right now and since when, shared between the event loop and the
+/// service.
+type Focused = Arc<Mutex<Option<(String, SystemTime)>>>;
+
+/// The object served at [`OBJECT_PATH`], its methods run on zbus' own thread, so it has a
+/// connection of its own and never touches the daemon's.
+struct Usage {
+    db: Mutex<Database>,
+    focused: Focused,
+}
+
+#[zbus::interface(name = "org.wayland_appusage.Usage")]
+impl Usage {
+    fn today_total(&self) -> fdo::Result<u64> {
+        let db = self.db.lock().unwrap();
+        let range = resolve_range(&db, "today").map_err(to_fdo)?;
+        let total = db.total(range).map_err(to_fdo)?;
+        Ok(total.as_millis() as u64)
+    }
+
+    fn top_apps(&self, range: &str, n: u32) -> fdo::Result<Vec<(String, u64)>> {
+        let db = self.db.lock().unwrap();
+        let range = resolve_range(&db, range).map_err(to_fdo)?;
+        Ok(db
+            .apps(range)
+            .map_err(to_fdo)?
+            .into_iter()
+            .take(n as usize)
+            .map(|x| (x.app_id, x.total.as_millis() as u64))
+            .collect())
+    }
+
+    fn current_focus(&self) -> (String, u64) {
+        match &*self.focused.lock().unwrap() {
+            Some((app_id, since)) => (
+                app_id.clone(),
+                since
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+            ),
+            None => (String::new(), 0),
+        }
+    }
+}
+
+fn to_fdo(e: impl std::fmt::Display) -> fdo::Error {
+    fdo::Error::Failed(e.to_string())
+}
+
+/// The time range `today`, `week`, `month` or `all` stands for, days start at local midnight
+/// and the week and month are the current ones like in the tui.
+fn resolve_range(db: &Database, name: &str) -> anyhow::Result<Option<TimeRange>> {
+    let modifiers = match name {
+        "today" => String::new(),
+        // sqlite counts weekdays from sunday, "weekday" moves forward to the next such day
+        "week" => format!(
+            ", '-6 days', 'weekday {}'",
+            (appusage_core::locale_week_start() + 1) % 7
+        ),
+        "month" => ", 'start of month'".to_string(),
+        "all" => return Ok(None),
+        _ => anyhow::bail!("unknown range {name:?}, expected today, week, month or all"),
+    };
+    // the daemon has no time zone handling of its own, sqlite knows the local time though
+    let start: u64 = db.connection().query_row(
+        &format!(
+            "SELECT CAST(strftime('%s', 'now', 'localtime', 'start of day'{modifiers}, 'utc')
+                AS INTEGER)"
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(Some(TimeRange::new(
+        UNIX_EPOCH + Duration::from_secs(start),
+        SystemTime::now(),
+    )))
+}
+
+/// The daemon's end of the service, used to send signals.
+#[derive(Debug)]
+pub struct Service {
+    conn: zbus::blocking::Connection,
+    focused: Focused,
+}
+
+impl Service {
+    /// Claims the service name on the session bus and starts answering calls, reading the
+    /// database at `db_path`.
+    pub fn start(db_path: &Path) -> anyhow::Result<Self> {
+        let db = Database::open(db_path).context("failed to open database for D-Bus")?;
+        let focused = Focused::default();
+        let conn = zbus::blocking::connection::Builder::session()?
+            .name(SERVICE_NAME)?
+            .serve_at(
+                OBJECT_PATH,
+                Usage {
+                    db: Mutex::new(db),
+                    focused: focused.clone(),
+                },
+            )?
+            .build()
+            .context("failed to register on the session bus")?;
+        Ok(Self { conn, focused })
+    }
+
+    /// Tells listeners that `app_id` gained focus.
+    pub fn focus_changed(&self, app_id: &str) {
+        *self.focused.lock().unwrap() = Some((app_id.to_string(), SystemTime::now()));
+        if let Err(e) = self.conn.emit_signal(
+            None::<&str>,
+            OBJECT_PATH,
+            INTERFACE_NAME,
+            "FocusChanged",
+            &(app_id,),
+        ) {
+            warn!("failed to send focus change over D-Bus: {e}");
+        }
+    }
+
+    /// Forgets whichever app is focused, like when the user went idle.
+    pub fn clear_focus(&self) {
+        *self.focused.lock().unwrap() = None;
+    }
+
+    /// Forgets that `app_id` is focused, unless another app gained focus meanwhile.
+    pub fn focus_lost(&self, app_id: &str) {
+        let mut focused = self.focused.lock().unwrap();
+        if focused
+            .as_ref()
+            .is_some_and(|(focused, _)| focused == app_id)
+        {
+            *focused = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_resolved() {
+        let db = Database::open_in_memory().unwrap();
+        assert_eq!(resolve_range(&db, "all").unwrap(), None);
+        assert!(resolve_range(&db, "yesterday").is_err());
+
+        let today = resolve_range(&db, "today").unwrap().unwrap();
+        assert!(today.start <= SystemTime::now());
+        // the week and month started at most 6 and 30 days before today, a daylight saving
+        // time change in between makes them an hour off
+        for (name, max_days) in [("week", 6.0), ("month", 30.0)] {
+            let range = resolve_range(&db, name).unwrap().unwrap();
+            let days = today
+                .start
+                .duration_since(range.start)
+                .unwrap()
+                .as_secs_f64()
+                / 86_400.0;
+            assert!(
+                days > -1.0 / 24.0 && days < max_days + 1.0 / 24.0,
+                "{name} started {days} days ago"
+            );
+            assert!(
+                (days - days.round()).abs() <= 1.0 / 24.0,
+                "{name} is {days} days"
+            );
+        }
+    }
+
+    #[test]
+    fn top_apps_are_limited() {
+        let db = Database::open_in_memory().unwrap();
+        for (app_id, secs) in [("firefox", 60), ("foot", 30), ("mpv", 10)] {
+            db.record(app_id, SystemTime::now(), Duration::from_secs(secs))
+                .unwrap();
+        }
+        let usage = Usage {
+            db: Mutex::new(db),
+            focused: Focused::default(),
+        };
+
+        assert_eq!(
+            usage.top_apps("all", 2).unwrap(),
+            vec![
+                ("firefox".to_string(), 60_000),
+                ("foot".to_string(), 30_000)
+            ]
+        );
+        assert!(usage.top_apps("fortnight", 2).is_err());
+    }
+
+    #[test]
+    fn current_focus_is_reported() {
+        let usage = Usage {
+            db: Mutex::new(Database::open_in_memory().unwrap()),
+            focused: Focused::default(),
+        };
+        assert_eq!(usage.current_focus(), (String::new(), 0));
+
+        *usage.focused.lock().unwrap() = Some((
+            "firefox".to_string(),
+            UNIX_EPOCH + Duration::from_millis(1500),
+        ));
+        assert_eq!(usage.current_focus(), ("firefox".to_string(), 1500));
+    }
+}
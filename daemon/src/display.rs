@@ -0,0 +1,93 @@
+//! Picks the wayland socket to connect to, `--wayland-display` chooses one other than that of
+//! the session the daemon was started in, for nested compositors or when several sessions are
+//! running.
+//!
+//! Like `WAYLAND_DISPLAY`, which is used without the flag, the display is either the name of a
+//! socket in `XDG_RUNTIME_DIR` or an absolute path. With neither of them the connection is
+//! left to `connect_to_env`, which also takes a socket handed over in `WAYLAND_SOCKET`.
+
+use std::{
+    env,
+    ffi::OsString,
+    fmt,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use wayland_client::Connection;
+
+/// The compositor to connect to, resolved once so reconnecting goes to the same one.
+#[derive(Debug, Clone)]
+pub enum WaylandDisplay {
+    Socket(PathBuf),
+    /// Whatever the environment says, see [`Connection::connect_to_env`].
+    Env,
+}
+
+impl WaylandDisplay {
+    /// Resolves the display given on the command line, or the one in `WAYLAND_DISPLAY`,
+    /// failing if its socket doesn't exist.
+    pub fn resolve(display: Option<OsString>) -> anyhow::Result<Self> {
+        let Some(display) = display.or_else(|| env::var_os("WAYLAND_DISPLAY")) else {
+            return Ok(Self::Env);
+        };
+        let path = socket_path(Path::new(&display), env::var_os("XDG_RUNTIME_DIR"))?;
+        if !path.exists() {
+            bail!(
+                "wayland socket {} does not exist, is the compositor running?",
+                path.display()
+            );
+        }
+        Ok(Self::Socket(path))
+    }
+
+    pub fn connect(&self) -> anyhow::Result<Connection> {
+        match self {
+            Self::Socket(path) => {
+                let stream = UnixStream::connect(path)
+                    .with_context(|| format!("failed to connect to {}", path.display()))?;
+                Ok(Connection::from_socket(stream)?)
+            }
+            Self::Env => Ok(Connection::connect_to_env()?),
+        }
+    }
+}
+
+impl fmt::Display for WaylandDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Socket(path) => write!(f, "{}", path.display()),
+            Self::Env => write!(f, "the display from the environment"),
+        }
+    }
+}
+
+/// Names are relative to the runtime directory, absolute paths are taken as they are.
+fn socket_path(display: &Path, runtime_dir: Option<OsString>) -> anyhow::Result<PathBuf> {
+    if display.is_absolute() {
+        return Ok(display.to_path_buf());
+    }
+    let runtime_dir = runtime_dir
+        .context("XDG_RUNTIME_DIR isn't set, give the full path of the wayland socket instead")?;
+    Ok(Path::new(&runtime_dir).join(display))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_looked_up_in_the_runtime_dir() {
+        let runtime_dir = Some(OsString::from("/run/user/1000"));
+        assert_eq!(
+            socket_path(Path::new("wayland-1"), runtime_dir.clone()).unwrap(),
+            Path::new("/run/user/1000/wayland-1")
+        );
+        assert_eq!(
+            socket_path(Path::new("/tmp/nested/wayland-0"), runtime_dir).unwrap(),
+            Path::new("/tmp/nested/wayland-0")
+        );
+        assert!(socket_path(Path::new("wayland-1"), None).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Asks GNOME Shell which app is focused, for when the compositor is mutter, which implements
+//! neither wlr-foreign-toplevel-management nor ext-idle-notify.
+//!
+//! Mutter doesn't tell other clients which window is focused by itself, so this relies on the
+//! [Focused Window D-Bus](https://extensions.gnome.org/extension/5592/focused-window-d-bus/)
+//! extension. Idle time comes from mutter's own idle monitor. Neither sends signals for
+//! changes, so both are polled.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::app::{AppState, WindowKey};
+
+/// How often the focused window and idle time are checked.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const SHELL_DESTINATION: &str = "org.gnome.Shell";
+const FOCUSED_WINDOW_PATH: &str = "/org/gnome/shell/extensions/FocusedWindow";
+const FOCUSED_WINDOW_INTERFACE: &str = "org.gnome.shell.extensions.FocusedWindow";
+
+const IDLE_MONITOR_DESTINATION: &str = "org.gnome.Mutter.IdleMonitor";
+const IDLE_MONITOR_PATH: &str = "/org/gnome/Mutter/IdleMonitor/Core";
+const IDLE_MONITOR_INTERFACE: &str = "org.gnome.Mutter.IdleMonitor";
+
+/// The part of what the extension reports about the focused window that is needed here.
+#[derive(Debug, Deserialize)]
+struct FocusedWindow {
+    /// Set from the app id for wayland clients.
+    wm_class: Option<String>,
+    title: Option<String>,
+}
+
+pub struct Shell {
+    conn: zbus::blocking::Connection,
+    idle_timeout: Duration,
+    /// The app that was focused at the last poll.
+    focused: Option<String>,
+    idle: bool,
+}
+
+impl Shell {
+    /// Connects to the session bus, failing if GNOME Shell or the extension doesn't answer.
+    pub fn connect(idle_timeout: Duration) -> anyhow::Result<Self> {
+        let conn = zbus::blocking::Connection::session()
+            .context("failed to connect to the session bus")?;
+        let shell = Self {
+            conn,
+            idle_timeout,
+            focused: None,
+            idle: false,
+        };
+        shell.focused_window().context(
+            "could not ask GNOME Shell for the focused window, is the Focused Window D-Bus \
+             extension installed and enabled?",
+        )?;
+        shell
+            .idle_time()
+            .context("could not ask mutter for the idle time")?;
+        Ok(shell)
+    }
+
+    fn focused_window(&self) -> anyhow::Result<Option<FocusedWindow>> {
+        let reply = self.conn.call_method(
+            Some(SHELL_DESTINATION),
+            FOCUSED_WINDOW_PATH,
+            Some(FOCUSED_WINDOW_INTERFACE),
+            "Get",
+            &(),
+        )?;
+        let json: String = reply.body().deserialize()?;
+        // nothing being focused comes back empty
+        if json.trim().is_empty() {
+            return Ok(None);
+        }
+        let window: FocusedWindow = serde_json::from_str(&json)?;
+        Ok(Some(window).filter(|x| x.wm_class.as_ref().is_some_and(|x| !x.is_empty())))
+    }
+
+    fn idle_time(&self) -> anyhow::Result<Duration> {
+        let reply = self.conn.call_method(
+            Some(IDLE_MONITOR_DESTINATION),
+            IDLE_MONITOR_PATH,
+            Some(IDLE_MONITOR_INTERFACE),
+            "GetIdletime",
+            &(),
+        )?;
+        Ok(Duration::from_millis(reply.body().deserialize()?))
+    }
+
+    /// Checks what changed since the last poll and reports it to `state`, the same way the
+    /// wayland protocols would.
+    pub fn poll(&mut self, state: &mut AppState) {
+        match self.idle_time() {
+            Ok(idle_time) => {
+                let idle = idle_time >= self.idle_timeout;
+                if idle && !self.idle {
+                    debug!("idle for {idle_time:?}");
+                    state.idled();
+                } else if !idle && self.idle {
+                    state.resumed();
+                }
+                self.idle = idle;
+            }
+            Err(e) => warn!("failed to get idle time from mutter: {e:#}"),
+        }
+
+        let window = match self.focused_window() {
+            Ok(window) => window,
+            // this also fails while nothing is focused, like on the lock screen
+            Err(e) => {
+                debug!("failed to get focused window from GNOME Shell: {e:#}");
+                None
+            }
+        };
+        let (focused, title) = window.map(|x| (x.wm_class, x.title)).unwrap_or_default();
+        if focused != self.focused {
+            self.focus_moved(state, focused);
+        }
+        // windows of the same app are one here, so switching between them changes the title
+        if let (Some(app_id), Some(title)) = (&self.focused, title) {
+            state.set_title(WindowKey::Gnome(app_id.clone()), title);
+        }
+    }
+
+    fn focus_moved(&mut self, state: &mut AppState, focused: Option<String>) {
+        debug!("focus moved from {:?} to {:?}", self.focused, focused);
+        if let Some(previous) = self.focused.take() {
+            state.close_window(&WindowKey::Gnome(previous));
+        }
+        if let Some(app_id) = &focused {
+            let key = WindowKey::Gnome(app_id.clone());
+            state.add_window(key.clone(), Some(app_id.clone()));
+            state.set_focus(key, true);
+        }
+        self.focused = focused;
+    }
+}
@@ -0,0 +1,40 @@
+//! Keeps track of when the daemon was running, so a day without usage can be told apart from
+//! a day the daemon simply wasn't running on.
+//!
+//! Every run gets a row in `daemon_runs` whose end is pushed forward periodically by a timer on
+//! the event loop, so after a crash it is off by at most [`HEARTBEAT_INTERVAL`].
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, params};
+
+use crate::app::round_millis;
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A clock set before 1970 reads as the epoch rather than crashing the daemon.
+fn now_millis() -> u64 {
+    round_millis(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+    )
+}
+
+/// Records a new run starting now and returns its id.
+pub fn start_run(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    let now = now_millis();
+    conn.execute(
+        "INSERT INTO daemon_runs (start_time, end_time) VALUES (?1, ?2)",
+        params![now, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Moves the end of the run to now.
+pub fn touch_run(conn: &Connection, run_id: i64) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "UPDATE daemon_runs SET end_time = ?1 WHERE id = ?2",
+        params![now_millis(), run_id],
+    )
+}
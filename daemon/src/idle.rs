@@ -0,0 +1,95 @@
+//! Combines the idle notifications of several seats into whether the user is idle.
+//!
+//! Like the tracker this knows nothing about wayland, seats are identified by their registry
+//! name.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct SeatIdleness {
+    /// Whether each seat with an idle notification is idle.
+    seats: HashMap<u32, bool>,
+    /// Count as idle once any seat is, instead of once all of them are.
+    any: bool,
+}
+
+impl SeatIdleness {
+    pub fn new(any: bool) -> Self {
+        Self {
+            seats: HashMap::new(),
+            any,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.seats.len()
+    }
+
+    /// Whether the user counts as idle, never without any seats to tell.
+    pub fn is_idle(&self) -> bool {
+        if self.seats.is_empty() {
+            return false;
+        }
+        if self.any {
+            self.seats.values().any(|idle| *idle)
+        } else {
+            self.seats.values().all(|idle| *idle)
+        }
+    }
+
+    /// Updates whether the seat is idle, adding it if it is new, and returns whether the user
+    /// is idle now if that changed.
+    pub fn set(&mut self, seat: u32, idle: bool) -> Option<bool> {
+        let was_idle = self.is_idle();
+        self.seats.insert(seat, idle);
+        self.changed(was_idle)
+    }
+
+    /// Forgets the seat, returning whether the user is idle now if that changed.
+    pub fn remove(&mut self, seat: u32) -> Option<bool> {
+        let was_idle = self.is_idle();
+        self.seats.remove(&seat);
+        self.changed(was_idle)
+    }
+
+    pub fn clear(&mut self) {
+        self.seats.clear();
+    }
+
+    fn changed(&self, was_idle: bool) -> Option<bool> {
+        let idle = self.is_idle();
+        (idle != was_idle).then_some(idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_once_all_seats_are() {
+        let mut seats = SeatIdleness::new(false);
+        assert_eq!(seats.set(1, false), None);
+        assert_eq!(seats.set(2, false), None);
+
+        assert_eq!(seats.set(1, true), None);
+        assert_eq!(seats.set(2, true), Some(true));
+        assert_eq!(seats.set(1, false), Some(false));
+
+        // the only seat that was still in use going away leaves everything idle
+        assert_eq!(seats.remove(1), Some(true));
+        assert_eq!(seats.remove(2), Some(false));
+    }
+
+    #[test]
+    fn idle_once_any_seat_is() {
+        let mut seats = SeatIdleness::new(true);
+        seats.set(1, false);
+        seats.set(2, false);
+
+        assert_eq!(seats.set(1, true), Some(true));
+        assert_eq!(seats.set(2, true), None);
+        assert_eq!(seats.set(1, false), None);
+        assert_eq!(seats.set(2, false), Some(false));
+    }
+}
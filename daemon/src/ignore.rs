@@ -0,0 +1,129 @@
+//! Keeps apps listed under `ignore` in the config, like lock screens or the compositor's own
+//! surfaces, out of the database, or everything but the apps listed under `record_only`.
+//!
+//! `record_only` takes precedence, as long as it lists any app `ignore` isn't looked at, so
+//! an app in both of them is still recorded. Both being empty, the default, records every
+//! app.
+//!
+//! Focus is still tracked on them like on any other app, so focusing one ends the session of
+//! the app focused before, only their own sessions are dropped instead of being written.
+
+use appusage_core::config::Config;
+
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    /// Patterns where `*` matches any run of characters, case folded when `fold_case` is set.
+    ignore: Vec<String>,
+    record_only: Vec<String>,
+    fold_case: bool,
+}
+
+impl IgnoreList {
+    pub fn from_config(config: &Config) -> Self {
+        let fold_case = config.fold_app_id_case;
+        let patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|x| {
+                    if fold_case {
+                        x.to_lowercase()
+                    } else {
+                        x.clone()
+                    }
+                })
+                .collect()
+        };
+        Self {
+            ignore: patterns(&config.ignore),
+            record_only: patterns(&config.record_only),
+            fold_case,
+        }
+    }
+
+    pub fn is_ignored(&self, app_id: &str) -> bool {
+        if self.ignore.is_empty() && self.record_only.is_empty() {
+            return false;
+        }
+        let app_id = if self.fold_case {
+            app_id.to_lowercase()
+        } else {
+            app_id.to_string()
+        };
+        let listed = |patterns: &[String]| patterns.iter().any(|x| matches(x, &app_id));
+        if self.record_only.is_empty() {
+            listed(&self.ignore)
+        } else {
+            !listed(&self.record_only)
+        }
+    }
+}
+
+/// Matches a whole app id against a pattern in which `*` stands for any run of characters.
+fn matches(pattern: &str, app_id: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(rest) = app_id.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    // without any `*` the prefix has to be all of it
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+
+    // taking the first occurrence of each part leaves the most room for the ones after it
+    let mut rest = rest;
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_whole_app_ids() {
+        assert!(matches("swaylock", "swaylock"));
+        assert!(!matches("swaylock", "swaylock-effects"));
+        assert!(matches("org.kde.*", "org.kde.plasmashell"));
+        assert!(!matches("org.kde.*", "org.gnome.Shell"));
+        assert!(matches("*lock*", "gtklock"));
+        assert!(matches("a*b*c", "abbc"));
+        assert!(!matches("a*b*c", "acb"));
+        assert!(!matches("ab*ba", "aba"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn case_is_folded_like_the_config_says() {
+        let mut config = Config::default();
+        config.ignore = vec!["Swaylock".to_string(), "org.KDE.*".to_string()];
+        let ignored = IgnoreList::from_config(&config);
+        assert!(ignored.is_ignored("swaylock"));
+        assert!(ignored.is_ignored("org.kde.krunner"));
+        assert!(!ignored.is_ignored("firefox"));
+
+        config.fold_app_id_case = false;
+        let ignored = IgnoreList::from_config(&config);
+        assert!(!ignored.is_ignored("swaylock"));
+        assert!(ignored.is_ignored("Swaylock"));
+    }
+
+    #[test]
+    fn record_only_takes_precedence() {
+        let mut config = Config::default();
+        assert!(!IgnoreList::from_config(&config).is_ignored("firefox"));
+
+        config.ignore = vec!["firefox".to_string(), "mpv".to_string()];
+        config.record_only = vec!["firefox".to_string(), "foot".to_string()];
+        let ignored = IgnoreList::from_config(&config);
+        assert!(!ignored.is_ignored("firefox"));
+        assert!(!ignored.is_ignored("foot"));
+        assert!(ignored.is_ignored("mpv"));
+        assert!(ignored.is_ignored("steam"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Follows the active window on KDE Plasma through a KWin script, for KWin versions that
+//! don't implement wlr-foreign-toplevel-management.
+//!
+//! KWin doesn't tell other clients which window is active, but its scripts know and can call
+//! D-Bus methods. [`start`] serves `WindowActivated(s app_id, s title)` on the session bus as
+//! `org.wayland_appusage.KWin` and loads a script into KWin that calls it whenever the active
+//! window changes, with an empty app id when none is. Idle time still comes from
+//! ext-idle-notify, which KWin implements.
+//!
+//! The script only sees the active window, so windows are told apart by app id like with
+//! GNOME Shell, and the window count can't be recorded.
+
+use std::{fs, sync::Mutex};
+
+use anyhow::{Context, bail};
+use calloop::{
+    LoopHandle,
+    channel::{self, Sender},
+};
+use tracing::debug;
+
+use crate::app::{AppState, WindowKey};
+
+const KWIN_DESTINATION: &str = "org.kde.KWin";
+const SCRIPTING_PATH: &str = "/Scripting";
+const SCRIPTING_INTERFACE: &str = "org.kde.kwin.Scripting";
+const SCRIPT_INTERFACE: &str = "org.kde.kwin.Script";
+/// What the script is loaded as, so the one of an earlier run can be unloaded.
+const PLUGIN_NAME: &str = "wayland-appusage";
+
+const SERVICE_NAME: &str = "org.wayland_appusage.KWin";
+const OBJECT_PATH: &str = "/org/wayland_appusage/KWin";
+
+/// Calls back whenever the active window changes, KWin 6 calls windows what KWin 5 called
+/// clients.
+const SCRIPT: &str = r#"
+function report(window) {
+    callDBus(
+        "org.wayland_appusage.KWin",
+        "/org/wayland_appusage/KWin",
+        "org.wayland_appusage.KWin",
+        "WindowActivated",
+        window ? window.desktopFileName || window.resourceClass || "" : "",
+        window ? window.caption : "",
+    );
+}
+if (workspace.windowActivated) {
+    workspace.windowActivated.connect(report);
+    report(workspace.activeWindow);
+} else {
+    workspace.clientActivated.connect(report);
+    report(workspace.activeClient);
+}
+"#;
+
+struct Activation {
+    app_id: Option<String>,
+    title: String,
+}
+
+/// The object the script calls, its methods run on zbus' own thread, so activations are
+/// passed on to the event loop.
+struct Listener {
+    sender: Mutex<Sender<Activation>>,
+}
+
+#[zbus::interface(name = "org.wayland_appusage.KWin")]
+impl Listener {
+    fn window_activated(&self, app_id: String, title: String) {
+        let activation = Activation {
+            app_id: Some(app_id).filter(|x| !x.is_empty()),
+            title,
+        };
+        // only fails once the event loop is gone, when the daemon exits anyway
+        let _ = self.sender.lock().unwrap().send(activation);
+    }
+}
+
+pub struct KWin {
+    conn: zbus::blocking::Connection,
+}
+
+impl KWin {
+    /// Connects to the session bus, failing if KWin isn't running on it.
+    pub fn connect() -> anyhow::Result<Self> {
+        let conn = zbus::blocking::Connection::session()
+            .context("failed to connect to the session bus")?;
+        let reply = conn.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus"),
+            "NameHasOwner",
+            &(KWIN_DESTINATION,),
+        )?;
+        if !reply.body().deserialize::<bool>()? {
+            bail!("KWin isn't running");
+        }
+        Ok(Self { conn })
+    }
+
+    /// Loads the script into KWin, replacing the one of an earlier run.
+    fn load_script(&self) -> anyhow::Result<()> {
+        let path = xdg::BaseDirectories::with_prefix("wayland-appusage")?
+            .place_runtime_file("kwin.js")
+            .context("failed to find a place for the KWin script")?;
+        fs::write(&path, SCRIPT)
+            .with_context(|| format!("failed to write KWin script to {}", path.display()))?;
+
+        // a script that is already loaded keeps running the old version
+        self.conn.call_method(
+            Some(KWIN_DESTINATION),
+            SCRIPTING_PATH,
+            Some(SCRIPTING_INTERFACE),
+            "unloadScript",
+            &(PLUGIN_NAME,),
+        )?;
+        let reply = self.conn.call_method(
+            Some(KWIN_DESTINATION),
+            SCRIPTING_PATH,
+            Some(SCRIPTING_INTERFACE),
+            "loadScript",
+            &(path.to_string_lossy().as_ref(), PLUGIN_NAME),
+        )?;
+        let id: i32 = reply.body().deserialize()?;
+        if id < 0 {
+            bail!("KWin refused to load the script");
+        }
+
+        // KWin 6 has the scripts below the scripting object, KWin 5 at the root
+        let run = |path: String| {
+            self.conn.call_method(
+                Some(KWIN_DESTINATION),
+                path,
+                Some(SCRIPT_INTERFACE),
+                "run",
+                &(),
+            )
+        };
+        run(format!("{SCRIPTING_PATH}/Script{id}"))
+            .or_else(|_| run(format!("/{id}")))
+            .context("failed to run the KWin script")?;
+        Ok(())
+    }
+}
+
+/// Serves the object the script calls, loads the script and reports every change of the
+/// active window to the state.
+pub fn start(handle: &LoopHandle<'static, AppState>, kwin: KWin) -> anyhow::Result<()> {
+    let (sender, receiver) = channel::channel();
+    let listener = zbus::blocking::connection::Builder::session()?
+        .name(SERVICE_NAME)?
+        .serve_at(
+            OBJECT_PATH,
+            Listener {
+                sender: Mutex::new(sender),
+            },
+        )?
+        .build()
+        .context("failed to register on the session bus")?;
+
+    let mut focused = None;
+    handle
+        .insert_source(receiver, move |event, _, state| {
+            // the connection has to stay open for the script to reach the listener
+            let _ = &listener;
+            if let channel::Event::Msg(activation) = event {
+                focus_moved(state, &mut focused, activation);
+            }
+        })
+        .map_err(|e| anyhow::anyhow!("failed to add KWin events to event loop: {e}"))?;
+
+    kwin.load_script()
+}
+
+fn focus_moved(state: &mut AppState, focused: &mut Option<String>, activation: Activation) {
+    if activation.app_id != *focused {
+        debug!("focus moved from {focused:?} to {:?}", activation.app_id);
+        if let Some(previous) = focused.take() {
+            state.close_window(&WindowKey::Kwin(previous));
+        }
+        if let Some(app_id) = &activation.app_id {
+            let key = WindowKey::Kwin(app_id.clone());
+            state.add_window(key.clone(), Some(app_id.clone()));
+            state.set_focus(key, true);
+        }
+        *focused = activation.app_id;
+    }
+    // windows of the same app are one here, so switching between them changes the title
+    if let Some(app_id) = focused {
+        state.set_title(WindowKey::Kwin(app_id.clone()), activation.title);
+    }
+}
@@ -0,0 +1,314 @@
+//! Shows a desktop notification once an app, or all of them together, was used for longer
+//! than its daily limit.
+//!
+//! Today's usage is read from the database once a day and kept up to date as sessions are
+//! recorded after that, so checking the limits never has to query anything. Each limit is
+//! only notified about once a day. Without the `notifications` feature reached limits are
+//! only logged.
+//!
+//! Limits go by app like the tui shows them, app ids that only differ in case (unless
+//! `fold_app_id_case` is turned off) or are aliases of the same app share one limit, which
+//! can be set under any of those names.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use appusage_core::config::Config;
+use rusqlite::Connection;
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub struct Limits {
+    /// The limit of each app along with the name it was configured under, by [`Self::key`].
+    apps: HashMap<String, (String, Duration)>,
+    total: Option<Duration>,
+    app_message: String,
+    total_message: String,
+    /// Usage since local midnight by app, by [`Self::key`].
+    today: HashMap<String, Duration>,
+    /// When today is over and the usage starts over.
+    day_end: SystemTime,
+    /// Limits already notified about today, by [`Self::key`], unset for the total.
+    notified: HashSet<Option<String>>,
+    /// Canonical names by app id, case folded when `fold_case` is set.
+    aliases: HashMap<String, String>,
+    fold_case: bool,
+    /// Connected on the first notification.
+    #[cfg(feature = "notifications")]
+    bus: Option<zbus::blocking::Connection>,
+}
+
+impl Limits {
+    /// Reads the limits from the config and today's usage so far from the database, unless no
+    /// limits are set.
+    ///
+    /// Limits that were already reached today don't notify again, they most likely did before
+    /// the daemon was restarted.
+    pub fn load(config: &Config, conn: &Connection) -> Option<Self> {
+        let mut limits = Self::from_config(config)?;
+        limits.start_day(conn);
+        limits.reached();
+        Some(limits)
+    }
+
+    fn from_config(config: &Config) -> Option<Self> {
+        if config.daily_limits.is_empty() && config.daily_total_limit.is_none() {
+            return None;
+        }
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        let fold_case = config.fold_app_id_case;
+        let mut limits = Self {
+            apps: HashMap::new(),
+            total: config.daily_total_limit.map(minutes),
+            app_message: config.limit_message.clone(),
+            total_message: config.total_limit_message.clone(),
+            today: HashMap::new(),
+            day_end: UNIX_EPOCH,
+            notified: HashSet::new(),
+            aliases: config
+                .aliases
+                .iter()
+                .map(|(app_id, name)| (fold(app_id, fold_case), name.clone()))
+                .collect(),
+            fold_case,
+            #[cfg(feature = "notifications")]
+            bus: None,
+        };
+        limits.apps = config
+            .daily_limits
+            .iter()
+            .map(|(app_id, limit)| (limits.key(app_id), (app_id.clone(), minutes(*limit))))
+            .collect();
+        Some(limits)
+    }
+
+    /// Returns the app an app id belongs to, which is what usage and limits are kept by.
+    fn key(&self, app_id: &str) -> String {
+        let folded = fold(app_id, self.fold_case);
+        match self.aliases.get(&folded) {
+            Some(name) => fold(name, self.fold_case),
+            None => folded,
+        }
+    }
+
+    /// Adds a recorded session to today's usage and notifies about every limit it reached.
+    pub fn record(&mut self, conn: &Connection, app_id: &str, duration: Duration) {
+        if SystemTime::now() >= self.day_end {
+            // the session is in the database already
+            self.start_day(conn);
+        } else {
+            self.add(app_id, duration);
+        }
+        for message in self.reached() {
+            info!("daily limit reached: {message}");
+            #[cfg(feature = "notifications")]
+            self.notify(&message);
+        }
+    }
+
+    fn start_day(&mut self, conn: &Connection) {
+        self.notified.clear();
+        match today(conn) {
+            Ok((today, day_end)) => {
+                self.today.clear();
+                for (app_id, duration) in today {
+                    self.add(&app_id, duration);
+                }
+                self.day_end = day_end;
+            }
+            Err(e) => {
+                warn!("failed to read today's usage for the daily limits: {e}");
+                self.today.clear();
+                // try again with the next session
+                self.day_end = UNIX_EPOCH;
+            }
+        }
+    }
+
+    fn add(&mut self, app_id: &str, duration: Duration) {
+        *self.today.entry(self.key(app_id)).or_default() += duration;
+    }
+
+    /// Returns the messages of the limits that were reached and not notified about yet.
+    fn reached(&mut self) -> Vec<String> {
+        let mut messages = vec![];
+        for (key, (name, limit)) in &self.apps {
+            let used = self.today.get(key).copied().unwrap_or_default();
+            if used >= *limit && self.notified.insert(Some(key.clone())) {
+                messages.push(fill(&self.app_message, Some(name.as_str()), used, *limit));
+            }
+        }
+        if let Some(limit) = self.total {
+            let used = self.today.values().sum();
+            if used >= limit && self.notified.insert(None) {
+                messages.push(fill(&self.total_message, None, used, limit));
+            }
+        }
+        messages
+    }
+
+    #[cfg(feature = "notifications")]
+    fn notify(&mut self, message: &str) {
+        if self.bus.is_none() {
+            match zbus::blocking::Connection::session() {
+                Ok(bus) => self.bus = Some(bus),
+                Err(e) => {
+                    warn!("failed to connect to the session bus to show a notification: {e}");
+                    return;
+                }
+            }
+        }
+        let Some(bus) = &self.bus else {
+            return;
+        };
+
+        let actions: &[&str] = &[];
+        let hints = HashMap::<&str, zbus::zvariant::Value>::new();
+        if let Err(e) = bus.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "wayland-appusage",
+                0_u32,
+                "",
+                "Daily limit reached",
+                message,
+                actions,
+                hints,
+                -1_i32,
+            ),
+        ) {
+            warn!("failed to show notification: {e}");
+        }
+    }
+}
+
+/// Today's usage by app along with when today ends, days start at local midnight.
+fn today(conn: &Connection) -> Result<(HashMap<String, Duration>, SystemTime), rusqlite::Error> {
+    // the daemon has no time zone handling of its own, sqlite knows the local time though
+    let (start, end): (u64, u64) = conn.query_row(
+        "SELECT CAST(strftime('%s', 'now', 'localtime', 'start of day', 'utc') AS INTEGER),
+            CAST(strftime('%s', 'now', 'localtime', 'start of day', '+1 day', 'utc') AS INTEGER)",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    // sessions from before midnight only count with the part after it
+    let mut stmt = conn.prepare(
+        "SELECT app_name, sum(min(duration, end_time - max(start_time, ?1)))
+            FROM app_usage
+            WHERE end_time > ?1
+            GROUP BY app_name",
+    )?;
+    let today = stmt
+        .query_map([start * 1000], |row| {
+            Ok((row.get::<_, String>(0)?, Duration::from_millis(row.get(1)?)))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok((today, UNIX_EPOCH + Duration::from_secs(end)))
+}
+
+fn fold(app_id: &str, fold_case: bool) -> String {
+    if fold_case {
+        app_id.to_lowercase()
+    } else {
+        app_id.to_string()
+    }
+}
+
+/// Replaces `{app}`, `{used}` and `{limit}` in a message template.
+fn fill(template: &str, app_id: Option<&str>, used: Duration, limit: Duration) -> String {
+    template
+        .replace("{app}", app_id.unwrap_or("all apps"))
+        .replace("{used}", &format_minutes(used))
+        .replace("{limit}", &format_minutes(limit))
+}
+
+/// Formats a duration as hours and minutes, like `1h 5m`.
+fn format_minutes(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, 0) => format!("{hours}h"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_notify_once_a_day() {
+        let mut config = Config::default();
+        config.daily_limits.insert("firefox".to_string(), 60);
+        config.daily_total_limit = Some(90);
+        let mut limits = Limits::from_config(&config).unwrap();
+        let minutes = |minutes| Duration::from_secs(minutes * 60);
+
+        limits.add("firefox", minutes(50));
+        limits.add("foot", minutes(20));
+        assert_eq!(limits.reached(), Vec::<String>::new());
+
+        limits.add("firefox", minutes(15));
+        assert_eq!(
+            limits.reached(),
+            ["You used firefox for 1h 5m today, your limit is 1h."]
+        );
+        limits.add("foot", minutes(10));
+        assert_eq!(
+            limits.reached(),
+            ["You used all apps for 1h 35m today, your limit is 1h 30m."]
+        );
+        limits.add("firefox", minutes(30));
+        assert_eq!(limits.reached(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn limits_go_by_app_across_case_and_aliases() {
+        let mut config = Config::default();
+        config.daily_limits.insert("Firefox".to_string(), 60);
+        config.daily_limits.insert("foot".to_string(), 60);
+        config
+            .aliases
+            .insert("org.mozilla.firefox".to_string(), "firefox".to_string());
+        let mut limits = Limits::from_config(&config).unwrap();
+        let minutes = |minutes| Duration::from_secs(minutes * 60);
+
+        limits.add("org.mozilla.firefox", minutes(40));
+        limits.add("firefox", minutes(10));
+        assert_eq!(limits.reached(), Vec::<String>::new());
+        limits.add("FIREFOX", minutes(10));
+        assert_eq!(
+            limits.reached(),
+            ["You used Firefox for 1h today, your limit is 1h."]
+        );
+
+        config.fold_app_id_case = false;
+        let mut limits = Limits::from_config(&config).unwrap();
+        limits.add("Foot", minutes(60));
+        assert_eq!(limits.reached(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn no_limits_means_nothing_to_track() {
+        assert!(Limits::from_config(&Config::default()).is_none());
+    }
+
+    #[test]
+    fn today_splits_sessions_at_midnight() {
+        let db = appusage_core::Database::open_in_memory().unwrap();
+        let now = SystemTime::now();
+        db.record("firefox", now, Duration::from_secs(60)).unwrap();
+        db.record("firefox", now, Duration::from_secs(30)).unwrap();
+
+        let (today, day_end) = today(db.connection()).unwrap();
+        assert!(day_end > now);
+        // unless the test happens to run right after midnight
+        assert!(today["firefox"] <= Duration::from_secs(90));
+    }
+}
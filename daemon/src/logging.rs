@@ -0,0 +1,131 @@
+//! Sets up `tracing`, which always logs to stdout and optionally also to a file.
+//!
+//! The file is written from a background thread and rotated once a day (in UTC) or once it
+//! grows past [`MAX_LOG_SIZE`], whichever comes first, keeping [`KEPT_LOGS`] old ones next to
+//! it as `<name>.1`, `<name>.2`, ...
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    EnvFilter, filter::LevelFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// A week of them, unless some day logged more than [`MAX_LOG_SIZE`].
+const KEPT_LOGS: usize = 7;
+
+/// Returns the location of the log file inside the XDG state directory.
+pub fn default_path() -> anyhow::Result<PathBuf> {
+    Ok(xdg::BaseDirectories::with_prefix("wayland-appusage")?.place_state_file("daemon.log")?)
+}
+
+/// Installs the global subscriber, `RUST_LOG` overrides `level` if it is set.
+///
+/// When logging to a file, the returned guard has to be kept alive until the daemon exits,
+/// dropping it writes out whatever is still buffered.
+pub fn init(level: LevelFilter, file: Option<&Path>) -> anyhow::Result<Option<WorkerGuard>> {
+    let (file_layer, guard) = match file {
+        Some(path) => {
+            let (writer, guard) = tracing_appender::non_blocking(RotatingFile::open(path)?);
+            (
+                Some(fmt::layer().with_ansi(false).with_writer(writer)),
+                Some(guard),
+            )
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(level.into())
+                .from_env_lossy(),
+        )
+        .with(fmt::layer().without_time())
+        .with(file_layer)
+        .init();
+
+    Ok(guard)
+}
+
+/// Exits with an error after writing out what is still buffered for the log file, which
+/// `process::exit` would skip along with every other destructor.
+pub fn fail(guard: Option<WorkerGuard>) -> ! {
+    drop(guard);
+    std::process::exit(1)
+}
+
+/// Days since the epoch in UTC.
+fn day_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86400
+}
+
+/// A log file that is moved aside once a day and once it gets too big.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// The day the file was last written on, a file left over from an earlier day is moved
+    /// aside before the first write.
+    day: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            day: day_of(metadata.modified().unwrap_or_else(|_| SystemTime::now())),
+            file,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    /// Shifts every old log up by one, dropping the oldest, and starts a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for n in (1..KEPT_LOGS).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let today = day_of(SystemTime::now());
+        if self.size > 0 && (self.size + buf.len() as u64 > MAX_LOG_SIZE || self.day != today) {
+            self.rotate()?;
+        }
+        self.day = today;
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
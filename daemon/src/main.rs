@@ -1,53 +1,425 @@
-use tracing::{error, level_filters::LevelFilter};
-use tracing_subscriber::EnvFilter;
+use std::{ffi::OsString, path::PathBuf, time::Duration};
+
+use calloop::{
+    EventLoop,
+    signals::{Signal, Signals},
+    timer::{TimeoutAction, Timer},
+};
+use calloop_wayland_source::WaylandSource;
+use clap::Parser;
+use display::WaylandDisplay;
+use tracing::{error, info, level_filters::LevelFilter, warn};
 
 mod app;
+mod config;
+mod db;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod display;
+#[cfg(feature = "gnome")]
+mod gnome;
+#[cfg(not(feature = "gnome"))]
+mod gnome {
+    /// Built without GNOME Shell support, so there never is one to poll.
+    pub enum Shell {}
+}
+mod heartbeat;
+mod idle;
+mod ignore;
+#[cfg(feature = "kwin")]
+mod kwin;
+#[cfg(not(feature = "kwin"))]
+mod kwin {
+    /// Built without KWin support, so there never is a script to follow.
+    pub enum KWin {}
+}
+mod limits;
+mod logging;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod reconnect;
+mod sway;
+mod tracker;
+
+/// Records how long each wayland application is focused.
+///
+/// Sending the daemon SIGUSR1 writes all running sessions to the database right away, without
+/// ending them, for example before taking a backup. SIGTERM and SIGINT do the same before
+/// exiting.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Database to record into, instead of the one in `WAYLAND_APPUSAGE_DB` or the config
+    #[arg(long = "db", value_name = "PATH")]
+    db_path: Option<PathBuf>,
+
+    /// Move a corrupt database aside and salvage its readable rows into a fresh one
+    #[arg(long, conflicts_with = "no_write")]
+    recover: bool,
+
+    /// Log every session instead of recording it, to check what would be recorded. The
+    /// database isn't even opened, so it is neither created nor has its schema upgraded
+    #[arg(long)]
+    no_write: bool,
+
+    /// Record the number of open windows over time, for the window count chart
+    #[arg(long)]
+    track_window_count: bool,
+
+    /// Only count an app once it stayed focused this many milliseconds, so quickly passing
+    /// through it while switching windows isn't recorded
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    focus_grace: u64,
+
+    /// Follow focus through sway's IPC instead of wlr-foreign-toplevel-management
+    #[arg(long)]
+    sway: bool,
+
+    /// Wayland display to connect to instead of the one in `WAYLAND_DISPLAY`, the name of a
+    /// socket in `XDG_RUNTIME_DIR` or the path of one
+    #[arg(long, value_name = "NAME")]
+    wayland_display: Option<OsString>,
+
+    /// Address to serve Prometheus metrics on
+    #[cfg(feature = "metrics")]
+    #[arg(long, value_name = "ADDR", default_value = metrics::DEFAULT_ADDR)]
+    metrics_addr: std::net::SocketAddr,
+
+    /// Also write logs to a file, in the state directory unless a path is given
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<Option<PathBuf>>,
+
+    /// Least severe level that gets logged, `RUST_LOG` takes precedence if it is set
+    #[arg(long, default_value_t = LevelFilter::INFO)]
+    log_level: LevelFilter,
+}
 
 fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
-        )
-        .without_time()
-        .init();
+    let args = Args::parse();
 
-    let wayland_connection = wayland_client::Connection::connect_to_env()
-        .expect("Failed to connect to wayland server");
+    let log_file = match args.log_file {
+        Some(Some(path)) => Some(path),
+        Some(None) => match logging::default_path() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("Failed to find log file location: {e:#}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // flushes the log file once main returns
+    let log_guard = match logging::init(args.log_level, log_file.as_deref()) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Failed to open log file: {e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load config: {e:#}");
+            logging::fail(log_guard);
+        }
+    };
+    info!("idle timeout is {}ms", config.idle_ms);
+
+    let display = match WaylandDisplay::resolve(args.wayland_display.clone()) {
+        Ok(display) => display,
+        Err(e) => {
+            error!("Failed to find wayland display: {e:#}");
+            logging::fail(log_guard);
+        }
+    };
+    let wayland_connection = match display.connect() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to connect to wayland server at {display}: {e:#}");
+            logging::fail(log_guard);
+        }
+    };
+    info!("connected to {display}");
 
-    let mut queue = {
+    let (mut queue, registry) = {
         let display = wayland_connection.display();
 
         let queue = wayland_connection.new_event_queue();
         let queue_handle = queue.handle();
 
-        display.get_registry(&queue_handle, ());
+        let registry = display.get_registry(&queue_handle, ());
 
-        queue
+        (queue, registry)
     };
 
-    let mut state = app::AppState::new().expect("Initialization failed");
+    let db_path = match args.db_path.clone().map_or_else(|| config.db_path(), Ok) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Failed to find database location: {e:#}");
+            logging::fail(log_guard);
+        }
+    };
+    let db_connection = if args.no_write {
+        info!("not writing to the database, sessions are only logged");
+        db::open_scratch()
+    } else {
+        info!("recording into {}", db_path.display());
+        db::open(&db_path, args.recover)
+    };
+    let db_connection = match db_connection {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to open database: {e:#}");
+            logging::fail(log_guard);
+        }
+    };
 
-    if let Err(e) = queue.roundtrip(&mut state) {
-        error!("Roundtrip failed: {e}");
+    if let Some(days) = config.retention_days.filter(|_| !args.no_write) {
+        match appusage_core::retention::prune_older_than(
+            &db_connection,
+            appusage_core::retention::cutoff(days),
+            config.archive_pruned,
+        ) {
+            Ok(0) => {}
+            Ok(pruned) => info!("pruned {pruned} sessions older than {days} days"),
+            Err(e) => warn!("Failed to prune old sessions: {e}"),
+        }
+    }
+
+    let run_id = if args.no_write {
+        None
+    } else {
+        match heartbeat::start_run(&db_connection) {
+            Ok(run_id) => Some(run_id),
+            Err(e) => {
+                warn!("Failed to record daemon start, uptime won't be recorded: {e}");
+                None
+            }
+        }
+    };
+
+    let mut state = app::AppState::new(
+        db_connection,
+        args.track_window_count,
+        Duration::from_millis(args.focus_grace),
+        &config,
+    );
+    state.set_no_write(args.no_write);
+
+    #[cfg(feature = "dbus")]
+    match dbus::Service::start(&db_path) {
+        Ok(service) => {
+            info!("serving usage on D-Bus");
+            state.set_dbus(service);
+        }
+        Err(e) => warn!("Failed to start D-Bus service, continuing without it: {e:#}"),
+    }
+
+    #[cfg(feature = "metrics")]
+    match metrics::start(args.metrics_addr, &db_path) {
+        Ok(focused) => {
+            info!("serving metrics on http://{}/metrics", args.metrics_addr);
+            state.set_metrics(focused);
+        }
+        Err(e) => warn!("Failed to start metrics server, continuing without it: {e:#}"),
     }
 
-    if state.toplevel_manager.is_none() {
-        error!("Failed to get toplevel manager, does you compositor implement wlr-foreign-toplevel-management-unstable?");
-        return;
+    // some compositors announce their globals a little later, so this waits for them instead
+    // of giving up on the first roundtrip
+    if let Err(e) = state.wait_for_globals(&mut queue, !args.sway, false) {
+        error!("Roundtrip failed: {e}");
     }
 
-    if let Some(ref idle_notifier) = state.idle_notifier {
-        idle_notifier.get_idle_notification(30_000, &state.seats[0], &queue.handle(), ());
+    // KWin may implement ext-foreign-toplevel-list, which doesn't say which window is focused,
+    // so it is preferred over that
+    #[cfg(feature = "kwin")]
+    let kwin = if args.sway || state.has_wlr_toplevels() {
+        None
     } else {
-        error!("Failed to get idle notifier, does you compositor implement ext-idle-notify?");
-        return;
+        kwin::KWin::connect()
+            .inspect_err(|e| tracing::debug!("not using KWin: {e:#}"))
+            .ok()
+    };
+    #[cfg(not(feature = "kwin"))]
+    let kwin: Option<kwin::KWin> = None;
+
+    // sway implements wlr-foreign-toplevel-management too, it must not report windows as well
+    if !args.sway && kwin.is_none() {
+        state.bind_toplevel_backend(&registry, &queue.handle());
     }
+    let gnome_shell: Option<gnome::Shell> = match state.toplevel_backend {
+        _ if args.sway => {
+            info!("using sway's IPC to track the focused window");
+            None
+        }
+        _ if kwin.is_some() => {
+            info!(
+                "compositor lacks wlr-foreign-toplevel-management, using a KWin script to track the focused window"
+            );
+            if args.track_window_count {
+                warn!(
+                    "The KWin script only reports the focused window, the window count won't be recorded"
+                );
+            }
+            None
+        }
+        Some(app::ToplevelBackend::Wlr(_)) => {
+            info!("using wlr-foreign-toplevel-management to track the focused window");
+            None
+        }
+        Some(app::ToplevelBackend::ExtList(_)) => {
+            warn!(
+                "Compositor only implements ext-foreign-toplevel-list, which doesn't say which window is focused, so no usage will be recorded, only the window count"
+            );
+            None
+        }
+        #[cfg(feature = "gnome")]
+        None => match gnome::Shell::connect(Duration::from_millis(config.idle_ms.into())) {
+            Ok(shell) => {
+                info!(
+                    "compositor has no toplevel protocol, using GNOME Shell over D-Bus to track the focused app"
+                );
+                if args.track_window_count {
+                    warn!(
+                        "GNOME Shell only reports the focused app, the window count won't be recorded"
+                    );
+                }
+                Some(shell)
+            }
+            Err(e) => {
+                error!(
+                    "Failed to get toplevel manager, does you compositor implement wlr-foreign-toplevel-management-unstable or ext-foreign-toplevel-list? Falling back to GNOME Shell didn't work either: {e:#}"
+                );
+                return;
+            }
+        },
+        #[cfg(not(feature = "gnome"))]
+        None => {
+            error!(
+                "Failed to get toplevel manager, does you compositor implement wlr-foreign-toplevel-management-unstable or ext-foreign-toplevel-list?"
+            );
+            return;
+        }
+    };
+
+    // GNOME Shell is asked for the idle time along with the focused app instead
+    if gnome_shell.is_none() {
+        if let Err(e) = state.wait_for_globals(&mut queue, false, true) {
+            error!("Roundtrip failed: {e}");
+        }
+        if state.idle_notifier.is_none() {
+            error!("Failed to get idle notifier, does you compositor implement ext-idle-notify?");
+            return;
+        }
+        // seats that appear later get one as soon as they do
+        state.ensure_idle_notifications(&queue.handle());
+        info!("watching {} seats for idleness", state.idle_seat_count());
+    }
+
+    // everything the daemon reacts to is a source on this loop, so handlers never run
+    // concurrently and can all use the same database connection
+    let mut event_loop: EventLoop<app::AppState> =
+        EventLoop::try_new().expect("Failed to create event loop");
+
+    state.set_loop_handle(event_loop.handle());
+
+    let wayland_source = WaylandSource::new(wayland_connection, queue)
+        .insert(event_loop.handle())
+        .expect("Failed to add wayland connection to event loop");
+    state.set_wayland_source(wayland_source);
+    let reconnect_options = reconnect::Options {
+        display,
+        bind_toplevels: !args.sway && gnome_shell.is_none() && kwin.is_none(),
+        idle_notification: gnome_shell.is_none(),
+    };
+
+    if args.sway {
+        if let Err(e) = sway::start(&event_loop.handle(), &mut state) {
+            error!("Failed to follow sway's window events: {e:#}");
+            return;
+        }
+    }
+
+    #[cfg(feature = "kwin")]
+    if let Some(kwin) = kwin {
+        if let Err(e) = kwin::start(&event_loop.handle(), kwin) {
+            error!("Failed to follow KWin's active window: {e:#}");
+            return;
+        }
+    }
+
+    #[cfg(feature = "gnome")]
+    if let Some(mut shell) = gnome_shell {
+        event_loop
+            .handle()
+            .insert_source(Timer::immediate(), move |_, _, state| {
+                shell.poll(state);
+                TimeoutAction::ToDuration(gnome::POLL_INTERVAL)
+            })
+            .expect("Failed to add GNOME Shell polling to event loop");
+    }
+
+    if let Some(run_id) = run_id {
+        event_loop
+            .handle()
+            .insert_source(
+                Timer::from_duration(heartbeat::HEARTBEAT_INTERVAL),
+                move |_, _, state| {
+                    if let Err(e) = heartbeat::touch_run(state.db_connection(), run_id) {
+                        warn!("heartbeat failed: {e}");
+                    }
+                    TimeoutAction::ToDuration(heartbeat::HEARTBEAT_INTERVAL)
+                },
+            )
+            .expect("Failed to add heartbeat timer to event loop");
+    }
+
+    if let Some(days) = config.vacuum_interval_days.filter(|_| !args.no_write) {
+        let interval = Duration::from_millis(u64::from(days) * appusage_core::DAY_MS);
+        event_loop
+            .handle()
+            .insert_source(Timer::from_duration(interval), move |_, _, state| {
+                // handlers never run concurrently, so no write is halfway through here
+                db::vacuum(state.db_connection());
+                TimeoutAction::ToDuration(interval)
+            })
+            .expect("Failed to add vacuum timer to event loop");
+    }
+
+    // sessions are only written once they end, so they have to be ended before exiting or
+    // the time since the last focus change would be lost
+    let loop_signal = event_loop.get_signal();
+    event_loop
+        .handle()
+        .insert_source(
+            Signals::new(&[Signal::SIGUSR1, Signal::SIGTERM, Signal::SIGINT])
+                .expect("Failed to listen for signals"),
+            move |event, _, state| {
+                let flushed = state.flush_sessions();
+                match event.signal() {
+                    Signal::SIGUSR1 => {
+                        info!("received SIGUSR1, flushed {flushed} active sessions");
+                    }
+                    signal => {
+                        info!("received {signal:?}, flushed {flushed} active sessions, exiting");
+                        if let Some(run_id) = run_id {
+                            if let Err(e) = heartbeat::touch_run(state.db_connection(), run_id) {
+                                warn!("failed to record daemon stop: {e}");
+                            }
+                        }
+                        loop_signal.stop();
+                    }
+                }
+            },
+        )
+        .expect("Failed to add signal handler to event loop");
 
-    loop {
-        queue
-            .blocking_dispatch(&mut state)
-            .expect("Wayland dispatch failed");
+    // the wayland connection is the only source that fails, usually because the compositor
+    // went away, the loop keeps going while it reconnects
+    while let Err(e) = event_loop.run(None, &mut state, |_| {}) {
+        error!("Wayland connection failed: {e}");
+        reconnect::start(&mut state, &event_loop.handle(), reconnect_options.clone());
     }
 }
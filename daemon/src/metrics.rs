@@ -0,0 +1,154 @@
+//! Serves the recorded usage in the Prometheus text format at `/metrics`, for graphing it.
+//!
+//! - `appusage_seconds_total{app_id}`: how long each app was used over all time, including
+//!   pruned sessions so the counters never go down.
+//! - `appusage_focused{app_id}`: 1 for the app that is focused right now.
+//!
+//! The server runs on a thread of its own with its own connection to the database, so slow
+//! scrapes never hold up the event loop. It is plain HTTP without any dependencies, which is
+//! all a scraper needs. Only built with the `metrics` feature.
+
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use appusage_core::Database;
+use tracing::{debug, warn};
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:9464";
+
+/// How long a client may take to send its request or read the response, requests are answered
+/// one at a time, so one that stalls would hold up every other scrape.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The app that is focused right now, shared between the event loop and the server.
+pub type Focused = Arc<Mutex<Option<String>>>;
+
+/// Starts serving on `addr`, returning where the event loop should put the focused app.
+pub fn start(addr: SocketAddr, db_path: &Path) -> anyhow::Result<Focused> {
+    let db = Database::open(db_path).context("failed to open database for metrics")?;
+    let listener = TcpListener::bind(addr).with_context(|| format!("failed to bind {addr}"))?;
+    let focused = Focused::default();
+
+    let server_focused = focused.clone();
+    thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .map_err(anyhow::Error::from)
+                    .and_then(|stream| respond(stream, &db, &server_focused));
+                if let Err(e) = result {
+                    debug!("failed to answer metrics request: {e:#}");
+                }
+            }
+        })?;
+
+    Ok(focused)
+}
+
+fn respond(mut stream: TcpStream, db: &Database, focused: &Focused) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    // the rest of the request doesn't matter
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => {
+            let focused = focused.lock().unwrap().clone();
+            match render(db, focused.as_deref()) {
+                Ok(body) => ("200 OK", body),
+                Err(e) => {
+                    warn!("failed to gather metrics: {e}");
+                    ("500 Internal Server Error", format!("{e}\n"))
+                }
+            }
+        }
+        _ => ("404 Not Found", "only /metrics is served\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+fn render(db: &Database, focused: Option<&str>) -> Result<String, rusqlite::Error> {
+    let mut stmt = db.connection().prepare(
+        "SELECT app_name, sum(duration)
+            FROM (SELECT app_name, duration FROM app_usage
+                UNION ALL SELECT app_name, duration FROM app_usage_archive)
+            GROUP BY app_name
+            ORDER BY app_name",
+    )?;
+    let totals = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut out = String::from(
+        "# HELP appusage_seconds_total How long each app was focused.\n\
+         # TYPE appusage_seconds_total counter\n",
+    );
+    for (app_id, millis) in totals {
+        let _ = writeln!(
+            out,
+            "appusage_seconds_total{{app_id=\"{}\"}} {}",
+            escape_label(&app_id),
+            millis as f64 / 1000.0
+        );
+    }
+
+    out.push_str(
+        "# HELP appusage_focused Whether the app is focused right now.\n\
+         # TYPE appusage_focused gauge\n",
+    );
+    if let Some(app_id) = focused {
+        let _ = writeln!(
+            out,
+            "appusage_focused{{app_id=\"{}\"}} 1",
+            escape_label(app_id)
+        );
+    }
+    Ok(out)
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[test]
+    fn metrics_are_rendered() {
+        let db = Database::open_in_memory().unwrap();
+        db.record("firefox", SystemTime::now(), Duration::from_millis(1500))
+            .unwrap();
+        db.record("we\"ird", SystemTime::now(), Duration::from_secs(2))
+            .unwrap();
+
+        let text = render(&db, Some("firefox")).unwrap();
+        assert!(text.contains("appusage_seconds_total{app_id=\"firefox\"} 1.5\n"));
+        assert!(text.contains("appusage_seconds_total{app_id=\"we\\\"ird\"} 2\n"));
+        assert!(text.contains("appusage_focused{app_id=\"firefox\"} 1\n"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Connects to the compositor again after the wayland connection failed, for example because
+//! the compositor crashed and was restarted.
+//!
+//! Everything happens in timer callbacks on the event loop, so other sources like sway's IPC
+//! keep being handled while the compositor is still starting up.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use calloop::{
+    LoopHandle,
+    timer::{TimeoutAction, Timer},
+};
+use calloop_wayland_source::WaylandSource;
+use tracing::{debug, error, info, warn};
+use wayland_client::{Connection, EventQueue, protocol::wl_registry::WlRegistry};
+
+use crate::{
+    app::{AppState, GLOBALS_RETRY_INTERVAL, GLOBALS_TIMEOUT},
+    display::WaylandDisplay,
+};
+
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// What to set up on a new connection, the same as on the first one.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The compositor the first connection went to.
+    pub display: WaylandDisplay,
+    /// Whether to bind a toplevel global, not when another backend reports focus.
+    pub bind_toplevels: bool,
+    /// Whether to watch for idleness through ext-idle-notify.
+    pub idle_notification: bool,
+}
+
+/// Forgets the failed connection and keeps trying to connect again, waiting twice as long
+/// after every failed attempt.
+pub fn start(state: &mut AppState, handle: &LoopHandle<'static, AppState>, options: Options) {
+    state.forget_wayland();
+
+    let mut delay = INITIAL_DELAY;
+    let mut attempt = None;
+    let timer_handle = handle.clone();
+    if let Err(e) = handle.insert_source(Timer::from_duration(delay), move |_, _, state| {
+        match step(state, &timer_handle, &options, &mut attempt) {
+            Ok(true) => {
+                info!("reconnected to the compositor");
+                TimeoutAction::Drop
+            }
+            // the timer comes back to this attempt instead of sleeping on the event loop
+            Ok(false) => TimeoutAction::ToDuration(GLOBALS_RETRY_INTERVAL),
+            Err(e) => {
+                delay = (delay * 2).min(MAX_DELAY);
+                warn!("reconnecting failed, trying again in {delay:?}: {e:#}");
+                TimeoutAction::ToDuration(delay)
+            }
+        }
+    }) {
+        error!("failed to schedule reconnecting, nothing will be recorded anymore: {e}");
+    }
+}
+
+/// A new connection waiting for the compositor to announce its globals.
+struct Attempt {
+    conn: Connection,
+    queue: EventQueue<AppState>,
+    registry: WlRegistry,
+    /// When to stop waiting and go on with whatever globals there are.
+    deadline: Instant,
+}
+
+/// Connects if there is no attempt yet and does a roundtrip, returning whether the connection
+/// is back, or false to be called again in a bit while globals are still missing.
+///
+/// A failed attempt is dropped, so the next call starts over with a new connection.
+fn step(
+    state: &mut AppState,
+    handle: &LoopHandle<'static, AppState>,
+    options: &Options,
+    attempt: &mut Option<Attempt>,
+) -> anyhow::Result<bool> {
+    let mut current = match attempt.take() {
+        Some(current) => current,
+        None => {
+            info!("reconnecting to the compositor");
+            let conn = options.display.connect()?;
+            let queue = conn.new_event_queue();
+            let registry = conn.display().get_registry(&queue.handle(), ());
+            Attempt {
+                conn,
+                queue,
+                registry,
+                deadline: Instant::now() + GLOBALS_TIMEOUT,
+            }
+        }
+    };
+    current.queue.roundtrip(state)?;
+    if let Some(missing) = state.missing_global(options.bind_toplevels, options.idle_notification) {
+        if Instant::now() < current.deadline {
+            *attempt = Some(current);
+            return Ok(false);
+        }
+        debug!("compositor announced no {missing} after {GLOBALS_TIMEOUT:?}");
+    }
+
+    finish(state, handle, options, current)?;
+    Ok(true)
+}
+
+/// Binds the globals on the new connection and adds it to the event loop.
+fn finish(
+    state: &mut AppState,
+    handle: &LoopHandle<'static, AppState>,
+    options: &Options,
+    Attempt {
+        conn,
+        queue,
+        registry,
+        ..
+    }: Attempt,
+) -> anyhow::Result<()> {
+    let qhandle = queue.handle();
+    if options.bind_toplevels {
+        state.bind_toplevel_backend(&registry, &qhandle);
+        if state.toplevel_backend.is_none() {
+            warn!("compositor came back without a toplevel protocol, no usage will be recorded");
+        }
+    }
+    if options.idle_notification {
+        state.ensure_idle_notifications(&qhandle);
+    }
+
+    let token = WaylandSource::new(conn, queue)
+        .insert(handle.clone())
+        .map_err(|e| e.error)
+        .context("failed to add wayland connection to event loop")?;
+    state.set_wayland_source(token);
+    Ok(())
+}
@@ -0,0 +1,434 @@
+//! A small client for sway's IPC protocol, see sway-ipc(7).
+//!
+//! Every message is framed the same way in both directions: the magic string `i3-ipc`, the
+//! length of the payload and the message type (both 32 bit, in native byte order) and then
+//! the payload, which is JSON for everything this daemon uses.
+//!
+//! [`start`] uses it as a backend that follows focus through sway's window events instead of
+//! wlr-foreign-toplevel-management.
+
+use std::{
+    collections::HashSet,
+    env,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use calloop::{
+    Interest, LoopHandle, Mode, PostAction,
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+};
+use serde::Deserialize;
+use tracing::{debug, info, trace, warn};
+
+use crate::app::{AppState, WindowKey};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+/// Payloads bigger than this are treated as a broken stream rather than allocated, even the
+/// tree of a busy session is nowhere near it.
+const MAX_PAYLOAD_LEN: u32 = 64 * 1024 * 1024;
+
+/// Message types, a reply has the type of its request and events have the highest bit set.
+pub mod kind {
+    pub const SUBSCRIBE: u32 = 2;
+    pub const GET_TREE: u32 = 4;
+
+    const EVENT: u32 = 1 << 31;
+    pub const WORKSPACE_EVENT: u32 = EVENT;
+    pub const WINDOW_EVENT: u32 = EVENT | 3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: u32,
+    pub payload: Vec<u8>,
+}
+
+pub struct Connection {
+    stream: UnixStream,
+}
+
+impl Connection {
+    /// Connects to the sway instance given by `SWAYSOCK`.
+    pub fn connect() -> anyhow::Result<Self> {
+        let path = env::var_os("SWAYSOCK").context("SWAYSOCK isn't set, is sway running?")?;
+        Self::connect_to(Path::new(&path))
+    }
+
+    pub fn connect_to(path: &Path) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("failed to connect to sway at {}", path.display()))?;
+        Ok(Self { stream })
+    }
+
+    /// Sends a request, its reply has to be read with [`Connection::read_message`].
+    pub fn send_message(&mut self, kind: u32, payload: &[u8]) -> anyhow::Result<()> {
+        write_message(&mut self.stream, kind, payload)
+    }
+
+    /// Blocks until the next reply or event arrives.
+    pub fn read_message(&mut self) -> anyhow::Result<Message> {
+        read_message(&mut self.stream)
+    }
+
+    /// Sends a request and waits for its reply, which must not be preceded by any events.
+    fn request<T: for<'de> Deserialize<'de>>(
+        &mut self,
+        kind: u32,
+        payload: &[u8],
+    ) -> anyhow::Result<T> {
+        self.send_message(kind, payload)?;
+        let reply = self.read_message()?;
+        if reply.kind != kind {
+            bail!("expected a reply of type {kind} but got {}", reply.kind);
+        }
+        Ok(serde_json::from_slice(&reply.payload)?)
+    }
+}
+
+/// How long to wait before trying to connect again after sway went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A node of sway's layout tree, only what's needed to find windows.
+#[derive(Debug, Deserialize)]
+struct Node {
+    id: i64,
+    #[serde(default)]
+    focused: bool,
+    /// Set for wayland clients.
+    app_id: Option<String>,
+    /// Set for xwayland clients instead of an app id.
+    window_properties: Option<WindowProperties>,
+    /// The title, for windows.
+    name: Option<String>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+impl Node {
+    fn is_window(&self) -> bool {
+        self.app_id.is_some() || self.window_properties.is_some()
+    }
+
+    fn app_id(&self) -> Option<String> {
+        self.app_id
+            .clone()
+            .or_else(|| self.window_properties.as_ref()?.class.clone())
+    }
+
+    /// Every window in this part of the tree.
+    fn windows(&self) -> Vec<&Node> {
+        if self.is_window() {
+            return vec![self];
+        }
+        self.nodes
+            .iter()
+            .chain(&self.floating_nodes)
+            .flat_map(Node::windows)
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowEvent {
+    change: String,
+    container: Node,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceEvent {
+    change: String,
+    current: Option<Node>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandReply {
+    success: bool,
+}
+
+/// The windows sway told about, so they can be ended if the connection drops.
+#[derive(Debug, Default)]
+struct Backend {
+    windows: HashSet<i64>,
+    focused: Option<i64>,
+}
+
+impl Backend {
+    fn add(&mut self, state: &mut AppState, window: &Node) {
+        self.windows.insert(window.id);
+        state.add_window(WindowKey::Sway(window.id), window.app_id());
+        if let Some(title) = &window.name {
+            state.set_title(WindowKey::Sway(window.id), title.clone());
+        }
+    }
+
+    fn focus(&mut self, state: &mut AppState, window: Option<&Node>) {
+        let id = window.map(|x| x.id);
+        if self.focused == id {
+            return;
+        }
+        if let Some(previous) = self.focused.take() {
+            state.set_focus(WindowKey::Sway(previous), false);
+        }
+        if let Some(window) = window {
+            self.add(state, window);
+            state.set_focus(WindowKey::Sway(window.id), true);
+        }
+        self.focused = id;
+    }
+
+    fn close(&mut self, state: &mut AppState, id: i64) {
+        self.windows.remove(&id);
+        if self.focused == Some(id) {
+            self.focused = None;
+        }
+        state.close_window(&WindowKey::Sway(id));
+        state.sample_window_count();
+    }
+
+    /// Picks up every window that exists right now.
+    fn sync(&mut self, state: &mut AppState, tree: &Node) {
+        let windows = tree.windows();
+        for window in &windows {
+            self.add(state, window);
+        }
+        state.sample_window_count();
+        self.focus(state, windows.into_iter().find(|x| x.focused));
+    }
+
+    fn handle(&mut self, state: &mut AppState, message: Message) -> anyhow::Result<()> {
+        match message.kind {
+            kind::WINDOW_EVENT => {
+                let event: WindowEvent = serde_json::from_slice(&message.payload)?;
+                trace!(
+                    "sway window event: {} {:?}",
+                    event.change,
+                    event.container.app_id()
+                );
+                match event.change.as_str() {
+                    "new" => {
+                        self.add(state, &event.container);
+                        state.sample_window_count();
+                    }
+                    "focus" => self.focus(state, Some(&event.container)),
+                    "title" => self.add(state, &event.container),
+                    "close" => self.close(state, event.container.id),
+                    _ => (),
+                }
+            }
+            // switching to an empty workspace focuses no window, and sway doesn't send a
+            // window event for that
+            kind::WORKSPACE_EVENT => {
+                let event: WorkspaceEvent = serde_json::from_slice(&message.payload)?;
+                if event.change == "focus" && event.current.is_some_and(|x| x.windows().is_empty())
+                {
+                    self.focus(state, None);
+                }
+            }
+            kind => debug!("ignoring sway message of type {kind}"),
+        }
+        Ok(())
+    }
+
+    /// Ends every window, since nothing is known about them anymore.
+    fn disconnected(&mut self, state: &mut AppState) {
+        self.focused = None;
+        for id in self.windows.drain() {
+            state.close_window(&WindowKey::Sway(id));
+        }
+        state.sample_window_count();
+    }
+}
+
+/// Connects to sway and follows its window events on the event loop, reconnecting whenever
+/// the connection drops.
+pub fn start(handle: &LoopHandle<'static, AppState>, state: &mut AppState) -> anyhow::Result<()> {
+    let (conn, backend) = connect(state)?;
+    insert(handle, conn, backend)
+}
+
+fn connect(state: &mut AppState) -> anyhow::Result<(Connection, Backend)> {
+    let mut conn = Connection::connect()?;
+    // the tree comes first, once subscribed events could arrive before the reply to it
+    let tree: Node = conn.request(kind::GET_TREE, b"")?;
+    let reply: CommandReply = conn.request(kind::SUBSCRIBE, br#"["window","workspace"]"#)?;
+    if !reply.success {
+        bail!("sway refused the subscription to window events");
+    }
+
+    let mut backend = Backend::default();
+    backend.sync(state, &tree);
+    Ok((conn, backend))
+}
+
+fn insert(
+    handle: &LoopHandle<'static, AppState>,
+    conn: Connection,
+    mut backend: Backend,
+) -> anyhow::Result<()> {
+    let reconnect_handle = handle.clone();
+    handle
+        .insert_source(
+            Generic::new(conn.stream, Interest::READ, Mode::Level),
+            move |_, stream, state| {
+                let message = match read_message(&mut stream.get_ref()) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("lost connection to sway: {e:#}");
+                        backend.disconnected(state);
+                        reconnect(&reconnect_handle);
+                        return Ok(PostAction::Remove);
+                    }
+                };
+                if let Err(e) = backend.handle(state, message) {
+                    warn!("failed to handle sway event: {e:#}");
+                }
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|e| anyhow::anyhow!("failed to add sway connection to event loop: {e}"))?;
+    Ok(())
+}
+
+fn reconnect(handle: &LoopHandle<'static, AppState>) {
+    let timer_handle = handle.clone();
+    if let Err(e) = handle.insert_source(
+        Timer::from_duration(RECONNECT_DELAY),
+        move |_, _, state| match connect(state) {
+            Ok((conn, backend)) => {
+                info!("reconnected to sway");
+                if let Err(e) = insert(&timer_handle, conn, backend) {
+                    warn!("{e:#}");
+                }
+                TimeoutAction::Drop
+            }
+            Err(e) => {
+                debug!("reconnecting to sway failed: {e:#}");
+                TimeoutAction::ToDuration(RECONNECT_DELAY)
+            }
+        },
+    ) {
+        warn!("failed to schedule reconnecting to sway, focus won't be tracked anymore: {e}");
+    }
+}
+
+fn write_message(writer: &mut impl Write, kind: u32, payload: &[u8]) -> anyhow::Result<()> {
+    let len = u32::try_from(payload.len()).context("payload too long")?;
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&len.to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    writer.write_all(&message)?;
+    Ok(())
+}
+
+fn read_message(reader: &mut impl Read) -> anyhow::Result<Message> {
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+
+    let (magic, rest) = header.split_at(MAGIC.len());
+    if magic != MAGIC {
+        bail!("message doesn't start with {MAGIC:?} but {magic:?}");
+    }
+    let len = u32::from_ne_bytes(rest[..4].try_into().unwrap());
+    let kind = u32::from_ne_bytes(rest[4..].try_into().unwrap());
+    if len > MAX_PAYLOAD_LEN {
+        bail!("message claims to have a payload of {len} bytes");
+    }
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(Message { kind, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let mut buf = vec![];
+        write_message(&mut buf, kind::SUBSCRIBE, br#"["window"]"#).unwrap();
+        write_message(&mut buf, kind::GET_TREE, b"").unwrap();
+
+        let mut reader = Cursor::new(buf);
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Message {
+                kind: kind::SUBSCRIBE,
+                payload: br#"["window"]"#.to_vec(),
+            }
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Message {
+                kind: kind::GET_TREE,
+                payload: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn windows_are_found_in_tree() {
+        let tree: Node = serde_json::from_str(
+            r#"{
+                "id": 1,
+                "type": "root",
+                "nodes": [{
+                    "id": 2,
+                    "type": "workspace",
+                    "nodes": [
+                        {"id": 3, "type": "con", "app_id": "foot", "focused": true},
+                        {"id": 4, "type": "con", "nodes": [
+                            {"id": 5, "type": "con", "app_id": null,
+                             "window_properties": {"class": "Steam"}}
+                        ]}
+                    ],
+                    "floating_nodes": [{"id": 6, "type": "floating_con", "app_id": "mpv"}]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let windows = tree.windows();
+        assert_eq!(
+            windows
+                .iter()
+                .map(|x| (x.id, x.app_id(), x.focused))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, Some("foot".to_string()), true),
+                (5, Some("Steam".to_string()), false),
+                (6, Some("mpv".to_string()), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_messages_are_errors() {
+        let mut buf = vec![];
+        write_message(&mut buf, kind::GET_TREE, b"{}").unwrap();
+
+        // cut off payload
+        assert!(read_message(&mut Cursor::new(&buf[..buf.len() - 1])).is_err());
+
+        buf[0] = b'x';
+        assert!(read_message(&mut Cursor::new(&buf)).is_err());
+    }
+}
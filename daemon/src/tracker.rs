@@ -0,0 +1,767 @@
+//! Turns focus and idle transitions into finished usage sessions.
+//!
+//! This knows nothing about wayland, toplevels are identified by whatever key the caller
+//! uses, and the current time is passed in so the logic can be tested without waiting.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    time::{Duration, Instant, SystemTime},
+};
+
+use appusage_core::config::UnidentifiedApps;
+use tracing::{debug, warn};
+
+/// A stretch of time an app was focused, ready to be recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub app_id: String,
+    /// The title the window had when the session ended.
+    pub title: Option<String>,
+    /// The output the window was on when the session ended.
+    pub output: Option<String>,
+    /// When the session started by the wall clock.
+    pub start: SystemTime,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ToplevelInfo {
+    app_id: Option<String>,
+    title: Option<String>,
+    /// Outputs the toplevel is on, in the order it entered them.
+    outputs: Vec<String>,
+    /// When the toplevel was focused, unset while it isn't or while the user is idle.
+    ///
+    /// The wall clock time is kept next to the monotonic one, the session is timed with the
+    /// latter but only the former means anything outside of this process.
+    focused_since: Option<(Instant, SystemTime)>,
+    active: bool,
+    /// When the toplevel became active, it only starts being counted once it stayed active
+    /// for the grace period.
+    activated_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct Tracker<K> {
+    toplevels: HashMap<K, ToplevelInfo>,
+    /// How long a toplevel has to stay focused before it is counted.
+    grace: Duration,
+    idle: bool,
+    /// How long the user has to stay idle before the idle time stops counting, shorter idle
+    /// stretches leave sessions running as if there was input all along.
+    idle_grace: Duration,
+    /// When the user went idle, while it is still within `idle_grace`.
+    idle_since: Option<Instant>,
+    /// Whether a title change ends the running session and starts a new one.
+    split_on_title: bool,
+    /// What windows without an app id are recorded as.
+    unidentified: Unidentified,
+    /// Where sessions get their wall clock start from, replaced in tests.
+    wall_clock: fn() -> SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct Unidentified {
+    apps: UnidentifiedApps,
+    /// Whether the user was told about a window without an app id yet, only the first one
+    /// is worth a warning.
+    warned: bool,
+}
+
+impl Unidentified {
+    fn app_id(&mut self, title: Option<&str>) -> Option<String> {
+        let app_id = self.apps.app_id(title);
+        if !self.warned {
+            self.warned = true;
+            match &app_id {
+                Some(app_id) => {
+                    warn!("a focused window never sent an app id, recording it as {app_id}")
+                }
+                None => warn!("a focused window never sent an app id, its usage isn't recorded"),
+            }
+        }
+        app_id
+    }
+}
+
+impl<K> Default for Tracker<K> {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+impl<K> Tracker<K> {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            toplevels: HashMap::new(),
+            grace,
+            idle: false,
+            idle_grace: Duration::ZERO,
+            idle_since: None,
+            split_on_title: false,
+            unidentified: Unidentified::default(),
+            wall_clock: SystemTime::now,
+        }
+    }
+
+    /// Makes title changes end the running session, so each title gets its own sessions.
+    pub fn split_on_title(mut self, split: bool) -> Self {
+        self.split_on_title = split;
+        self
+    }
+
+    /// Sets what windows that never send an app id are recorded as.
+    pub fn unidentified_apps(mut self, apps: UnidentifiedApps) -> Self {
+        self.unidentified.apps = apps;
+        self
+    }
+
+    /// Keeps sessions going through idle stretches shorter than `grace`, instead of ending
+    /// them as soon as the user goes idle.
+    pub fn idle_grace(mut self, grace: Duration) -> Self {
+        self.idle_grace = grace;
+        self
+    }
+
+    #[cfg(test)]
+    fn with_wall_clock(mut self, wall_clock: fn() -> SystemTime) -> Self {
+        self.wall_clock = wall_clock;
+        self
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+}
+
+impl<K: Hash + Eq + Debug> Tracker<K> {
+    /// Number of toplevels currently open.
+    pub fn len(&self) -> usize {
+        self.toplevels.len()
+    }
+
+    /// Starts tracking the toplevel if it isn't already.
+    pub fn add(&mut self, key: K) {
+        self.toplevels.entry(key).or_default();
+    }
+
+    pub fn set_app_id(&mut self, key: K, app_id: String) {
+        self.toplevels.entry(key).or_default().app_id = Some(app_id);
+    }
+
+    /// Updates the toplevel's title, returning the session that ended if titles split
+    /// sessions and it was being counted.
+    pub fn set_title(&mut self, key: K, title: String, now: Instant) -> Option<Session> {
+        let wall = (self.wall_clock)();
+        let item = self.toplevels.entry(key).or_default();
+        if item.title.as_ref() == Some(&title) {
+            return None;
+        }
+
+        // a window getting its first title isn't a change worth splitting on
+        let session = if self.split_on_title && item.title.is_some() && item.focused_since.is_some()
+        {
+            debug!("title changed, splitting session of: {:?}", item.app_id);
+            let session = finish(item, now, &mut self.unidentified);
+            item.focused_since = Some((now, wall));
+            session
+        } else {
+            None
+        };
+        item.title = Some(title);
+        session
+    }
+
+    /// Notes that the toplevel is now (also) shown on the output.
+    pub fn enter_output(&mut self, key: K, output: String) {
+        let item = self.toplevels.entry(key).or_default();
+        item.outputs.retain(|x| *x != output);
+        item.outputs.push(output);
+    }
+
+    pub fn leave_output(&mut self, key: K, output: &str) {
+        self.toplevels
+            .entry(key)
+            .or_default()
+            .outputs
+            .retain(|x| x != output);
+    }
+
+    #[cfg(any(feature = "dbus", feature = "metrics"))]
+    pub fn app_id(&self, key: &K) -> Option<&str> {
+        self.toplevels.get(key)?.app_id.as_deref()
+    }
+
+    /// Returns the app id of the active toplevel, if there is one and its app id is known.
+    #[cfg(any(feature = "dbus", feature = "metrics"))]
+    pub fn active_app_id(&self) -> Option<&str> {
+        self.toplevels
+            .values()
+            .find(|item| item.active)?
+            .app_id
+            .as_deref()
+    }
+
+    pub fn is_active(&self, key: &K) -> bool {
+        self.toplevels.get(key).is_some_and(|item| item.active)
+    }
+
+    /// Returns when the toplevel became active, if it is.
+    pub fn activated_at(&self, key: &K) -> Option<Instant> {
+        self.toplevels.get(key)?.activated_at
+    }
+
+    /// Updates whether the toplevel is focused, returning the session that ended if it lost
+    /// focus.
+    ///
+    /// With a grace period, a toplevel that became active is only counted once
+    /// [`Tracker::confirm_focus`] is called after the grace period passed.
+    pub fn set_active(&mut self, key: K, active: bool, now: Instant) -> Option<Session> {
+        let end = self.session_end(now);
+        let item = self.toplevels.entry(key).or_default();
+        let was_active = item.active;
+        item.active = active;
+
+        if was_active && !active {
+            debug!("became inactive: {:?}", item.app_id);
+            item.activated_at = None;
+            return finish(item, end, &mut self.unidentified);
+        }
+
+        if active && !was_active {
+            debug!("became active: {:?}", item.app_id);
+            item.activated_at = Some(now);
+            if self.grace.is_zero() {
+                item.focused_since = Some((now, (self.wall_clock)()));
+            }
+        }
+
+        None
+    }
+
+    /// Starts counting the toplevel once its grace period is over, as long as it stayed
+    /// active since `activated_at` and the user isn't idle.
+    pub fn confirm_focus(&mut self, key: &K, activated_at: Instant, now: Instant) {
+        if self.idle {
+            return;
+        }
+        if let Some(item) = self.toplevels.get_mut(key).filter(|item| {
+            item.active && item.activated_at == Some(activated_at) && item.focused_since.is_none()
+        }) {
+            debug!("focused long enough to count: {:?}", item.app_id);
+            item.focused_since = Some((now, (self.wall_clock)()));
+        }
+    }
+
+    /// Stops tracking the toplevel, returning its session if it was still being counted.
+    ///
+    /// This doesn't look at whether the toplevel was last seen active, a window can be
+    /// closed while focused without ever being deactivated.
+    pub fn close(&mut self, key: &K, now: Instant) -> Option<Session> {
+        let end = self.session_end(now);
+        let mut item = self.toplevels.remove(key)?;
+        if item.focused_since.is_some() {
+            debug!("focused toplevel closed: {:?}", item.app_id);
+        }
+        finish(&mut item, end, &mut self.unidentified)
+    }
+
+    /// Stops tracking every toplevel whose key matches, returning the sessions of those that
+    /// were still being counted.
+    pub fn close_matching(&mut self, now: Instant, f: impl Fn(&K) -> bool) -> Vec<Session> {
+        let end = self.session_end(now);
+        let mut sessions = vec![];
+        self.toplevels.retain(|key, item| {
+            if !f(key) {
+                return true;
+            }
+            sessions.extend(finish(item, end, &mut self.unidentified));
+            false
+        });
+        sessions
+    }
+
+    /// Ends the sessions of every toplevel that is being counted, since the user went idle.
+    ///
+    /// With an idle grace period the sessions keep going for now, [`Tracker::resumed`] decides
+    /// whether the idle time counts once the user is back.
+    pub fn idled(&mut self, now: Instant) -> Vec<Session> {
+        self.idle = true;
+        if !self.idle_grace.is_zero() {
+            self.idle_since = Some(now);
+            return vec![];
+        }
+        self.toplevels
+            .values_mut()
+            .filter(|item| item.focused_since.is_some())
+            .filter_map(|item| {
+                debug!("idling, ending session of: {:?}", item.app_id);
+                finish(item, now, &mut self.unidentified)
+            })
+            .collect()
+    }
+
+    /// Ends the session of every toplevel that is being counted and immediately starts a new
+    /// one, so everything up to now is recorded without anything being lost.
+    pub fn flush(&mut self, now: Instant) -> Vec<Session> {
+        let end = self.session_end(now);
+        let wall = (self.wall_clock)();
+        self.toplevels
+            .values_mut()
+            .filter(|item| item.focused_since.is_some())
+            .filter_map(|item| {
+                let session = finish(item, end, &mut self.unidentified);
+                item.focused_since = Some((now, wall));
+                session
+            })
+            .collect()
+    }
+
+    /// Starts counting every active toplevel that isn't being counted already, after the
+    /// user came back from being idle.
+    ///
+    /// If the user was idle for longer than the idle grace period, the sessions that were
+    /// kept going are ended when the user went idle and started again now, which is what is
+    /// returned. Shorter idle stretches leave them running.
+    ///
+    /// Toplevels still within their grace period are left for [`Tracker::confirm_focus`].
+    pub fn resumed(&mut self, now: Instant) -> Vec<Session> {
+        let end = self.session_end(now);
+        self.idle = false;
+        let grace = self.grace;
+        let wall = (self.wall_clock)();
+
+        let mut sessions = vec![];
+        if let Some(idle_since) = self.idle_since.take().filter(|_| end < now) {
+            debug!(
+                "idle for {:?}, ending the sessions at its start",
+                now.duration_since(idle_since)
+            );
+            for item in self.toplevels.values_mut() {
+                // anything flushed while idle has nothing left worth recording
+                match item.focused_since {
+                    Some((focused_at, _)) if focused_at < end => {
+                        sessions.extend(finish(item, end, &mut self.unidentified))
+                    }
+                    Some(_) => {}
+                    None => continue,
+                }
+                item.focused_since = Some((now, wall));
+            }
+        }
+
+        for item in self.toplevels.values_mut().filter(|item| {
+            item.focused_since.is_none()
+                && item
+                    .activated_at
+                    .is_some_and(|activated_at| now.duration_since(activated_at) >= grace)
+        }) {
+            item.focused_since = Some((now, wall));
+        }
+        sessions
+    }
+
+    /// When sessions ending now actually end, which is when the user went idle if that was
+    /// longer ago than the idle grace period.
+    fn session_end(&self, now: Instant) -> Instant {
+        match self.idle_since {
+            Some(idle_since) if now.duration_since(idle_since) >= self.idle_grace => idle_since,
+            _ => now,
+        }
+    }
+}
+
+/// Ends the toplevel's session if it is being counted, one without an app id is recorded as
+/// whatever `unidentified` says.
+fn finish(
+    item: &mut ToplevelInfo,
+    now: Instant,
+    unidentified: &mut Unidentified,
+) -> Option<Session> {
+    let (focused_since, start) = item.focused_since.take()?;
+    let app_id = match &item.app_id {
+        Some(app_id) => app_id.clone(),
+        None => unidentified.app_id(item.title.as_deref())?,
+    };
+    Some(Session {
+        app_id,
+        title: item.title.clone(),
+        // a toplevel spanning several outputs counts for the one it entered last
+        output: item.outputs.last().cloned(),
+        start,
+        duration: now.duration_since(focused_since),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn epoch() -> SystemTime {
+        UNIX_EPOCH
+    }
+
+    #[test]
+    fn closing_while_focused_records_session() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        assert_eq!(tracker.set_active(1, true, t0), None);
+
+        assert_eq!(
+            tracker.close(&1, t0 + Duration::from_secs(60)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(tracker.len(), 0);
+    }
+
+    #[test]
+    fn idle_time_is_not_counted() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+
+        assert_eq!(
+            tracker.idled(t0 + Duration::from_secs(60)),
+            vec![Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(60),
+            }]
+        );
+
+        tracker.resumed(t0 + Duration::from_secs(300));
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(360)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(60),
+            })
+        );
+    }
+
+    #[test]
+    fn short_idle_keeps_sessions_going() {
+        let mut tracker = Tracker::default()
+            .with_wall_clock(epoch)
+            .idle_grace(Duration::from_secs(120));
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+
+        assert_eq!(tracker.idled(t0 + Duration::from_secs(60)), vec![]);
+        assert_eq!(tracker.resumed(t0 + Duration::from_secs(120)), vec![]);
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(180)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(180),
+            })
+        );
+    }
+
+    #[test]
+    fn long_idle_ends_sessions_when_it_began() {
+        let mut tracker = Tracker::default()
+            .with_wall_clock(epoch)
+            .idle_grace(Duration::from_secs(120));
+        let t0 = Instant::now();
+        let firefox = |secs| Session {
+            app_id: "firefox".to_string(),
+            title: None,
+            output: None,
+            start: UNIX_EPOCH,
+            duration: Duration::from_secs(secs),
+        };
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        tracker.set_app_id(2, "foot".to_string());
+        tracker.set_active(2, true, t0);
+
+        tracker.idled(t0 + Duration::from_secs(60));
+        // closed after the grace period ran out, while still idle
+        assert_eq!(
+            tracker.close(&2, t0 + Duration::from_secs(500)),
+            Some(Session {
+                app_id: "foot".to_string(),
+                ..firefox(60)
+            })
+        );
+        assert_eq!(
+            tracker.resumed(t0 + Duration::from_secs(600)),
+            vec![firefox(60)]
+        );
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(630)),
+            Some(firefox(30))
+        );
+    }
+
+    #[test]
+    fn fly_by_focus_is_not_counted() {
+        let mut tracker = Tracker::new(Duration::from_secs(2)).with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(1)),
+            None
+        );
+
+        // the timer of the earlier activation firing late changes nothing
+        tracker.confirm_focus(&1, t0, t0 + Duration::from_secs(2));
+        assert_eq!(tracker.close(&1, t0 + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn grace_period_is_not_counted() {
+        let mut tracker = Tracker::new(Duration::from_secs(2)).with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        tracker.confirm_focus(&1, t0, t0 + Duration::from_secs(2));
+
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(10)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(8),
+            })
+        );
+    }
+
+    #[test]
+    fn flushing_keeps_counting() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+
+        assert_eq!(
+            tracker.flush(t0 + Duration::from_secs(30)),
+            vec![Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(30),
+            }]
+        );
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(40)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(10),
+            })
+        );
+    }
+
+    #[test]
+    fn title_is_the_one_at_the_end() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_title(1, "Inbox".to_string(), t0);
+        tracker.set_active(1, true, t0);
+        assert_eq!(
+            tracker.set_title(1, "News".to_string(), t0 + Duration::from_secs(10)),
+            None
+        );
+
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(30)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: Some("News".to_string()),
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(30),
+            })
+        );
+    }
+
+    #[test]
+    fn title_changes_split_sessions() {
+        let mut tracker = Tracker::default()
+            .with_wall_clock(epoch)
+            .split_on_title(true);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        assert_eq!(tracker.set_title(1, "Inbox".to_string(), t0), None);
+
+        // the same title again is no change
+        assert_eq!(
+            tracker.set_title(1, "Inbox".to_string(), t0 + Duration::from_secs(5)),
+            None
+        );
+        assert_eq!(
+            tracker.set_title(1, "News".to_string(), t0 + Duration::from_secs(10)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: Some("Inbox".to_string()),
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(10),
+            })
+        );
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(30)),
+            Some(Session {
+                app_id: "firefox".to_string(),
+                title: Some("News".to_string()),
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(20),
+            })
+        );
+
+        // unfocused windows have nothing to split
+        assert_eq!(
+            tracker.set_title(1, "Inbox".to_string(), t0 + Duration::from_secs(40)),
+            None
+        );
+    }
+
+    #[test]
+    fn output_is_the_one_entered_last() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.enter_output(1, "DP-1".to_string());
+        tracker.set_active(1, true, t0);
+        tracker.enter_output(1, "HDMI-A-1".to_string());
+        let output = |session: Option<Session>| session.unwrap().output;
+        assert_eq!(
+            output(tracker.set_active(1, false, t0 + Duration::from_secs(10))),
+            Some("HDMI-A-1".to_string())
+        );
+
+        // moved over completely
+        tracker.set_active(1, true, t0);
+        tracker.leave_output(1, "HDMI-A-1");
+        assert_eq!(
+            output(tracker.set_active(1, false, t0 + Duration::from_secs(10))),
+            Some("DP-1".to_string())
+        );
+
+        tracker.set_active(1, true, t0);
+        tracker.leave_output(1, "DP-1");
+        assert_eq!(
+            output(tracker.set_active(1, false, t0 + Duration::from_secs(10))),
+            None
+        );
+    }
+
+    #[test]
+    fn start_comes_from_the_wall_clock() {
+        fn later() -> SystemTime {
+            UNIX_EPOCH + Duration::from_secs(1_000)
+        }
+
+        let mut tracker = Tracker::default().with_wall_clock(later);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        let session = tracker.flush(t0 + Duration::from_secs(30)).pop().unwrap();
+        assert_eq!(session.start, later());
+
+        // the next session starts when the flush happened, not when the toplevel got focus
+        tracker = tracker.with_wall_clock(epoch);
+        tracker.flush(t0 + Duration::from_secs(40));
+        let session = tracker.set_active(1, false, t0 + Duration::from_secs(50));
+        assert_eq!(session.unwrap().start, UNIX_EPOCH);
+    }
+
+    #[test]
+    fn closing_unfocused_records_nothing() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        tracker.set_active(1, false, t0 + Duration::from_secs(10));
+
+        assert_eq!(tracker.close(&1, t0 + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn closing_matching_keeps_the_rest() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_app_id(1, "firefox".to_string());
+        tracker.set_active(1, true, t0);
+        tracker.set_app_id(2, "foot".to_string());
+
+        assert_eq!(
+            tracker.close_matching(t0 + Duration::from_secs(5), |key| *key == 1),
+            vec![Session {
+                app_id: "firefox".to_string(),
+                title: None,
+                output: None,
+                start: UNIX_EPOCH,
+                duration: Duration::from_secs(5),
+            }]
+        );
+        assert_eq!(tracker.len(), 1);
+    }
+
+    #[test]
+    fn windows_without_app_id_are_recorded_by_title() {
+        let mut tracker = Tracker::default().with_wall_clock(epoch);
+        let t0 = Instant::now();
+
+        tracker.set_title(1, "Untitled 1".to_string(), t0);
+        tracker.set_active(1, true, t0);
+        assert_eq!(
+            tracker
+                .set_active(1, false, t0 + Duration::from_secs(5))
+                .map(|x| x.app_id),
+            Some("unidentified:Untitled 1".to_string())
+        );
+
+        let mut tracker = Tracker::default().unidentified_apps(UnidentifiedApps::Drop);
+        tracker.set_active(1, true, t0);
+        assert_eq!(
+            tracker.set_active(1, false, t0 + Duration::from_secs(5)),
+            None
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Merges app ids that stand for the same app under one canonical name.
+//!
+//! App ids aren't consistent, the same browser shows up as `org.mozilla.firefox`, `firefox` or
+//! `Firefox` depending on the version and the compositor. The `aliases` table of the config
+//! maps raw ids to the name to show instead, and ids that only differ in case are merged
+//! unless `fold_app_id_case` is turned off.
+//!
+//! This only happens when usage is shown, the database keeps the ids as they were recorded.
+
+use std::collections::HashMap;
+
+use appusage_core::config::Config;
+
+#[derive(Debug, Default)]
+pub struct Aliases {
+    /// Canonical names by raw app id, case folded when `fold_case` is set.
+    names: HashMap<String, String>,
+    fold_case: bool,
+}
+
+impl Aliases {
+    pub fn from_config(config: &Config) -> Self {
+        let fold_case = config.fold_app_id_case;
+        let names = config
+            .aliases
+            .iter()
+            .map(|(app_id, name)| (fold(app_id, fold_case), name.clone()))
+            .collect();
+        Self { names, fold_case }
+    }
+
+    /// Merges the usage of app ids that are aliases of the same app, sorted by duration again.
+    ///
+    /// Returns the merged list along with the app ids that went into each merged entry. Entries
+    /// go by the alias if any of their ids has one, otherwise by the spelling that was used
+    /// the most, so ids without an alias and nothing to merge with keep their name.
+    pub fn merge(
+        &self,
+        items: &[(String, u64)],
+    ) -> (Vec<(String, u64)>, HashMap<String, Vec<String>>) {
+        let mut merged: HashMap<String, Merged> = HashMap::new();
+
+        for (app_id, duration) in items {
+            let folded = fold(app_id, self.fold_case);
+            let alias = self.names.get(&folded);
+            let key = match alias {
+                Some(name) => fold(name, self.fold_case),
+                None => folded,
+            };
+
+            let entry = merged.entry(key).or_default();
+            entry.total += duration;
+            entry.members.push(app_id.clone());
+            if let Some(name) = alias {
+                entry.alias = Some(name.clone());
+            }
+            if entry
+                .most_used
+                .as_ref()
+                .is_none_or(|(_, most)| duration > most)
+            {
+                entry.most_used = Some((app_id.clone(), *duration));
+            }
+        }
+
+        let mut members = HashMap::new();
+        let mut merged = merged
+            .into_values()
+            .map(|entry| {
+                let label = entry
+                    .alias
+                    .or(entry.most_used.map(|(app_id, _)| app_id))
+                    .unwrap_or_default();
+                members.insert(label.clone(), entry.members);
+                (label, entry.total)
+            })
+            .collect::<Vec<_>>();
+        merged.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        (merged, members)
+    }
+}
+
+#[derive(Default)]
+struct Merged {
+    total: u64,
+    members: Vec<String>,
+    alias: Option<String>,
+    /// The id used the most and how long it was used.
+    most_used: Option<(String, u64)>,
+}
+
+fn fold(app_id: &str, fold_case: bool) -> String {
+    if fold_case {
+        app_id.to_lowercase()
+    } else {
+        app_id.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(fold_case: bool) -> Aliases {
+        let mut config = Config::default();
+        config.fold_app_id_case = fold_case;
+        config
+            .aliases
+            .insert("org.mozilla.firefox".to_string(), "Firefox".to_string());
+        Aliases::from_config(&config)
+    }
+
+    #[test]
+    fn aliases_and_case_are_merged() {
+        let items = [
+            ("firefox".to_string(), 30),
+            ("org.mozilla.firefox".to_string(), 20),
+            ("Foot".to_string(), 10),
+            ("foot".to_string(), 5),
+            ("mpv".to_string(), 1),
+        ];
+
+        let (merged, members) = aliases(true).merge(&items);
+        assert_eq!(
+            merged,
+            [
+                ("Firefox".to_string(), 50),
+                ("Foot".to_string(), 15),
+                ("mpv".to_string(), 1)
+            ]
+        );
+        assert_eq!(members["Firefox"], ["firefox", "org.mozilla.firefox"]);
+        assert_eq!(members["Foot"], ["Foot", "foot"]);
+        assert_eq!(members["mpv"], ["mpv"]);
+
+        // without case folding only the alias itself matches
+        let (merged, _) = aliases(false).merge(&items);
+        assert_eq!(merged.len(), 5);
+        assert_eq!(merged[0], ("firefox".to_string(), 30));
+        assert_eq!(merged[1], ("Firefox".to_string(), 20));
+    }
+}
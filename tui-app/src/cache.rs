@@ -0,0 +1,122 @@
+//! Remembers query results so flipping back and forth between views doesn't hit the database
+//! every time.
+//!
+//! Everything is dropped once the day changes, since "today" then means something else, and
+//! when the user asks for a refresh to see what the daemon recorded in the meantime.
+
+use std::collections::HashMap;
+
+use appusage_core::DAY_MS;
+use chrono::{Local, NaiveDate};
+use rusqlite::Connection;
+
+use crate::{AppListTime, TREND_DAYS, db};
+
+pub struct QueryCache {
+    /// The day everything in here was fetched on.
+    day: NaiveDate,
+    /// One entry per fixed time range and only the latest custom one, so this can't grow
+    /// unbounded.
+    apps: HashMap<AppListTime, Vec<(String, u64)>>,
+    /// Total usage and active days of each time range, kept the same way as `apps`.
+    summaries: HashMap<AppListTime, (u64, u64)>,
+    week: Option<Vec<(String, u64)>>,
+    /// Focus switches on each of the past [`TREND_DAYS`] days, oldest first.
+    switches: Option<Vec<u64>>,
+}
+
+impl Default for QueryCache {
+    fn default() -> Self {
+        Self {
+            day: Local::now().date_naive(),
+            apps: HashMap::new(),
+            summaries: HashMap::new(),
+            week: None,
+            switches: None,
+        }
+    }
+}
+
+impl QueryCache {
+    /// Forgets everything, so the next lookups fetch fresh data.
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    fn invalidate_if_day_changed(&mut self) {
+        if self.day != Local::now().date_naive() {
+            self.invalidate();
+        }
+    }
+
+    pub fn list_apps(
+        &mut self,
+        conn: &Connection,
+        time: AppListTime,
+    ) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+        self.invalidate_if_day_changed();
+        if let Some(apps) = self.apps.get(&time) {
+            return Ok(apps.clone());
+        }
+
+        // the daily rollup only pays off once there are more sessions than days in the range
+        let apps = match time.timestamps() {
+            Some((start_time, end_time)) if end_time - start_time <= 7 * DAY_MS => {
+                db::list_apps(conn, Some((start_time, end_time)))?
+            }
+            range => db::list_apps_rollup(conn, range)?,
+        };
+        if let AppListTime::Custom { .. } = time {
+            self.apps
+                .retain(|time, _| !matches!(time, AppListTime::Custom { .. }));
+        }
+        self.apps.insert(time, apps.clone());
+        Ok(apps)
+    }
+
+    /// Returns the total usage within the time range along with on how many days there was
+    /// any, see [`db::get_tracked_summary`].
+    pub fn tracked_summary(
+        &mut self,
+        conn: &Connection,
+        time: AppListTime,
+    ) -> Result<(u64, u64), rusqlite::Error> {
+        self.invalidate_if_day_changed();
+        if let Some(summary) = self.summaries.get(&time) {
+            return Ok(*summary);
+        }
+
+        let summary = db::get_tracked_summary(conn, time.timestamps())?;
+        if let AppListTime::Custom { .. } = time {
+            self.summaries
+                .retain(|time, _| !matches!(time, AppListTime::Custom { .. }));
+        }
+        self.summaries.insert(time, summary);
+        Ok(summary)
+    }
+
+    /// Returns how often focus moved between windows on each of the past [`TREND_DAYS`] days,
+    /// ending with today.
+    pub fn focus_switches(&mut self, conn: &Connection) -> Result<Vec<u64>, rusqlite::Error> {
+        self.invalidate_if_day_changed();
+        if let Some(switches) = &self.switches {
+            return Ok(switches.clone());
+        }
+
+        let first_day = self.day - chrono::Duration::days(TREND_DAYS as i64 - 1);
+        let switches = db::get_focus_switches(conn, first_day, TREND_DAYS)?;
+        self.switches = Some(switches.clone());
+        Ok(switches)
+    }
+
+    /// Returns the bar chart data, if it was fetched already today and nothing was refreshed
+    /// since.
+    pub fn week_data(&mut self) -> Option<Vec<(String, u64)>> {
+        self.invalidate_if_day_changed();
+        self.week.clone()
+    }
+
+    pub fn set_week_data(&mut self, week_data: Vec<(String, u64)>) {
+        self.week = Some(week_data);
+    }
+}
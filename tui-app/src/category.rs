@@ -0,0 +1,121 @@
+//! Sums up the usage of apps by the categories (Work, Games, ...) they are assigned to in the
+//! `categories` table of the config.
+//!
+//! Apps without a category end up in [`UNCATEGORIZED`]. An app listed under several
+//! categories counts for the first one in alphabetical order.
+
+use std::collections::HashMap;
+
+use appusage_core::config::Config;
+use rusqlite::Connection;
+
+use crate::db;
+
+pub const UNCATEGORIZED: &str = "Uncategorized";
+
+#[derive(Debug, Default)]
+pub struct Categories {
+    /// Category of each app id, case folded when `fold_case` is set.
+    by_app: HashMap<String, String>,
+    fold_case: bool,
+}
+
+impl Categories {
+    pub fn from_config(config: &Config) -> Self {
+        let fold_case = config.fold_app_id_case;
+        let mut categories = config.categories.iter().collect::<Vec<_>>();
+        categories.sort();
+
+        let mut by_app = HashMap::new();
+        for (category, app_ids) in categories {
+            for app_id in app_ids {
+                let app_id = if fold_case {
+                    app_id.to_lowercase()
+                } else {
+                    app_id.clone()
+                };
+                by_app.entry(app_id).or_insert_with(|| category.clone());
+            }
+        }
+        Self { by_app, fold_case }
+    }
+
+    pub fn category_of(&self, app_id: &str) -> &str {
+        let found = if self.fold_case {
+            self.by_app.get(&app_id.to_lowercase())
+        } else {
+            self.by_app.get(app_id)
+        };
+        found.map_or(UNCATEGORIZED, String::as_str)
+    }
+
+    /// Sums up the usage of the apps in each category, sorted by duration again.
+    ///
+    /// Returns the categories along with the app ids that went into each of them.
+    pub fn group(
+        &self,
+        items: &[(String, u64)],
+    ) -> (Vec<(String, u64)>, HashMap<String, Vec<String>>) {
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        let mut members: HashMap<String, Vec<String>> = HashMap::new();
+
+        for (app_id, duration) in items {
+            let category = self.category_of(app_id).to_string();
+            *totals.entry(category.clone()).or_default() += duration;
+            members.entry(category).or_default().push(app_id.clone());
+        }
+
+        let mut grouped = totals.into_iter().collect::<Vec<_>>();
+        grouped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        (grouped, members)
+    }
+}
+
+/// Returns how long the apps of each category were used within the range, most used first.
+pub fn list_categories(
+    conn: &Connection,
+    categories: &Categories,
+    time_range: Option<(u64, u64)>,
+) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+    let apps = db::list_apps(conn, time_range)?;
+    Ok(categories.group(&apps).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apps_are_summed_per_category() {
+        let mut config = Config::default();
+        config.categories.insert(
+            "Work".to_string(),
+            vec!["foot".to_string(), "Code".to_string()],
+        );
+        config
+            .categories
+            .insert("Games".to_string(), vec!["foot".to_string()]);
+        let categories = Categories::from_config(&config);
+
+        assert_eq!(categories.category_of("code"), "Work");
+        assert_eq!(categories.category_of("foot"), "Games");
+        assert_eq!(categories.category_of("mpv"), UNCATEGORIZED);
+
+        let (grouped, members) = categories.group(&[
+            ("code".to_string(), 30),
+            ("foot".to_string(), 20),
+            ("mpv".to_string(), 5),
+            ("firefox".to_string(), 10),
+        ]);
+        assert_eq!(
+            grouped,
+            [
+                ("Work".to_string(), 30),
+                ("Games".to_string(), 20),
+                (UNCATEGORIZED.to_string(), 15)
+            ]
+        );
+        assert_eq!(members[UNCATEGORIZED], ["mpv", "firefox"]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Finds rows whose times contradict each other, and optionally repairs them.
+
+use std::error::Error;
+
+use rusqlite::Connection;
+
+use crate::db;
+
+/// Rows of a single database that failed the check.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Rows ending before they start, these can't be fixed and get deleted.
+    pub inverted: usize,
+    /// Rows whose duration is longer than from their start to their end, the duration gets
+    /// shortened to fit.
+    pub mismatched: usize,
+}
+
+/// Checks every `(schema, label)` database and prints what was found, fixing it if `repair`
+/// is set.
+///
+/// Opening the databases only did a quick check, so this runs the full integrity check first
+/// and leaves the rows of a corrupt database alone.
+pub fn run(
+    conn: &Connection,
+    sources: &[(String, String)],
+    repair: bool,
+) -> Result<(), Box<dyn Error>> {
+    for (schema, label) in sources {
+        if let Some(problem) = db::check_integrity(conn, schema, true)? {
+            println!(
+                "{label}: corrupt ({problem}), run `appusage-daemon --recover` to move it aside \
+                 and salvage what is still readable"
+            );
+            continue;
+        }
+
+        let report = check(conn, schema, repair)?;
+        println!(
+            "{label}: {} rows end before they start{}, {} rows last longer than their span{}",
+            report.inverted,
+            if repair { " (deleted)" } else { "" },
+            report.mismatched,
+            if repair { " (fixed)" } else { "" },
+        );
+    }
+    if !repair {
+        println!("run again with --repair to fix these");
+    }
+    Ok(())
+}
+
+fn check(conn: &Connection, schema: &str, repair: bool) -> Result<Report, rusqlite::Error> {
+    if !repair {
+        return Ok(Report {
+            inverted: db::count_inverted_rows(conn, schema)?,
+            mismatched: db::count_mismatched_durations(conn, schema)?,
+        });
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let report = Report {
+        inverted: db::delete_inverted_rows(&tx, schema)?,
+        mismatched: db::fix_mismatched_durations(&tx, schema)?,
+    };
+    tx.commit()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "create table app_usage (
+                id integer primary key autoincrement,
+                app_name text not null,
+                start_time integer not null,
+                end_time integer not null,
+                duration integer not null
+            );
+            insert into app_usage (app_name, start_time, end_time, duration) values
+                ('fine', 1000, 2000, 1000),
+                ('off-by-rounding', 1000, 2001, 1000),
+                ('inverted', 5000, 4000, 1000),
+                ('wrong-duration', 1000, 6000, 60000),
+                ('suspended', 1000, 61000, 5000);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn finds_inconsistent_rows() {
+        let conn = test_conn();
+        assert_eq!(
+            check(&conn, "main", false).unwrap(),
+            Report {
+                inverted: 1,
+                mismatched: 1
+            }
+        );
+        // only looking doesn't change anything
+        assert_eq!(
+            check(&conn, "main", false).unwrap(),
+            Report {
+                inverted: 1,
+                mismatched: 1
+            }
+        );
+    }
+
+    #[test]
+    fn repairs_inconsistent_rows() {
+        let conn = test_conn();
+        check(&conn, "main", true).unwrap();
+
+        assert_eq!(check(&conn, "main", false).unwrap(), Report::default());
+        let duration: u64 = conn
+            .query_row(
+                "select duration from app_usage where app_name = 'wrong-duration'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(duration, 5000);
+        let rows: u64 = conn
+            .query_row("select count(*) from app_usage", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 4);
+    }
+}
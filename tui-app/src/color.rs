@@ -0,0 +1,147 @@
+//! Decides once whether the tui uses colors, so every view degrades the same way on
+//! terminals without them, and which colors it uses for what, from the `colors` section of
+//! the config.
+
+use std::{env, str::FromStr};
+
+use appusage_core::config::ColorConfig;
+use ratatui::{
+    crossterm::style::available_color_count,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::Block,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colors {
+    enabled: bool,
+    theme: Theme,
+}
+
+/// Colors of the parts of the tui that can be configured, unset ones use the terminal's
+/// default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Theme {
+    selected: Option<Color>,
+    bars: Option<Color>,
+    today: Color,
+    borders: Option<Color>,
+    title: Option<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            selected: None,
+            bars: None,
+            today: Color::Cyan,
+            borders: None,
+            title: None,
+        }
+    }
+}
+
+impl Theme {
+    /// Parses the configured colors, failing on the first that is neither a known name nor
+    /// hex.
+    fn from_config(config: &ColorConfig) -> Result<Self, String> {
+        let parse = |role: &str, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|x| {
+                    Color::from_str(x).map_err(|_| {
+                        format!(
+                            "colors.{role} is {x:?}, which is neither a color name nor hex like #5e81ac"
+                        )
+                    })
+                })
+                .transpose()
+        };
+        let default = Self::default();
+        Ok(Self {
+            selected: parse("selected", &config.selected)?,
+            bars: parse("bars", &config.bars)?,
+            today: parse("today", &config.today)?.unwrap_or(default.today),
+            borders: parse("borders", &config.borders)?,
+            title: parse("title", &config.title)?,
+        })
+    }
+}
+
+impl Colors {
+    /// Uses colors unless `no_color` is set, `NO_COLOR` is set or the terminal can't show
+    /// them, those from the config if there are any.
+    pub fn detect(no_color: bool, config: &ColorConfig) -> Result<Self, String> {
+        let enabled = !no_color
+            && env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+            && env::var("TERM").as_deref() != Ok("dumb")
+            && available_color_count() >= 8;
+        // an invalid color is an error even when colors are off, so it doesn't go unnoticed
+        let theme = Theme::from_config(config)?;
+        Ok(Self { enabled, theme })
+    }
+
+    pub fn enabled(self) -> bool {
+        self.enabled
+    }
+
+    fn style(self, color: Option<Color>) -> Style {
+        match color {
+            Some(color) if self.enabled => Style::new().fg(color),
+            _ => Style::new(),
+        }
+    }
+
+    /// A bordered block in the configured colors, every view is drawn in one.
+    pub fn block(self) -> Block<'static> {
+        Block::bordered()
+            .border_style(self.style(self.theme.borders))
+            .title_style(self.style(self.theme.title))
+    }
+
+    pub fn selected(self) -> Style {
+        self.style(self.theme.selected)
+    }
+
+    pub fn bars(self) -> Style {
+        self.style(self.theme.bars)
+    }
+
+    /// The color today's bar stands out with.
+    pub fn today(self) -> Color {
+        self.theme.today
+    }
+
+    /// Colors `text`, or puts `marker` in front of it if there are no colors, so whatever
+    /// the color meant is still visible.
+    pub fn paint(self, text: impl Into<String>, color: Color, marker: &str) -> Line<'static> {
+        let text = text.into();
+        if self.enabled {
+            Line::from(text).fg(color)
+        } else {
+            Line::from(format!("{marker}{text}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_parsed_from_names_and_hex() {
+        let mut config = ColorConfig::default();
+        config.selected = Some("yellow".to_string());
+        config.bars = Some("#5e81ac".to_string());
+        let theme = Theme::from_config(&config).unwrap();
+        assert_eq!(theme.selected, Some(Color::Yellow));
+        assert_eq!(theme.bars, Some(Color::Rgb(0x5e, 0x81, 0xac)));
+        assert_eq!(theme.today, Color::Cyan);
+        assert_eq!(theme.borders, None);
+
+        let mut config = ColorConfig::default();
+        config.title = Some("blurple".to_string());
+        let e = Theme::from_config(&config).unwrap_err();
+        assert!(e.starts_with("colors.title is \"blurple\""), "{e}");
+    }
+}
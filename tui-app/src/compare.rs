@@ -0,0 +1,98 @@
+//! Lines up the usage of each entry of the list in the shown time range with the one right
+//! before it, to see what went up and what went down.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub name: String,
+    pub current: u64,
+    pub previous: u64,
+}
+
+impl Row {
+    /// How much more the entry was used in the current range, negative if less.
+    pub fn delta(&self) -> i64 {
+        self.current as i64 - self.previous as i64
+    }
+
+    /// Not used at all in the previous range.
+    pub fn is_new(&self) -> bool {
+        self.previous == 0 && self.current > 0
+    }
+
+    /// Not used at all in the current range anymore.
+    pub fn is_gone(&self) -> bool {
+        self.current == 0 && self.previous > 0
+    }
+}
+
+/// Joins the usage of both ranges by name, entries only in one of them count as unused in
+/// the other. The biggest changes come first, whichever direction they went in.
+pub fn compare(current: &[(String, u64)], previous: &[(String, u64)]) -> Vec<Row> {
+    let mut rows = current
+        .iter()
+        .map(|(name, duration)| {
+            (
+                name.as_str(),
+                Row {
+                    name: name.clone(),
+                    current: *duration,
+                    previous: 0,
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    for (name, duration) in previous {
+        rows.entry(name.as_str())
+            .or_insert_with(|| Row {
+                name: name.clone(),
+                current: 0,
+                previous: 0,
+            })
+            .previous += duration;
+    }
+
+    let mut rows = rows.into_values().collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        b.delta()
+            .unsigned_abs()
+            .cmp(&a.delta().unsigned_abs())
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_joined_by_name() {
+        let rows = compare(
+            &[
+                ("firefox".to_string(), 100),
+                ("foot".to_string(), 50),
+                ("steam".to_string(), 30),
+            ],
+            &[
+                ("firefox".to_string(), 90),
+                ("foot".to_string(), 120),
+                ("mpv".to_string(), 20),
+            ],
+        );
+        let summary = rows
+            .iter()
+            .map(|x| (x.name.as_str(), x.delta(), x.is_new(), x.is_gone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("foot", -70, false, false),
+                ("steam", 30, true, false),
+                ("mpv", -20, false, true),
+                ("firefox", 10, false, false),
+            ]
+        );
+    }
+}
@@ -1,49 +1,286 @@
-use rusqlite::{params, Connection};
+use std::{collections::HashMap, path::Path};
+
+use appusage_core::DAY_MS;
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, TimeZone, Timelike};
+use rusqlite::{Connection, ErrorCode, OptionalExtension, params};
+
+/// Returns a description of the problem if the database attached as `schema` is corrupt.
+///
+/// Unless `full` is set only a quick check is done, which skips comparing the indexes with
+/// their tables and so stays fast on large databases.
+pub fn check_integrity(
+    conn: &Connection,
+    schema: &str,
+    full: bool,
+) -> Result<Option<String>, rusqlite::Error> {
+    let pragma = if full {
+        "integrity_check"
+    } else {
+        "quick_check"
+    };
+    match conn.query_row(&format!("PRAGMA {schema}.{pragma}"), [], |row| {
+        row.get::<_, String>(0)
+    }) {
+        Ok(status) if status == "ok" => Ok(None),
+        Ok(status) => Ok(Some(status)),
+        Err(e)
+            if e.sqlite_error_code().is_some_and(|code| {
+                matches!(code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+            }) =>
+        {
+            Ok(Some(e.to_string()))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// How much of a session lies within the range `?1..?2`, sessions crossing its start or end
+/// only count with the part inside it.
+///
+/// A session can't count for more than its duration, even if its start and end are further
+/// apart than that.
+const DURATION_IN_RANGE: &str = "min(duration, min(end_time, ?2) - max(start_time, ?1))";
+
+/// Sessions overlapping the range `?1..?2`.
+const OVERLAPS_RANGE: &str = "end_time > ?1 and start_time < ?2";
+
+/// Reads a summed up duration, which is NULL for a group without any rows and comes out
+/// negative for rows that end before they start (see `check`), both count as nothing.
+fn summed_duration(row: &rusqlite::Row, idx: usize) -> Result<u64, rusqlite::Error> {
+    Ok(row
+        .get::<_, Option<i64>>(idx)?
+        .map_or(0, |x| x.max(0) as u64))
+}
 
 pub fn list_apps(
     conn: &Connection,
     time_range: Option<(u64, u64)>,
+) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+    if let Some((start_time, end_time)) = time_range {
+        let mut stmt = conn.prepare(&apps_in_range())?;
+        let x = stmt
+            .query_map([start_time, end_time], |row| {
+                Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
+            })?
+            .collect();
+        x
+    } else {
+        // pruned sessions only count towards all time
+        let mut stmt = conn.prepare(
+            "select app_name, sum(duration)
+         from (
+             select app_name, duration from usage
+             union all
+             select app_name, duration from archived_usage
+         )
+         group by app_name
+         order by sum(duration) desc",
+        )?;
+        let x = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
+            })?
+            .collect();
+        x
+    }
+}
+
+/// The query of [`list_apps`] for a range `?1..?2`.
+fn apps_in_range() -> String {
+    // grouping by +app_name keeps sqlite from walking the whole (app_name, start_time) index to
+    // get the groups in order, instead of searching the end_time one
+    format!(
+        "select app_name, sum({DURATION_IN_RANGE}) as total_duration
+         from usage
+         where {OVERLAPS_RANGE}
+         group by +app_name
+         order by total_duration desc"
+    )
+}
+
+/// Same as [`list_apps`], but reads the daily rollup instead of every session, which is a lot
+/// faster for long ranges.
+///
+/// The rollup has whole local days, so the range has to start and end at local midnight like
+/// those of `AppListTime` do. Sessions count towards the day they started on instead of being
+/// split at midnight.
+pub fn list_apps_rollup(
+    conn: &Connection,
+    time_range: Option<(u64, u64)>,
 ) -> Result<Vec<(String, u64)>, rusqlite::Error> {
     if let Some((start_time, end_time)) = time_range {
         let mut stmt = conn.prepare(
             "select app_name, sum(duration) as total_duration
-         from app_usage
-         where start_time >= ? and start_time < ?
+         from daily_usage
+         where day >= ? and day < ?
          group by app_name
          order by total_duration desc",
         )?;
         let x = stmt
-            .query_map([start_time, end_time], |row| {
-                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            .query_map([local_day(start_time), local_day(end_time)], |row| {
+                Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
             })?
             .collect();
         x
     } else {
+        // pruned sessions only count towards all time
         let mut stmt = conn.prepare(
             "select app_name, sum(duration)
-         from app_usage
+         from (
+             select app_name, duration from daily_usage
+             union all
+             select app_name, duration from archived_usage
+         )
          group by app_name
          order by sum(duration) desc",
         )?;
         let x = stmt
             .query_map([], |row| {
-                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+                Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
             })?
             .collect();
         x
     }
 }
 
+/// The local day `time` falls on, counted from the epoch like the days of the daily rollup.
+fn local_day(time: u64) -> u64 {
+    let local = Local
+        .timestamp_millis_opt(time as i64)
+        .unwrap()
+        .naive_local();
+    local.and_utc().timestamp_millis() as u64 / DAY_MS
+}
+
+/// Counts the sessions overlapping the range whose duration falls into each range between
+/// consecutive `bounds` (in ms, ascending), plus one range below the first and one from the
+/// last bound onwards.
+pub fn get_session_length_counts(
+    conn: &Connection,
+    bounds: &[u64],
+    time_range: Option<(u64, u64)>,
+) -> Result<Vec<u64>, rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let mut stmt = conn.prepare(&format!(
+        "select count(*)
+         from usage
+         where duration >= ?3 and duration < ?4 and {OVERLAPS_RANGE}"
+    ))?;
+
+    let lower = std::iter::once(0).chain(bounds.iter().copied());
+    let upper = bounds
+        .iter()
+        .copied()
+        .chain(std::iter::once(i64::MAX as u64));
+    let x = lower
+        .zip(upper)
+        .map(|(lower, upper)| {
+            stmt.query_row([start_time, end_time, lower, upper], |row| row.get(0))
+        })
+        .collect();
+    x
+}
+
+/// Returns the `(app_name, start_time, end_time)` of every session that started within the
+/// range, oldest first.
+pub fn get_sessions(
+    conn: &Connection,
+    (start_time, end_time): (u64, u64),
+) -> Result<Vec<(String, u64, u64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "select app_name, start_time, end_time
+         from usage
+         where start_time >= ? and start_time < ?
+         order by start_time",
+    )?;
+    let x = stmt
+        .query_map([start_time, end_time], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        })?
+        .collect();
+    x
+}
+
+/// Returns the `(start_time, end_time, duration)` of every session of an app that started
+/// within the range, oldest first.
+pub fn get_app_sessions(
+    conn: &Connection,
+    app_name: &str,
+    (start_time, end_time): (u64, u64),
+) -> Result<Vec<(u64, u64, u64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "select start_time, end_time, duration
+         from usage
+         where app_name = ? and start_time >= ? and start_time < ?
+         order by start_time",
+    )?;
+    let x = stmt
+        .query_map(params![app_name, start_time, end_time], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        })?
+        .collect();
+    x
+}
+
+/// Returns the `(app_name, start_time, end_time)` of every session within the range, with
+/// sessions of the same app less than `max_gap` ms apart merged into one.
+///
+/// Merged sessions span from the first start to the last end, gaps included. Sessions
+/// crossing the start or end of the range are cut off at it.
+pub fn get_merged_sessions(
+    conn: &Connection,
+    max_gap: u64,
+    time_range: Option<(u64, u64)>,
+) -> Result<Vec<(String, u64, u64)>, rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let mut stmt = conn.prepare(&format!(
+        "select app_name, max(start_time, ?1), min(end_time, ?2)
+         from usage
+         where {OVERLAPS_RANGE}
+         order by app_name, start_time"
+    ))?;
+    let rows = stmt
+        .query_map([start_time, end_time], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut merged: Vec<(String, u64, u64)> = Vec::with_capacity(rows.len());
+    for (app_name, start, end) in rows {
+        match merged.last_mut() {
+            Some(last) if last.0 == app_name && start < last.2.saturating_add(max_gap) => {
+                last.2 = last.2.max(end);
+            }
+            _ => merged.push((app_name, start, end)),
+        }
+    }
+    Ok(merged)
+}
+
 pub fn get_data_for_app_and_time(
     conn: &Connection,
     app_name: String,
     (start_time, end_time): (u64, u64),
 ) -> Result<u64, rusqlite::Error> {
     conn.query_row(
-        "select sum(duration)
-            from app_usage
-            where app_name == ? and start_time >= ? and start_time < ?",
-        params![app_name, start_time, end_time],
+        &format!(
+            "select sum({DURATION_IN_RANGE})
+            from usage
+            where app_name == ?3 and {OVERLAPS_RANGE}"
+        ),
+        params![start_time, end_time, app_name],
         |row| {
             // println!("row!!: {:?}", row.get::<_, u64>(0).or_else(|_| Ok(0)));
             Ok(row.get::<_, u64>(0).unwrap_or(0))
@@ -51,10 +288,194 @@ pub fn get_data_for_app_and_time(
     )
 }
 
+/// Returns how many sessions of an app overlap the range, or there are at all without one.
+///
+/// Pruned sessions only live on as daily totals, so they aren't counted.
+pub fn get_session_count(
+    conn: &Connection,
+    app_name: &str,
+    time_range: Option<(u64, u64)>,
+) -> Result<u64, rusqlite::Error> {
+    // sqlite integers are signed
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    conn.query_row(
+        &format!(
+            "select count(*)
+            from usage
+            where app_name == ?3 and {OVERLAPS_RANGE}"
+        ),
+        params![start_time, end_time, app_name],
+        |row| row.get(0),
+    )
+}
+
+/// Returns how long the sessions of an app overlapping the range lasted on average, counting
+/// only the part of them inside it. `None` if there are no such sessions.
+pub fn get_average_session_length(
+    conn: &Connection,
+    app_name: &str,
+    time_range: Option<(u64, u64)>,
+) -> Result<Option<u64>, rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let (count, total): (u64, u64) = conn.query_row(
+        &format!(
+            "select count(*), coalesce(sum({DURATION_IN_RANGE}), 0)
+            from usage
+            where app_name == ?3 and {OVERLAPS_RANGE}"
+        ),
+        params![start_time, end_time, app_name],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(total.checked_div(count))
+}
+
+/// Returns how long an app was used under each window title within the range, longest
+/// first. Sessions without a title, like those recorded before titles were, are left out.
+pub fn get_titles_for_app(
+    conn: &Connection,
+    app_name: &str,
+    time_range: Option<(u64, u64)>,
+) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let mut stmt = conn.prepare(&format!(
+        "select title, sum({DURATION_IN_RANGE}) as total_duration
+            from usage
+            where app_name == ?3 and title is not null and {OVERLAPS_RANGE}
+            group by title
+            order by total_duration desc"
+    ))?;
+    let x = stmt
+        .query_map(params![start_time, end_time, app_name], |row| {
+            Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
+        })?
+        .collect();
+    x
+}
+
+/// Returns the total usage of an app on each weekday in local time, starting with Sunday.
+///
+/// Sessions spanning midnight are split between the days in proportion to how much of them
+/// fell on each.
+pub fn get_weekday_totals(conn: &Connection, app_name: &str) -> Result<[u64; 7], rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "select start_time, end_time, duration
+            from usage
+            where app_name == ?",
+    )?;
+    let sessions = stmt.query_map([app_name], |row| {
+        Ok((
+            row.get::<_, u64>(0)?,
+            row.get::<_, u64>(1)?,
+            row.get::<_, u64>(2)?,
+        ))
+    })?;
+
+    let weekday_of = |time: u64| {
+        let local = Local.timestamp_millis_opt(time as i64).unwrap();
+        (
+            local.weekday().num_days_from_sunday() as usize,
+            local.date_naive(),
+        )
+    };
+    let mut totals = [0; 7];
+    for session in sessions {
+        let (start_time, end_time, duration) = session?;
+        let span = end_time.saturating_sub(start_time);
+        if span == 0 {
+            totals[weekday_of(start_time).0] += duration;
+            continue;
+        }
+
+        let mut time = start_time;
+        while time < end_time {
+            let (weekday, day) = weekday_of(time);
+            let next = day
+                .succ_opt()
+                .and_then(|day| {
+                    day.and_time(NaiveTime::MIN)
+                        .and_local_timezone(Local)
+                        .earliest()
+                })
+                .map_or(end_time, |midnight| midnight.timestamp_millis() as u64)
+                .min(end_time);
+            totals[weekday] += (next - time) * duration / span;
+            time = next;
+        }
+    }
+    Ok(totals)
+}
+
+/// Returns how long an app was used in each hour of the day (local time) within the range,
+/// sessions spanning several hours are split between them.
+pub fn get_hourly_distribution(
+    conn: &Connection,
+    app_name: &str,
+    time_range: Option<(u64, u64)>,
+) -> Result<[u64; 24], rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let mut stmt = conn.prepare(&format!(
+        "select max(start_time, ?1), min(end_time, ?2), {DURATION_IN_RANGE}
+            from usage
+            where app_name == ?3 and {OVERLAPS_RANGE}"
+    ))?;
+    let sessions = stmt
+        .query_map(params![start_time, end_time, app_name], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(spread_over_hours(&sessions, |time| {
+        let local = Local.timestamp_millis_opt(time as i64).unwrap();
+        (
+            local.hour() as usize,
+            ((local.minute() * 60 + local.second()) * 1000 + local.timestamp_subsec_millis())
+                as u64,
+        )
+    }))
+}
+
+/// Splits the `(start_time, end_time, duration)` of sessions between the hours of the day
+/// they cover, `position` gives the hour of a time along with how far into it it is.
+///
+/// Sessions whose start and end are further apart than their duration give every hour its
+/// share of the duration.
+fn spread_over_hours(
+    sessions: &[(u64, u64, u64)],
+    position: impl Fn(u64) -> (usize, u64),
+) -> [u64; 24] {
+    const HOUR_MS: u64 = 3_600_000;
+
+    let mut totals = [0; 24];
+    for &(start_time, end_time, duration) in sessions {
+        let span = end_time.saturating_sub(start_time);
+        let mut time = start_time;
+        while time < end_time {
+            let (hour, into_hour) = position(time);
+            let next = (time - into_hour + HOUR_MS).min(end_time);
+            totals[hour % 24] += (next - time) * duration / span;
+            time = next;
+        }
+    }
+    totals
+}
+
+/// Returns when an app was first used, if it ever was.
+pub fn get_first_use(conn: &Connection, app_name: &str) -> Result<Option<u64>, rusqlite::Error> {
+    conn.query_row(
+        "select min(start_time) from usage where app_name == ?",
+        [app_name],
+        |row| row.get::<_, Option<u64>>(0),
+    )
+}
+
 pub fn get_total_app_usage(conn: &Connection, app_name: String) -> Result<u64, rusqlite::Error> {
     conn.query_row(
         "select sum(duration)
-            from app_usage
+            from (
+                select app_name, duration from usage
+                union all
+                select app_name, duration from archived_usage
+            )
             where app_name == ?",
         [app_name],
         |row| {
@@ -69,9 +490,11 @@ pub fn get_data_for_time(
     (start_time, end_time): (u64, u64),
 ) -> Result<u64, rusqlite::Error> {
     conn.query_row(
-        "select sum(duration)
-            from app_usage
-            where start_time >= ? and start_time < ?",
+        &format!(
+            "select sum({DURATION_IN_RANGE})
+            from usage
+            where {OVERLAPS_RANGE}"
+        ),
         [start_time, end_time],
         |row| {
             // println!("row!!: {:?}", row.get::<_, u64>(0).or_else(|_| Ok(0)));
@@ -79,3 +502,1153 @@ pub fn get_data_for_time(
         },
     )
 }
+
+/// Returns the `(time, count)` samples of open windows within the range, oldest first.
+pub fn get_window_counts(
+    conn: &Connection,
+    (start_time, end_time): (u64, u64),
+) -> Result<Vec<(u64, u64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "select time, count
+            from window_counts
+            where time >= ? and time < ?
+            order by time",
+    )?;
+    let x = stmt
+        .query_map([start_time, end_time], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?))
+        })?
+        .collect();
+    x
+}
+
+/// Returns the local date along with the earliest start and latest end time of every day
+/// within the range that has any usage, oldest first.
+pub fn get_daily_activity_spans(
+    conn: &Connection,
+    (start_time, end_time): (u64, u64),
+) -> Result<Vec<(String, u64, u64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "select date(start_time / 1000, 'unixepoch', 'localtime') as day,
+                min(start_time),
+                max(end_time)
+            from usage
+            where start_time >= ? and start_time < ?
+            group by day
+            order by day",
+    )?;
+    let x = stmt
+        .query_map([start_time, end_time], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        })?
+        .collect();
+    x
+}
+
+// durations recorded before they were rounded can be a millisecond off from their
+// timestamps, which isn't worth reporting. A duration shorter than the span is fine, the
+// system may have been suspended or the user idle in between.
+const INVERTED_ROWS: &str = "end_time < start_time";
+const MISMATCHED_DURATIONS: &str =
+    "end_time >= start_time and duration - (end_time - start_time) > 1";
+
+/// Counts the rows of `app_usage` in the given schema that end before they start.
+pub fn count_inverted_rows(conn: &Connection, schema: &str) -> Result<usize, rusqlite::Error> {
+    conn.query_row(
+        &format!("select count(*) from {schema}.app_usage where {INVERTED_ROWS}"),
+        [],
+        |row| row.get(0),
+    )
+}
+
+pub fn delete_inverted_rows(conn: &Connection, schema: &str) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        &format!("delete from {schema}.app_usage where {INVERTED_ROWS}"),
+        [],
+    )
+}
+
+/// Counts the rows of `app_usage` in the given schema whose duration is longer than the time
+/// between their start and end.
+pub fn count_mismatched_durations(
+    conn: &Connection,
+    schema: &str,
+) -> Result<usize, rusqlite::Error> {
+    conn.query_row(
+        &format!("select count(*) from {schema}.app_usage where {MISMATCHED_DURATIONS}"),
+        [],
+        |row| row.get(0),
+    )
+}
+
+pub fn fix_mismatched_durations(conn: &Connection, schema: &str) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        &format!(
+            "update {schema}.app_usage set duration = end_time - start_time
+                where {MISMATCHED_DURATIONS}"
+        ),
+        [],
+    )
+}
+
+/// Columns `app_usage` needs to have in every database that gets loaded.
+const REQUIRED_COLUMNS: [&str; 4] = ["app_name", "start_time", "end_time", "duration"];
+
+pub fn attach(conn: &Connection, schema: &str, path: &Path) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        &format!("attach database ? as {schema}"),
+        [path.to_string_lossy()],
+    )
+}
+
+/// Attaches the database at `path` without ever writing to it.
+pub fn attach_read_only(
+    conn: &Connection,
+    schema: &str,
+    path: &Path,
+) -> Result<usize, rusqlite::Error> {
+    // characters that mean something in a URI have to be escaped in the path
+    let path = path
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    conn.execute(
+        &format!("attach database ? as {schema}"),
+        [format!("file:{path}?mode=ro")],
+    )
+}
+
+/// Returns the columns `app_usage` is missing in the given schema, for databases written by
+/// an incompatible version.
+pub fn missing_columns(
+    conn: &Connection,
+    schema: &str,
+) -> Result<Vec<&'static str>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("pragma {schema}.table_info(app_usage)"))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(REQUIRED_COLUMNS
+        .into_iter()
+        .filter(|required| !columns.iter().any(|x| x == required))
+        .collect())
+}
+
+/// Creates the temporary `usage` view every query reads from, combining `app_usage` of all
+/// given `(schema, label)` sources and recording the label of each row in a `source` column.
+///
+/// The daily rollups get combined the same way into `daily_usage`, and the daily usage of
+/// pruned sessions into `archived_usage`.
+pub fn create_usage_view(
+    conn: &Connection,
+    sources: &[(String, String)],
+) -> Result<usize, rusqlite::Error> {
+    let dailies = sources
+        .iter()
+        .map(|(schema, _)| format!("select app_name, day, duration from {schema}.app_usage_daily"))
+        .collect::<Vec<_>>();
+    conn.execute(
+        &format!(
+            "create temp view daily_usage as {}",
+            dailies.join(" union all ")
+        ),
+        (),
+    )?;
+    let archives = sources
+        .iter()
+        .map(|(schema, _)| {
+            format!("select app_name, day, duration from {schema}.app_usage_archive")
+        })
+        .collect::<Vec<_>>();
+    conn.execute(
+        &format!(
+            "create temp view archived_usage as {}",
+            archives.join(" union all ")
+        ),
+        (),
+    )?;
+    let switches = sources
+        .iter()
+        .map(|(schema, _)| format!("select day, count from {schema}.focus_switches"))
+        .collect::<Vec<_>>();
+    conn.execute(
+        &format!(
+            "create temp view daily_switches as {}",
+            switches.join(" union all ")
+        ),
+        (),
+    )?;
+
+    let selects = sources
+        .iter()
+        .map(|(schema, label)| {
+            format!(
+                "select app_name, title, output, start_time, end_time, duration, start_utc_offset, end_utc_offset, '{}' as source from {schema}.app_usage",
+                label.replace('\'', "''")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    conn.execute(
+        &format!("create temp view usage as {}", selects.join(" union all ")),
+        (),
+    )
+}
+
+/// Returns the total usage of an app split up by the database it was recorded in.
+pub fn get_app_usage_by_source(
+    conn: &Connection,
+    app_name: &str,
+    time_range: Option<(u64, u64)>,
+) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let mut stmt = conn.prepare(&format!(
+        "select source, sum({DURATION_IN_RANGE}) as total_duration
+            from usage
+            where app_name == ?3 and {OVERLAPS_RANGE}
+            group by source
+            order by total_duration desc"
+    ))?;
+    let x = stmt
+        .query_map(params![start_time, end_time, app_name], |row| {
+            Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
+        })?
+        .collect();
+    x
+}
+
+/// Returns the usage of an app within the range split up by the output it was on, leaving
+/// out sessions whose output isn't known.
+pub fn get_app_usage_by_output(
+    conn: &Connection,
+    app_name: &str,
+    time_range: Option<(u64, u64)>,
+) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+    let (start_time, end_time) = time_range.unwrap_or((0, i64::MAX as u64));
+    let mut stmt = conn.prepare(&format!(
+        "select output, sum({DURATION_IN_RANGE}) as total_duration
+            from usage
+            where app_name == ?3 and output is not null and {OVERLAPS_RANGE}
+            group by output
+            order by total_duration desc"
+    ))?;
+    let x = stmt
+        .query_map(params![start_time, end_time, app_name], |row| {
+            Ok((row.get::<_, String>(0)?, summed_duration(row, 1)?))
+        })?
+        .collect();
+    x
+}
+
+/// Creates the table holding user assigned tags, in case the daemon that created the
+/// database predates it.
+pub fn create_tag_table(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "create table if not exists app_tags (
+            app_name text not null,
+            tag text not null,
+            primary key (app_name, tag)
+        )",
+        (),
+    )
+}
+
+pub fn get_tags(conn: &Connection, app_name: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "select tag
+            from app_tags
+            where app_name == ?
+            order by tag",
+    )?;
+    let x = stmt
+        .query_map([app_name], |row| row.get::<_, String>(0))?
+        .collect();
+    x
+}
+
+pub fn add_tag(conn: &Connection, app_name: &str, tag: &str) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "insert or ignore into app_tags (app_name, tag) values (?, ?)",
+        [app_name, tag],
+    )
+}
+
+pub fn remove_tag(conn: &Connection, app_name: &str, tag: &str) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "delete from app_tags where app_name == ? and tag == ?",
+        [app_name, tag],
+    )
+}
+
+/// Returns when the most recent session ended, if any were recorded.
+pub fn get_last_end_time(conn: &Connection) -> Result<Option<u64>, rusqlite::Error> {
+    conn.query_row("select max(end_time) from usage", [], |row| {
+        row.get::<_, Option<u64>>(0)
+    })
+}
+
+/// Returns when the daemon was first started, if it ever recorded a run.
+pub fn get_first_daemon_run(conn: &Connection) -> Result<Option<u64>, rusqlite::Error> {
+    conn.query_row("select min(start_time) from daemon_runs", [], |row| {
+        row.get::<_, Option<u64>>(0)
+    })
+}
+
+/// Returns whether the daemon was running at any point within the range.
+pub fn has_daemon_coverage(
+    conn: &Connection,
+    (start_time, end_time): (u64, u64),
+) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "select exists(
+            select 1
+                from daemon_runs
+                where start_time < ? and end_time >= ?
+        )",
+        [end_time, start_time],
+        |row| row.get::<_, bool>(0),
+    )
+}
+
+/// `start_time` and `end_time` in local time, as ms since the unix epoch as if the local time
+/// were UTC, so every day is exactly [`DAY_MS`] long even when the clocks change.
+///
+/// Sessions go by the UTC offset the daemon recorded for them, so they stay on the day they
+/// happened on after moving to another time zone. Older sessions without one use the offset
+/// the current time zone had back then.
+const LOCAL_START_TIME: &str = "start_time + 1000 * coalesce(start_utc_offset, strftime('%s', start_time / 1000, 'unixepoch', 'localtime') - start_time / 1000)";
+const LOCAL_END_TIME: &str = "end_time + 1000 * coalesce(end_utc_offset, strftime('%s', end_time / 1000, 'unixepoch', 'localtime') - end_time / 1000)";
+
+/// Returns how long all apps together were used within the range, along with on how many
+/// local days anything was used. Sessions count for the day they started on.
+///
+/// Without a range pruned sessions count as well, on the UTC day they were archived under.
+pub fn get_tracked_summary(
+    conn: &Connection,
+    time_range: Option<(u64, u64)>,
+) -> Result<(u64, u64), rusqlite::Error> {
+    match time_range {
+        Some((start_time, end_time)) => conn.query_row(
+            &format!(
+                "select coalesce(sum({DURATION_IN_RANGE}), 0),
+                    count(distinct ({LOCAL_START_TIME}) / {DAY_MS})
+                from usage
+                where {OVERLAPS_RANGE}"
+            ),
+            [start_time, end_time],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ),
+        None => conn.query_row(
+            &format!(
+                "select coalesce(sum(duration), 0), count(distinct day)
+                from (
+                    select duration, ({LOCAL_START_TIME}) / {DAY_MS} as day from usage
+                    union all
+                    select duration, day from archived_usage
+                )"
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ),
+    }
+}
+
+/// Facts about everything that was recorded, see [`get_stats`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// The local day with the most usage, and how much there was on it (in ms).
+    pub busiest_day: Option<(NaiveDate, u64)>,
+    /// The app, start time and duration of the longest session.
+    pub longest_session: Option<(String, u64, u64)>,
+    /// When the first session started, in ms since the epoch.
+    pub first_seen: Option<u64>,
+}
+
+/// Looks for the busiest day, the longest session and the first thing ever recorded.
+///
+/// Pruned sessions count towards the busiest day, on the UTC day they were archived under,
+/// and make the first day they were on the first one seen. Only their daily totals are kept,
+/// so they don't take part in the longest session.
+pub fn get_stats(conn: &Connection) -> Result<Stats, rusqlite::Error> {
+    let busiest_day = conn
+        .query_row(
+            &format!(
+                "select day, sum(duration) as total
+                from (
+                    select ({LOCAL_START_TIME}) / {DAY_MS} as day, duration from usage
+                    union all
+                    select day, duration from archived_usage
+                )
+                group by day
+                order by total desc, day
+                limit 1"
+            ),
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, summed_duration(row, 1)?)),
+        )
+        .optional()?
+        .and_then(|(day, total)| {
+            let date = chrono::DateTime::from_timestamp_millis(day * DAY_MS as i64)?;
+            Some((date.date_naive(), total))
+        });
+
+    let longest_session = conn
+        .query_row(
+            "select app_name, start_time, duration from usage
+            order by duration desc, start_time
+            limit 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+
+    let first_seen = conn.query_row(
+        &format!(
+            "select min(first) from (
+                select min(start_time) as first from usage
+                union all
+                select min(day) * {DAY_MS} from archived_usage
+            )"
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(Stats {
+        busiest_day,
+        longest_session,
+        first_seen,
+    })
+}
+
+/// Returns how often focus moved from one window to another on each of `days` consecutive
+/// local days starting at `first_day`, oldest first.
+pub fn get_focus_switches(
+    conn: &Connection,
+    first_day: NaiveDate,
+    days: usize,
+) -> Result<Vec<u64>, rusqlite::Error> {
+    // the daemon counts local days since the epoch, which is what a local midnight comes to
+    let first = local_midnight(first_day) / DAY_MS;
+    let mut stmt = conn.prepare(
+        "select day, sum(count) from daily_switches
+        where day >= ?1 and day < ?2
+        group by day",
+    )?;
+    let rows = stmt.query_map([first, first + days as u64], |row| {
+        Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?))
+    })?;
+
+    let mut switches = vec![0; days];
+    for row in rows {
+        let (day, count) = row?;
+        switches[(day - first) as usize] = count;
+    }
+    Ok(switches)
+}
+
+/// Midnight at the start of `day` in the local time of [`LOCAL_START_TIME`].
+fn local_midnight(day: NaiveDate) -> u64 {
+    day.and_time(NaiveTime::MIN).and_utc().timestamp_millis() as u64
+}
+
+/// Returns the usage of every app on each of `days` consecutive local days starting at
+/// `first_day`, oldest first, fetched in a single query.
+pub fn get_daily_usage_per_app(
+    conn: &Connection,
+    first_day: NaiveDate,
+    days: usize,
+) -> Result<HashMap<String, Vec<u64>>, rusqlite::Error> {
+    let start_time = local_midnight(first_day);
+    let end_time = start_time + days as u64 * DAY_MS;
+
+    // UTC offsets are less than a day, so the sessions in question start within a day of the
+    // range, which keeps the index on start_time usable
+    let mut stmt = conn.prepare(&format!(
+        "select app_name, (local_start_time - ?1) / ?2 as day, sum(duration)
+            from (
+                select app_name, {LOCAL_START_TIME} as local_start_time, duration
+                    from usage
+                    where start_time >= ?3 and start_time < ?4
+            )
+            where local_start_time >= ?1 and local_start_time < ?5
+            group by app_name, day"
+    ))?;
+    let rows = stmt.query_map(
+        [
+            start_time,
+            DAY_MS,
+            start_time.saturating_sub(DAY_MS),
+            end_time + DAY_MS,
+            end_time,
+        ],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, usize>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        },
+    )?;
+
+    let mut usage: HashMap<String, Vec<u64>> = HashMap::new();
+    for row in rows {
+        let (app_name, day, duration) = row?;
+        usage.entry(app_name).or_insert_with(|| vec![0; days])[day] = duration;
+    }
+    Ok(usage)
+}
+
+/// Returns the usage of an app, or of all apps without one, on each of `days` consecutive
+/// local days starting at `first_day`, oldest first.
+///
+/// Sessions spanning midnight are split between the days in proportion to how much of their
+/// local time fell on each. A session during which the clocks changed is still counted with
+/// exactly its duration, just split a little unevenly.
+pub fn get_daily_totals(
+    conn: &Connection,
+    app_name: Option<&str>,
+    first_day: NaiveDate,
+    days: usize,
+) -> Result<Vec<u64>, rusqlite::Error> {
+    let start_time = local_midnight(first_day);
+    let end_time = start_time + days as u64 * DAY_MS;
+    let mut stmt = conn.prepare(&format!(
+        "select {LOCAL_START_TIME}, {LOCAL_END_TIME}, duration
+            from usage
+            where (?3 is null or app_name == ?3) and end_time > ?1 and start_time < ?2"
+    ))?;
+    let sessions = stmt.query_map(
+        params![
+            start_time.saturating_sub(DAY_MS),
+            end_time + DAY_MS,
+            app_name
+        ],
+        |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        },
+    )?;
+
+    let mut totals = vec![0; days];
+    let day_of = |time: u64| ((time - start_time) / DAY_MS) as usize;
+    for session in sessions {
+        let (session_start, session_end, duration) = session?;
+        // the clocks going back can make a short session end before it started
+        let span = session_end.saturating_sub(session_start);
+        if span == 0 {
+            if (start_time..end_time).contains(&session_start) {
+                totals[day_of(session_start)] += duration;
+            }
+            continue;
+        }
+
+        let mut time = session_start.max(start_time);
+        let until = session_end.min(end_time);
+        while time < until {
+            let day = day_of(time);
+            let next = (start_time + (day as u64 + 1) * DAY_MS).min(until);
+            totals[day] += (next - time) * duration / span;
+            time = next;
+        }
+    }
+    Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_apps_searches_sessions_by_time() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        conn.execute_batch(
+            "insert into app_usage (app_name, start_time, end_time, duration) values
+                ('firefox', 1000, 3000, 2000),
+                ('foot', 5000, 9000, 4000);",
+        )
+        .unwrap();
+
+        // a scan reads every session ever recorded to list a single day
+        let plan = conn
+            .prepare(&format!("explain query plan {}", apps_in_range()))
+            .unwrap()
+            .query_map([2000, 7000], |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(
+            plan.iter()
+                .any(|x| x.starts_with("SEARCH main.app_usage USING INDEX")),
+            "{plan:?}"
+        );
+        assert!(
+            !plan.iter().any(|x| x.starts_with("SCAN main.app_usage")),
+            "{plan:?}"
+        );
+
+        assert_eq!(
+            list_apps(&conn, Some((2000, 7000))).unwrap(),
+            vec![("foot".to_string(), 2000), ("firefox".to_string(), 1000)]
+        );
+    }
+
+    #[test]
+    fn sessions_are_split_at_midnight() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        let hour = 3_600_000;
+        let yesterday = (0, DAY_MS);
+        let today = (DAY_MS, 2 * DAY_MS);
+        // 23:00 to 02:00, and one that lasted an hour but was suspended for another 90 minutes
+        conn.execute(
+            "insert into app_usage (app_name, start_time, end_time, duration) values
+                ('firefox', ?1, ?2, ?3),
+                ('foot', ?4, ?5, ?6)",
+            params![
+                DAY_MS - hour,
+                DAY_MS + 2 * hour,
+                3 * hour,
+                DAY_MS - hour / 2,
+                DAY_MS + 2 * hour,
+                hour
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            list_apps(&conn, Some(yesterday)).unwrap(),
+            vec![
+                ("firefox".to_string(), hour),
+                ("foot".to_string(), hour / 2)
+            ]
+        );
+        assert_eq!(
+            list_apps(&conn, Some(today)).unwrap(),
+            vec![
+                ("firefox".to_string(), 2 * hour),
+                ("foot".to_string(), hour)
+            ]
+        );
+        assert_eq!(get_data_for_time(&conn, today).unwrap(), 3 * hour);
+        assert_eq!(
+            get_data_for_app_and_time(&conn, "firefox".to_string(), yesterday).unwrap(),
+            hour
+        );
+        // both days together still count each session once
+        assert_eq!(get_data_for_time(&conn, (0, 2 * DAY_MS)).unwrap(), 4 * hour);
+    }
+
+    #[test]
+    fn daily_totals_split_sessions_between_days() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        let hour = 3_600_000;
+        // 23:00 to 02:00, and 23:30 to 02:00 with only an hour of it used, in UTC
+        conn.execute(
+            "insert into app_usage (app_name, start_time, end_time, duration, start_utc_offset, end_utc_offset) values
+                ('firefox', ?1, ?2, ?3, 0, 0),
+                ('foot', ?4, ?5, ?6, 0, 0)",
+            params![
+                DAY_MS - hour,
+                DAY_MS + 2 * hour,
+                3 * hour,
+                DAY_MS - hour / 2,
+                DAY_MS + 2 * hour,
+                hour
+            ],
+        )
+        .unwrap();
+
+        let first_day = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        assert_eq!(
+            get_daily_totals(&conn, Some("firefox"), first_day, 3).unwrap(),
+            [hour, 2 * hour, 0]
+        );
+        // the hour of foot is spread evenly over the two and a half it spans
+        assert_eq!(
+            get_daily_totals(&conn, None, first_day, 3).unwrap(),
+            [hour + hour / 5, 2 * hour + 4 * hour / 5, 0]
+        );
+        assert_eq!(
+            get_daily_totals(&conn, Some("mpv"), first_day, 2).unwrap(),
+            [0, 0]
+        );
+    }
+
+    #[test]
+    fn days_follow_the_recorded_utc_offset() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        let hour = 3_600_000;
+        conn.execute(
+            "insert into app_usage (app_name, start_time, end_time, duration, start_utc_offset, end_utc_offset) values
+                ('firefox', ?1, ?2, ?3, 7200, 3600),
+                ('foot', ?4, ?5, ?6, -18000, -18000)",
+            params![
+                // 23:00 to 04:00 local time across the clocks going back from +2 to +1 at
+                // 03:00, which took 6 hours but only 5 on the clock
+                DAY_MS - 3 * hour,
+                DAY_MS + 3 * hour,
+                6 * hour,
+                // 02:00 UTC is still the evening before at -5
+                DAY_MS + 2 * hour,
+                DAY_MS + 3 * hour,
+                hour
+            ],
+        )
+        .unwrap();
+
+        let first_day = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let firefox = get_daily_totals(&conn, Some("firefox"), first_day, 2).unwrap();
+        // neither the repeated hour is counted twice nor is any time lost
+        assert_eq!(firefox.iter().sum::<u64>(), 6 * hour);
+        assert_eq!(firefox, [6 * hour / 5, 24 * hour / 5]);
+        assert_eq!(
+            get_daily_totals(&conn, Some("foot"), first_day, 2).unwrap(),
+            [hour, 0]
+        );
+
+        let per_app = get_daily_usage_per_app(&conn, first_day, 2).unwrap();
+        assert_eq!(per_app["firefox"], [6 * hour, 0]);
+        assert_eq!(per_app["foot"], [hour, 0]);
+    }
+
+    #[test]
+    fn sessions_are_spread_over_hours() {
+        let hour = 3_600_000;
+        let utc = |time: u64| (((time / hour) % 24) as usize, time % hour);
+
+        // 22:30 to 01:15 the next day, and half an hour with half of it suspended
+        let totals = spread_over_hours(
+            &[
+                (
+                    22 * hour + hour / 2,
+                    DAY_MS + hour + hour / 4,
+                    2 * hour + 3 * hour / 4,
+                ),
+                (DAY_MS + 5 * hour, DAY_MS + 5 * hour + hour / 2, hour / 4),
+            ],
+            utc,
+        );
+
+        let mut expected = [0; 24];
+        expected[22] = hour / 2;
+        expected[23] = hour;
+        expected[0] = hour;
+        expected[1] = hour / 4;
+        expected[5] = hour / 4;
+        assert_eq!(totals, expected);
+    }
+
+    #[test]
+    fn weekday_totals_split_sessions_at_midnight() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        let local = |day, hour| {
+            let time = NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap();
+            Local
+                .from_local_datetime(&time)
+                .earliest()
+                .unwrap()
+                .timestamp_millis() as u64
+        };
+        // monday 23:00 to tuesday 01:00
+        let (start, midnight, end) = (local(1, 23), local(2, 0), local(2, 1));
+        conn.execute(
+            "insert into app_usage (app_name, start_time, end_time, duration)
+                values ('firefox', ?1, ?2, ?3)",
+            [start, end, end - start],
+        )
+        .unwrap();
+
+        let totals = get_weekday_totals(&conn, "firefox").unwrap();
+        assert_eq!(totals[1], midnight - start);
+        assert_eq!(totals[2], end - midnight);
+        assert_eq!(totals.iter().sum::<u64>(), end - start);
+    }
+
+    #[test]
+    fn weekday_totals_add_up_each_weekday() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        let noon = |day| {
+            let time = NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap();
+            Local
+                .from_local_datetime(&time)
+                .earliest()
+                .unwrap()
+                .timestamp_millis() as u64
+        };
+        // an hour on monday, two half hours on wednesday, and another app on friday
+        let hour = 3_600_000;
+        for (app, start, duration) in [
+            ("firefox", noon(1), hour),
+            ("firefox", noon(3), hour / 2),
+            ("firefox", noon(3) + hour, hour / 2),
+            ("foot", noon(5), hour),
+        ] {
+            conn.execute(
+                "insert into app_usage (app_name, start_time, end_time, duration)
+                    values (?1, ?2, ?3, ?4)",
+                (app, start, start + duration, duration),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            get_weekday_totals(&conn, "firefox").unwrap(),
+            [0, hour, 0, hour, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn sessions_close_together_are_merged() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        // half a second between the first two, one and a half before the third
+        conn.execute_batch(
+            "insert into app_usage (app_name, start_time, end_time, duration) values
+                ('firefox', 0, 1000, 1000),
+                ('firefox', 1500, 2000, 500),
+                ('firefox', 3500, 4000, 500),
+                ('foot', 100, 300, 200);",
+        )
+        .unwrap();
+
+        let lengths = get_merged_sessions(&conn, 1000, None)
+            .unwrap()
+            .into_iter()
+            .map(|(app_name, start, end)| (app_name, end - start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lengths,
+            vec![
+                ("firefox".to_string(), 2000),
+                ("firefox".to_string(), 500),
+                ("foot".to_string(), 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn titles_are_summed_per_app() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        conn.execute_batch(
+            "insert into app_usage (app_name, title, start_time, end_time, duration) values
+                ('firefox', 'Inbox', 1000, 2000, 1000),
+                ('firefox', 'News', 3000, 7000, 4000),
+                ('firefox', 'Inbox', 7000, 9000, 2000),
+                ('firefox', null, 9000, 10000, 1000),
+                ('foot', 'Inbox', 1000, 2000, 1000);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_titles_for_app(&conn, "firefox", None).unwrap(),
+            vec![("News".to_string(), 4000), ("Inbox".to_string(), 3000)]
+        );
+        // only the part of a session inside the range counts
+        assert_eq!(
+            get_titles_for_app(&conn, "firefox", Some((0, 5000))).unwrap(),
+            vec![("News".to_string(), 2000), ("Inbox".to_string(), 1000)]
+        );
+    }
+
+    #[test]
+    fn usage_is_split_by_output() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        conn.execute_batch(
+            "insert into app_usage (app_name, output, start_time, end_time, duration) values
+                ('firefox', 'DP-1', 1000, 2000, 1000),
+                ('firefox', 'HDMI-A-1', 3000, 6000, 3000),
+                ('firefox', 'DP-1', 7000, 8000, 1000),
+                ('firefox', null, 9000, 10000, 1000);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_app_usage_by_output(&conn, "firefox", None).unwrap(),
+            vec![("HDMI-A-1".to_string(), 3000), ("DP-1".to_string(), 2000)]
+        );
+        assert_eq!(
+            get_app_usage_by_output(&conn, "firefox", Some((0, 3000))).unwrap(),
+            vec![("DP-1".to_string(), 1000)]
+        );
+    }
+
+    #[test]
+    fn all_time_includes_pruned_usage() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        conn.execute_batch(
+            "insert into app_usage (app_name, start_time, end_time, duration) values
+                ('firefox', 1000, 2000, 1000),
+                ('firefox', 5000, 6000, 1000);",
+        )
+        .unwrap();
+
+        appusage_core::retention::prune_older_than(&conn, 3000, true).unwrap();
+
+        assert_eq!(
+            list_apps(&conn, None).unwrap(),
+            vec![("firefox".to_string(), 2000)]
+        );
+        assert_eq!(
+            list_apps(&conn, Some((0, 10_000))).unwrap(),
+            vec![("firefox".to_string(), 1000)]
+        );
+        assert_eq!(
+            get_total_app_usage(&conn, "firefox".to_string()).unwrap(),
+            2000
+        );
+    }
+
+    #[test]
+    fn rollup_matches_sessions() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+
+        let tx = conn.unchecked_transaction().unwrap();
+        {
+            let mut stmt = tx
+                .prepare(
+                    "insert into app_usage (app_name, start_time, end_time, duration) values (?1, ?2, ?3, ?4)",
+                )
+                .unwrap();
+            // a row every 7 hours for about two months, so days aren't all alike
+            for i in 0..200_u64 {
+                let start_time = i * 25_200_000;
+                let duration = 60_000 + i * 1000;
+                stmt.execute(params![
+                    format!("app{}", i % 7),
+                    start_time,
+                    start_time + duration,
+                    duration
+                ])
+                .unwrap();
+            }
+        }
+        tx.commit().unwrap();
+        appusage_core::retention::prune_older_than(&conn, 3 * DAY_MS, true).unwrap();
+
+        // none of the sessions cross local midnight, which the rollup doesn't split them at
+        let midnight = |month, day| {
+            Local
+                .with_ymd_and_hms(1970, month, day, 0, 0, 0)
+                .earliest()
+                .unwrap()
+                .timestamp_millis() as u64
+        };
+        let month = (midnight(1, 11), midnight(2, 8));
+        assert_eq!(
+            list_apps_rollup(&conn, Some(month)).unwrap(),
+            list_apps(&conn, Some(month)).unwrap()
+        );
+        assert_eq!(
+            list_apps_rollup(&conn, None).unwrap(),
+            list_apps(&conn, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn sessions_are_counted_and_averaged() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        for (start_time, duration) in [(0, 60_000), (100_000, 30_000), (200_000, 10_000)] {
+            conn.execute(
+                "insert into app_usage (app_name, start_time, end_time, duration) values ('foot', ?1, ?2, ?3)",
+                params![start_time, start_time + duration, duration],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(get_session_count(&conn, "foot", None).unwrap(), 3);
+        assert_eq!(
+            get_average_session_length(&conn, "foot", None).unwrap(),
+            Some(33_333)
+        );
+        // only the part of the first session inside the range counts
+        let range = Some((30_000, 150_000));
+        assert_eq!(get_session_count(&conn, "foot", range).unwrap(), 2);
+        assert_eq!(
+            get_average_session_length(&conn, "foot", range).unwrap(),
+            Some(30_000)
+        );
+        assert_eq!(get_session_count(&conn, "mpv", None).unwrap(), 0);
+        assert_eq!(
+            get_average_session_length(&conn, "mpv", None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn tracked_time_is_summed_with_active_days() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        assert_eq!(get_tracked_summary(&conn, None).unwrap(), (0, 0));
+
+        // offsets are given so the days don't depend on the time zone the test runs in
+        for (app_name, start_time, duration) in [
+            ("foot", DAY_MS + 1000, 60_000),
+            ("firefox", DAY_MS + 100_000, 30_000),
+            ("foot", 3 * DAY_MS, 10_000),
+        ] {
+            conn.execute(
+                "insert into app_usage (app_name, start_time, end_time, duration, start_utc_offset, end_utc_offset)
+                    values (?1, ?2, ?3, ?4, 0, 0)",
+                params![app_name, start_time, start_time + duration, duration],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(get_tracked_summary(&conn, None).unwrap(), (100_000, 2));
+        assert_eq!(
+            get_tracked_summary(&conn, Some((DAY_MS, 2 * DAY_MS))).unwrap(),
+            (90_000, 1)
+        );
+    }
+
+    #[test]
+    fn breakdowns_cover_all_time_without_a_range() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        conn.execute(
+            "insert into app_usage (app_name, title, output, start_time, end_time, duration)
+                values ('firefox', 'Inbox', 'DP-1', 1000, 3000, 2000)",
+            (),
+        )
+        .unwrap();
+
+        let expected = |name: &str| vec![(name.to_string(), 2000)];
+        assert_eq!(
+            get_titles_for_app(&conn, "firefox", None).unwrap(),
+            expected("Inbox")
+        );
+        assert_eq!(
+            get_app_usage_by_source(&conn, "firefox", None).unwrap(),
+            expected("test")
+        );
+        assert_eq!(
+            get_app_usage_by_output(&conn, "firefox", None).unwrap(),
+            expected("DP-1")
+        );
+    }
+
+    #[test]
+    fn apps_with_nothing_in_range_are_listed_without_failing() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        // one only used before the range, the other only by a row ending before it starts
+        conn.execute_batch(
+            "insert into app_usage (app_name, start_time, end_time, duration) values
+                ('foot', 1000, 2000, 1000),
+                ('broken', 8000, 6000, 2000),
+                ('firefox', 5000, 7000, 2000);",
+        )
+        .unwrap();
+
+        let range = Some((5000, 10_000));
+        let expected = [("firefox".to_string(), 2000), ("broken".to_string(), 0)];
+        assert_eq!(list_apps(&conn, range).unwrap(), expected);
+    }
+
+    #[test]
+    fn stats_cover_sessions_and_the_archive() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        assert_eq!(
+            get_stats(&conn).unwrap(),
+            Stats {
+                busiest_day: None,
+                longest_session: None,
+                first_seen: None,
+            }
+        );
+
+        // offsets are given so the days don't depend on the time zone the test runs in
+        for (app_name, start_time, duration) in [
+            ("foot", 10 * DAY_MS + 1000, 3_000_000),
+            ("firefox", 10 * DAY_MS + 5_000_000, 2_000_000),
+            ("mpv", 11 * DAY_MS, 4_000_000),
+        ] {
+            conn.execute(
+                "insert into app_usage (app_name, start_time, end_time, duration, start_utc_offset, end_utc_offset)
+                    values (?1, ?2, ?3, ?4, 0, 0)",
+                params![app_name, start_time, start_time + duration, duration],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "insert into app_usage_archive (app_name, day, duration) values ('steam', 2, 1000)",
+            (),
+        )
+        .unwrap();
+
+        let day = |n| NaiveDate::from_ymd_opt(1970, 1, 1).unwrap() + chrono::Days::new(n);
+        assert_eq!(
+            get_stats(&conn).unwrap(),
+            Stats {
+                busiest_day: Some((day(10), 5_000_000)),
+                longest_session: Some(("mpv".to_string(), 11 * DAY_MS, 4_000_000)),
+                first_seen: Some(2 * DAY_MS),
+            }
+        );
+    }
+
+    #[test]
+    fn focus_switches_are_listed_per_day() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        let first_day = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        assert_eq!(get_focus_switches(&conn, first_day, 3).unwrap(), [0, 0, 0]);
+
+        let first = local_midnight(first_day) / DAY_MS;
+        for (day, count) in [(first - 1, 7), (first, 12), (first + 2, 30), (first + 3, 1)] {
+            conn.execute(
+                "insert into focus_switches (day, count) values (?1, ?2)",
+                [day, count],
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            get_focus_switches(&conn, first_day, 3).unwrap(),
+            [12, 0, 30]
+        );
+    }
+}
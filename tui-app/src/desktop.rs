@@ -0,0 +1,143 @@
+//! Looks up the desktop entries of apps to find friendlier names and icons than their app id.
+
+use std::{collections::HashMap, fs, path::Path};
+
+/// What the desktop entry of an app says about it, empty if there is no matching entry.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AppMeta {
+    pub name: Option<String>,
+    pub icon: Option<String>,
+}
+
+#[derive(Debug)]
+struct DesktopEntry {
+    /// File name without the `.desktop` extension.
+    id: String,
+    name: Option<String>,
+    icon: Option<String>,
+    wm_class: Option<String>,
+}
+
+/// Resolves app ids to desktop entries, remembering every result.
+///
+/// The desktop entries are only read on the first lookup.
+#[derive(Default)]
+pub struct Resolver {
+    entries: Option<Vec<DesktopEntry>>,
+    cache: HashMap<String, AppMeta>,
+}
+
+impl Resolver {
+    /// Finds the desktop entry for `app_id`, trying an exact match of the file name first,
+    /// then a case insensitive match of the file name or `StartupWMClass`, and finally a
+    /// match after dropping reverse-DNS prefixes and common suffixes like `-bin`.
+    pub fn resolve_app(&mut self, app_id: &str) -> AppMeta {
+        if let Some(meta) = self.cache.get(app_id) {
+            return meta.clone();
+        }
+
+        let entries = self.entries.get_or_insert_with(load_entries);
+
+        let entry = entries
+            .iter()
+            .find(|entry| entry.id == app_id)
+            .or_else(|| {
+                entries.iter().find(|entry| {
+                    entry.id.eq_ignore_ascii_case(app_id)
+                        || entry
+                            .wm_class
+                            .as_ref()
+                            .is_some_and(|wm_class| wm_class.eq_ignore_ascii_case(app_id))
+                })
+            })
+            .or_else(|| {
+                let normalized = normalize(app_id);
+                entries
+                    .iter()
+                    .find(|entry| normalize(&entry.id) == normalized)
+            });
+
+        let meta = entry
+            .map(|entry| AppMeta {
+                name: entry.name.clone(),
+                icon: entry.icon.clone(),
+            })
+            .unwrap_or_default();
+
+        self.cache.insert(app_id.to_string(), meta.clone());
+        meta
+    }
+}
+
+/// Reduces an id to its most distinctive part, `org.mozilla.firefox-bin` becomes `firefox`.
+fn normalize(id: &str) -> String {
+    let id = id.to_lowercase();
+    let last = id.rsplit('.').next().unwrap_or(&id);
+
+    ["-bin", "-wayland", "-desktop", "-stable"]
+        .iter()
+        .find_map(|suffix| last.strip_suffix(suffix))
+        .unwrap_or(last)
+        .to_string()
+}
+
+/// Reads every desktop entry in the XDG data directories, earlier directories take
+/// precedence like they do for the desktop itself.
+fn load_entries() -> Vec<DesktopEntry> {
+    let Ok(dirs) = xdg::BaseDirectories::new() else {
+        return vec![];
+    };
+
+    let mut entries: Vec<DesktopEntry> = vec![];
+    for path in dirs.list_data_files("applications") {
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "desktop")
+        {
+            continue;
+        }
+        let Some(entry) = parse_entry(&path) else {
+            continue;
+        };
+        if !entries.iter().any(|existing| existing.id == entry.id) {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+fn parse_entry(path: &Path) -> Option<DesktopEntry> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut entry = DesktopEntry {
+        id: path.file_stem()?.to_string_lossy().into_owned(),
+        name: None,
+        icon: None,
+        wm_class: None,
+    };
+
+    // only the main group is interesting, actions have names of their own
+    let mut in_main_group = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_main_group = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_main_group {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = Some(value.trim().to_string());
+        // localized keys like `Name[de]` don't match here and are skipped
+        match key.trim() {
+            "Name" => entry.name = value,
+            "Icon" => entry.icon = value,
+            "StartupWMClass" => entry.wm_class = value,
+            _ => {}
+        }
+    }
+
+    Some(entry)
+}
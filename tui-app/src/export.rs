@@ -0,0 +1,203 @@
+//! Writes the per-app totals of a time range as JSON or a plain table, for scripts and quick
+//! looks that want the numbers without opening the tui.
+
+use std::{error::Error, fmt::Write as _, time};
+
+use clap::ValueEnum;
+use rusqlite::Connection;
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    AppListTime,
+    category::{self, Categories},
+    db,
+};
+
+/// The time ranges of the app list, as they are given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Range {
+    Today,
+    /// Since the start of the week.
+    Week,
+    /// Today and the 6 days before it.
+    #[value(name = "7d")]
+    #[serde(rename = "7d")]
+    Last7Days,
+    /// Since the 1st of the month.
+    Month,
+    /// Today and the 27 days before it.
+    #[value(name = "28d")]
+    #[serde(rename = "28d")]
+    Last28Days,
+    All,
+}
+
+impl From<Range> for AppListTime {
+    fn from(range: Range) -> Self {
+        match range {
+            Range::Today => AppListTime::Today,
+            Range::Week => AppListTime::ThisWeek,
+            Range::Last7Days => AppListTime::Last7Days,
+            Range::Month => AppListTime::ThisMonth,
+            Range::Last28Days => AppListTime::Last28Days,
+            Range::All => AppListTime::AllTime,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Export {
+    pub range: Range,
+    /// Start of the range in ms since the epoch, unset for all time.
+    pub start_ms: Option<u64>,
+    /// End of the range (exclusive) in ms since the epoch, unset for all time.
+    pub end_ms: Option<u64>,
+    pub total_ms: u64,
+    /// Most used first.
+    pub apps: Vec<AppTotal>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct AppTotal {
+    pub app_name: String,
+    pub total_ms: u64,
+    /// The total formatted like the tui shows it, e.g. `1h 5m 3s`.
+    pub human: String,
+}
+
+impl Export {
+    pub fn new(range: Range, apps: Vec<(String, u64)>) -> Self {
+        let (start_ms, end_ms) = AppListTime::from(range).timestamps().unzip();
+        Self {
+            range,
+            start_ms,
+            end_ms,
+            total_ms: apps.iter().map(|(_, total)| total).sum(),
+            apps: apps
+                .into_iter()
+                .map(|(app_name, total_ms)| AppTotal {
+                    app_name,
+                    total_ms,
+                    human: humantime::format_duration(time::Duration::from_secs(total_ms / 1000))
+                        .to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Prints the totals of every app within `range` to stdout.
+pub fn print_json(conn: &Connection, range: Range) -> Result<(), Box<dyn Error>> {
+    let apps = db::list_apps(conn, AppListTime::from(range).timestamps())?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Export::new(range, apps))?
+    );
+    Ok(())
+}
+
+/// Prints the `limit` most used apps within `range` to stdout, as a table unless `json` is
+/// set. The total still covers every app.
+///
+/// With `categories` the categories are listed instead of the apps.
+pub fn print_top(
+    conn: &Connection,
+    range: Range,
+    limit: usize,
+    json: bool,
+    categories: Option<&Categories>,
+) -> Result<(), Box<dyn Error>> {
+    let time_range = AppListTime::from(range).timestamps();
+    let apps = match categories {
+        Some(categories) => category::list_categories(conn, categories, time_range)?,
+        None => db::list_apps(conn, time_range)?,
+    };
+    let mut export = Export::new(range, apps);
+    export.apps.truncate(limit);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&export)?);
+    } else {
+        print!("{}", format_table(&export));
+    }
+    Ok(())
+}
+
+/// One app per line with the durations lined up, followed by the total.
+fn format_table(export: &Export) -> String {
+    let width = export
+        .apps
+        .iter()
+        .map(|x| x.app_name.width())
+        .max()
+        .unwrap_or(0)
+        .max("total".len());
+
+    let mut out = String::new();
+    for app in &export.apps {
+        let padding = width - app.app_name.width();
+        let _ = writeln!(
+            out,
+            "{}{}  {}",
+            app.app_name,
+            " ".repeat(padding),
+            app.human
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{:width$}  {}",
+        "total",
+        humantime::format_duration(time::Duration::from_secs(export.total_ms / 1000))
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_lines_up_durations() {
+        let mut export = Export::new(
+            Range::Today,
+            vec![
+                ("firefox".to_string(), 3_903_500),
+                ("foot".to_string(), 60_000),
+                ("mpv".to_string(), 1_000),
+            ],
+        );
+        export.apps.truncate(2);
+
+        assert_eq!(
+            format_table(&export),
+            "firefox  1h 5m 3s\nfoot     1m\ntotal    1h 6m 4s\n"
+        );
+    }
+
+    #[test]
+    fn export_has_totals_and_formatted_durations() {
+        let export = Export::new(
+            Range::All,
+            vec![
+                ("firefox".to_string(), 3_903_500),
+                ("foot".to_string(), 60_000),
+            ],
+        );
+
+        assert_eq!(
+            serde_json::to_value(&export).unwrap(),
+            serde_json::json!({
+                "range": "all",
+                "start_ms": null,
+                "end_ms": null,
+                "total_ms": 3_963_500,
+                "apps": [
+                    {"app_name": "firefox", "total_ms": 3_903_500, "human": "1h 5m 3s"},
+                    {"app_name": "foot", "total_ms": 60_000, "human": "1m"},
+                ],
+            })
+        );
+    }
+}
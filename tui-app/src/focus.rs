@@ -0,0 +1,46 @@
+//! Asks the daemon which app is focused right now, over the D-Bus interface it serves when
+//! it was built with the `dbus` feature.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SERVICE_NAME: &str = "org.wayland_appusage.Daemon";
+const OBJECT_PATH: &str = "/org/wayland_appusage/Usage";
+const INTERFACE_NAME: &str = "org.wayland_appusage.Usage";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Focus {
+    pub app_id: String,
+    pub since: SystemTime,
+}
+
+/// The daemon's end of the session bus, connected on first use.
+#[derive(Debug, Default)]
+pub struct Daemon {
+    conn: Option<zbus::blocking::Connection>,
+}
+
+impl Daemon {
+    /// Returns the focused app, or `None` if nothing is focused or the daemon can't be asked.
+    pub fn current_focus(&mut self) -> Option<Focus> {
+        if self.conn.is_none() {
+            self.conn = zbus::blocking::Connection::session().ok();
+        }
+        let reply = self
+            .conn
+            .as_ref()?
+            .call_method(
+                Some(SERVICE_NAME),
+                OBJECT_PATH,
+                Some(INTERFACE_NAME),
+                "CurrentFocus",
+                &(),
+            )
+            .ok()?;
+        let (app_id, since) = reply.body().deserialize::<(String, u64)>().ok()?;
+
+        (!app_id.is_empty()).then(|| Focus {
+            app_id,
+            since: UNIX_EPOCH + Duration::from_millis(since),
+        })
+    }
+}
@@ -0,0 +1,222 @@
+//! Groups web apps by the site they belong to.
+//!
+//! Chromium based browsers give every installed web app its own app id with the site's domain
+//! in it (`chrome-www.youtube.com__-Default`), rules extract that domain so all apps of one
+//! site, across profiles and paths, end up in a single bucket. Other browsers only name the
+//! site in their window titles, so the usage of a browser can be split by those too.
+//!
+//! Rules are regular expressions with a `domain` capture group. Extra ones can be added in
+//! the `[web_apps]` section of `config.toml`, `rules` are matched against app ids and tried
+//! before the built in ones, `title_rules` against the window titles of a browser's app id.
+
+use std::{collections::HashMap, error::Error};
+
+use appusage_core::config::WebAppConfig;
+use regex::Regex;
+
+/// Matches the web app ids of Chromium, Chrome, Edge, Brave and Vivaldi.
+const DEFAULT_RULES: [&str; 1] = [r"^(?:chrome|chromium|msedge|brave|vivaldi)-(?P<domain>[^_]+)__"];
+
+pub struct DomainRules {
+    rules: Vec<Regex>,
+    /// Rules for the window titles of each browser, by app id.
+    title_rules: HashMap<String, Vec<Regex>>,
+}
+
+impl Default for DomainRules {
+    /// Only the built in rules.
+    fn default() -> Self {
+        Self {
+            rules: DEFAULT_RULES
+                .iter()
+                .map(|rule| Regex::new(rule).expect("built in rules are valid"))
+                .collect(),
+            title_rules: HashMap::new(),
+        }
+    }
+}
+
+impl DomainRules {
+    /// Compiles the configured rules, the app id ones followed by the built in ones.
+    pub fn from_config(config: &WebAppConfig) -> Result<Self, Box<dyn Error>> {
+        let rules = config
+            .rules
+            .iter()
+            .map(String::as_str)
+            .chain(DEFAULT_RULES)
+            .map(compile)
+            .collect::<Result<_, _>>()?;
+        let title_rules = config
+            .title_rules
+            .iter()
+            .map(|(browser, rules)| {
+                let rules = rules
+                    .iter()
+                    .map(|rule| compile(rule))
+                    .collect::<Result<_, _>>()?;
+                Ok((browser.clone(), rules))
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+        Ok(Self { rules, title_rules })
+    }
+
+    /// Returns the domain of the site a web app belongs to, without a leading `www.`.
+    pub fn domain_of(&self, app_id: &str) -> Option<String> {
+        find_domain(&self.rules, app_id)
+    }
+
+    /// Returns the site a window of `browser` titled `title` shows, if that browser has rules.
+    pub fn domain_of_title(&self, browser: &str, title: &str) -> Option<String> {
+        find_domain(self.title_rules.get(browser)?, title)
+    }
+
+    /// Merges the usage of all apps belonging to the same site, sorted by duration again.
+    ///
+    /// The usage of browsers with title rules is split by site too, `titles` returns how
+    /// long each window title of a browser was used. Whatever doesn't match stays with the
+    /// browser.
+    ///
+    /// Returns the merged list along with the entries that went into each merged entry, apps
+    /// that aren't web apps are kept as they are.
+    pub fn group(
+        &self,
+        items: &[(String, u64)],
+        mut titles: impl FnMut(&str) -> Vec<(String, u64)>,
+    ) -> (Vec<(String, u64)>, HashMap<String, Vec<String>>) {
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        let mut members: HashMap<String, Vec<String>> = HashMap::new();
+        let mut add = |label: String, app_id: &str, duration| {
+            *totals.entry(label.clone()).or_default() += duration;
+            let names = members.entry(label).or_default();
+            if !names.iter().any(|name| name == app_id) {
+                names.push(app_id.to_string());
+            }
+        };
+
+        for (app_id, duration) in items {
+            if let Some(domain) = self.domain_of(app_id) {
+                add(domain, app_id, *duration);
+                continue;
+            }
+
+            let mut rest = *duration;
+            if self.title_rules.contains_key(app_id) {
+                for (title, duration) in titles(app_id) {
+                    if let Some(domain) = self.domain_of_title(app_id, &title) {
+                        let duration = duration.min(rest);
+                        rest -= duration;
+                        add(domain, app_id, duration);
+                    }
+                }
+            }
+            // browsers only showing sites are left out
+            if rest > 0 || rest == *duration {
+                add(app_id.clone(), app_id, rest);
+            }
+        }
+
+        let mut grouped = totals.into_iter().collect::<Vec<_>>();
+        grouped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        (grouped, members)
+    }
+}
+
+/// Compiles a rule, making sure it has a `domain` capture group.
+fn compile(rule: &str) -> Result<Regex, Box<dyn Error>> {
+    let regex = Regex::new(rule).map_err(|e| format!("invalid web app rule: {e}"))?;
+    if !regex.capture_names().any(|name| name == Some("domain")) {
+        return Err(format!("web app rule `{rule}` has no `domain` capture group").into());
+    }
+    Ok(regex)
+}
+
+/// Returns the domain the first matching rule captures, without a leading `www.`.
+fn find_domain(rules: &[Regex], text: &str) -> Option<String> {
+    rules.iter().find_map(|rule| {
+        let domain = rule.captures(text)?.name("domain")?.as_str().trim();
+        Some(domain.strip_prefix("www.").unwrap_or(domain).to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compiles the given app id rules and the title rules of a single browser.
+    fn rules(
+        app_id_rules: &[&str],
+        browser: &str,
+        title_rules: &[&str],
+    ) -> Result<DomainRules, Box<dyn Error>> {
+        let mut config = WebAppConfig::default();
+        config.rules = app_id_rules.iter().map(|rule| rule.to_string()).collect();
+        config.title_rules.insert(
+            browser.to_string(),
+            title_rules.iter().map(|rule| rule.to_string()).collect(),
+        );
+        DomainRules::from_config(&config)
+    }
+
+    #[test]
+    fn rules_are_read_along_with_the_built_in_ones() {
+        let rules = rules(&["^kiosk-(?P<domain>.+)$"], "firefox", &[]).unwrap();
+
+        assert_eq!(
+            rules.domain_of("kiosk-github.com").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            rules
+                .domain_of("chrome-www.youtube.com__-Default")
+                .as_deref(),
+            Some("youtube.com")
+        );
+        assert_eq!(rules.domain_of("foot"), None);
+        assert_eq!(DomainRules::default().domain_of("kiosk-github.com"), None);
+    }
+
+    #[test]
+    fn rules_without_a_domain_are_rejected() {
+        assert!(rules(&["^kiosk-"], "firefox", &[]).is_err());
+        assert!(rules(&["(?P<domain>"], "firefox", &[]).is_err());
+        assert!(rules(&[], "firefox", &["YouTube"]).is_err());
+    }
+
+    #[test]
+    fn browsers_are_split_by_the_sites_in_their_titles() {
+        let rules = rules(
+            &[],
+            "firefox",
+            &["[-—] (?P<domain>[^-—]+) — Mozilla Firefox$"],
+        )
+        .unwrap();
+        let items = [
+            ("firefox".to_string(), 10_000),
+            ("foot".to_string(), 3000),
+            ("chrome-github.com__-Default".to_string(), 1000),
+        ];
+        let (grouped, members) = rules.group(&items, |app_id| {
+            assert_eq!(app_id, "firefox");
+            vec![
+                ("Cats - YouTube — Mozilla Firefox".to_string(), 5000),
+                ("Issues - GitHub — Mozilla Firefox".to_string(), 1000),
+                ("Mozilla Firefox".to_string(), 4000),
+            ]
+        });
+
+        assert_eq!(
+            grouped,
+            [
+                ("YouTube".to_string(), 5000),
+                ("firefox".to_string(), 4000),
+                ("foot".to_string(), 3000),
+                ("GitHub".to_string(), 1000),
+                ("github.com".to_string(), 1000),
+            ]
+        );
+        assert_eq!(members["YouTube"], ["firefox"]);
+        assert_eq!(members["github.com"], ["chrome-github.com__-Default"]);
+    }
+}
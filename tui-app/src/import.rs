@@ -0,0 +1,172 @@
+//! Merges the sessions of another database into the first one, for moving the history of one
+//! machine over to another.
+//!
+//! A session counts as already recorded if the first database has one of the same app with
+//! the same start and end, so importing the same database twice, or one that was copied from
+//! the first one at some point, doesn't count anything twice. Only sessions are imported, the
+//! archive of pruned ones, window counts and focus switches stay behind.
+//!
+//! The other database is only read. Sessions from one written by an older version lack what
+//! wasn't recorded back then, like titles.
+
+use std::{error::Error, fs, path::Path};
+
+use rusqlite::Connection;
+
+use crate::db;
+
+/// Imports the sessions of the database at `path` into the main one and prints how many were
+/// new.
+pub fn run(conn: &Connection, path: &Path) -> Result<(), Box<dyn Error>> {
+    if !path.exists() {
+        return Err(format!("database {} does not exist", path.display()).into());
+    }
+    // both paths exist, so they can only differ in how they lead to the same file
+    if let Some(main_path) = conn.path().filter(|x| !x.is_empty()) {
+        if fs::canonicalize(main_path)? == fs::canonicalize(path)? {
+            return Err(format!("can't import {} into itself", path.display()).into());
+        }
+    }
+
+    db::attach_read_only(conn, "import", path)?;
+    let result = import(conn, "import");
+    conn.execute("detach database import", ())?;
+
+    let (imported, skipped) =
+        result.map_err(|e| format!("could not import {}: {e}", path.display()))?;
+    println!(
+        "imported {imported} sessions from {}, skipped {skipped} that were already recorded",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Columns of `app_usage` added after the first version, left empty for sessions of databases
+/// that don't have them yet.
+const OPTIONAL_COLUMNS: [&str; 4] = ["title", "output", "start_utc_offset", "end_utc_offset"];
+
+/// Copies every session of `schema` the main database doesn't have yet in a single
+/// transaction, returning how many were copied and how many were skipped.
+fn import(conn: &Connection, schema: &str) -> Result<(usize, usize), Box<dyn Error>> {
+    let missing = db::missing_columns(conn, schema)?;
+    if !missing.is_empty() {
+        return Err(format!(
+            "it is not an appusage database or is from an incompatible version, it is missing \
+             the columns {}",
+            missing.join(", ")
+        )
+        .into());
+    }
+    let columns = conn
+        .prepare(&format!("pragma {schema}.table_info(app_usage)"))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let optional = OPTIONAL_COLUMNS
+        .map(|column| {
+            if columns.iter().any(|x| x == column) {
+                column
+            } else {
+                "null"
+            }
+        })
+        .join(", ");
+
+    let tx = conn.unchecked_transaction()?;
+    let total = tx.query_row(
+        &format!("select count(*) from {schema}.app_usage"),
+        [],
+        |row| row.get::<_, usize>(0),
+    )?;
+    // sessions recorded twice within the imported database are only taken once as well
+    let imported = tx.execute(
+        &format!(
+            "insert into main.app_usage (app_name, start_time, end_time, duration, title, output, start_utc_offset, end_utc_offset)
+            select app_name, start_time, end_time, duration, {optional}
+            from {schema}.app_usage as imported
+            where imported.rowid in (
+                select min(rowid) from {schema}.app_usage group by app_name, start_time, end_time
+            )
+            and not exists (
+                select 1 from main.app_usage as existing
+                where existing.app_name = imported.app_name
+                    and existing.start_time = imported.start_time
+                    and existing.end_time = imported.end_time
+            )"
+        ),
+        (),
+    )?;
+    tx.commit()?;
+    Ok((imported, total - imported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_new_sessions_are_imported() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        conn.execute_batch(
+            "insert into app_usage (app_name, start_time, end_time, duration) values
+                ('firefox', 1000, 2000, 1000),
+                ('foot', 3000, 5000, 2000);
+            attach database ':memory:' as other;
+            create table other.app_usage (
+                id integer primary key autoincrement,
+                app_name text not null,
+                title text,
+                output text,
+                start_time integer not null,
+                end_time integer not null,
+                duration integer not null,
+                start_utc_offset integer,
+                end_utc_offset integer
+            );
+            insert into other.app_usage (app_name, title, start_time, end_time, duration) values
+                ('firefox', 'Inbox', 1000, 2000, 1000),
+                ('foot', null, 3000, 4000, 1000),
+                ('mpv', 'movie.mkv', 6000, 9000, 3000),
+                ('mpv', 'movie.mkv', 6000, 9000, 3000);",
+        )
+        .unwrap();
+
+        assert_eq!(import(&conn, "other").unwrap(), (2, 2));
+        // everything is there already the second time
+        assert_eq!(import(&conn, "other").unwrap(), (0, 4));
+
+        let daily = conn
+            .query_row("select sum(duration) from app_usage_daily", [], |row| {
+                row.get::<_, u64>(0)
+            })
+            .unwrap();
+        assert_eq!(daily, 7000);
+    }
+
+    #[test]
+    fn sessions_of_older_databases_are_imported() {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        conn.execute_batch(
+            "attach database ':memory:' as other;
+            create table other.app_usage (
+                id integer primary key autoincrement,
+                app_name text not null,
+                start_time integer not null,
+                end_time integer not null,
+                duration integer not null
+            );
+            insert into other.app_usage (app_name, start_time, end_time, duration) values
+                ('firefox', 1000, 2000, 1000);",
+        )
+        .unwrap();
+
+        assert_eq!(import(&conn, "other").unwrap(), (1, 0));
+        let title = conn
+            .query_row("select title from app_usage", [], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .unwrap();
+        assert_eq!(title, None);
+    }
+}
@@ -1,148 +1,1038 @@
-use std::{error::Error, io, time};
+use std::{borrow::Cow, collections::HashMap, error::Error, io, path::PathBuf, time};
 
-use chrono::{Datelike, Local};
+use alias::Aliases;
+use category::Categories;
+use chrono::{
+    DateTime, Datelike, Local, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Weekday,
+};
+use clap::{Parser, Subcommand};
+use grouping::DomainRules;
 use ratatui::{
     DefaultTerminal, Frame,
     buffer::Buffer,
-    crossterm::event::{self, Event, KeyCode, KeyEventKind},
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    crossterm::event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+        MouseButton, MouseEventKind,
+    },
+    layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
     style::{Color, Style, Stylize},
-    text::Text,
+    symbols,
+    text::{Line, Text},
     widgets::{
-        Bar, BarChart, BarGroup, Block, Borders, List, ListItem, ListState, Paragraph, Widget,
+        Axis, Bar, BarChart, BarGroup, Block, Chart, Clear, Dataset, GraphType, List, ListItem,
+        ListState, Paragraph, Sparkline, Widget, Wrap,
     },
 };
 use rusqlite::Connection;
+use streak::Targets;
+use ui_state::UiState;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+mod alias;
+mod cache;
+mod category;
+mod check;
+mod color;
+mod compare;
 mod db;
+mod desktop;
+mod export;
+mod focus;
+mod grouping;
+mod import;
+mod prune;
+mod sessions;
+mod stats;
+mod status;
+mod streak;
+mod timeline;
+mod ui_state;
+mod vacuum;
 
 pub struct App {
     exit: bool,
     connection: Connection,
     app_list: AppList,
+    chart: ChartKind,
+    bar_direction: Direction,
+    /// How many days the bar chart at the top covers.
+    chart_days: u16,
+    /// Text being typed into the prompt at the bottom, if one is open.
+    input: Option<Input>,
+    /// Labels of the databases that were loaded, usage of all of them is combined.
+    sources: Vec<String>,
+    ui_state: UiState,
+    desktop_entries: desktop::Resolver,
+    aliases: Aliases,
+    categories: Categories,
+    domain_rules: DomainRules,
+    /// Daily goals and limits the streaks chart counts days meeting.
+    targets: Targets,
+    /// Sum up the list by category instead of showing every app.
+    by_category: bool,
+    /// Merge web apps of the same site into one entry.
+    group_by_domain: bool,
+    /// Show a sparkline of the past days next to each entry.
+    show_trends: bool,
+    cache: cache::QueryCache,
+    colors: color::Colors,
+    /// Show the selected app's average usage on each weekday in the detail pane.
+    show_weekdays: bool,
+    /// Show how long the selected app was used under each window title in the detail pane.
+    show_titles: bool,
+    /// Show how the selected app's usage spreads over the hours of the day in the detail pane.
+    show_hours: bool,
+    /// Where the session length histogram splits its ranges, in ms and ascending.
+    session_buckets: Vec<u64>,
+    /// Sessions of the same app closer together than this (in ms) count as one in the
+    /// session length histogram.
+    merge_gap: u64,
+    /// How often the data is fetched again to show what the daemon recorded meanwhile.
+    refresh_interval: Option<time::Duration>,
+    last_refresh: time::Instant,
+    daemon: focus::Daemon,
+    /// The app the daemon said is focused, as of the last refresh.
+    focus: Option<focus::Focus>,
+    /// The entry used the most within the current clock hour and for how long (in ms), as of
+    /// the last refresh.
+    this_hour: Option<(String, u64)>,
+}
+
+struct Input {
+    kind: InputKind,
+    text: String,
+    /// Why the text was rejected the last time enter was pressed.
+    error: Option<String>,
+}
+
+enum InputKind {
+    /// Adds the tag to the selected app, or removes it if the app already has it.
+    ToggleTag,
+    /// Narrows down the app list while typing.
+    Filter,
+    /// Shows the app list for the dates in between two dates.
+    DateRange,
+}
+
+/// What the chart at the top of the screen shows.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum ChartKind {
+    #[default]
+    PastWeek,
+    WindowCount,
+    ActiveHours,
+    SessionLengths,
+    Streaks,
+    /// Usage of each entry in the list against the same time range right before it.
+    Comparison,
+}
+
+impl ChartKind {
+    fn next(self) -> Self {
+        match self {
+            ChartKind::PastWeek => ChartKind::WindowCount,
+            ChartKind::WindowCount => ChartKind::ActiveHours,
+            ChartKind::ActiveHours => ChartKind::SessionLengths,
+            ChartKind::SessionLengths => ChartKind::Streaks,
+            ChartKind::Streaks => ChartKind::Comparison,
+            ChartKind::Comparison => ChartKind::PastWeek,
+        }
+    }
 }
 
 struct AppList {
+    /// The entries that are shown, those of `all_items` that match `filter`.
     items: Vec<(String, u64)>,
+    all_items: Vec<(String, u64)>,
+    /// Only entries containing this (ignoring case) are shown.
+    filter: String,
+    sort: SortOrder,
     time_to_show: AppListTime,
     state: ListState,
+    /// App ids that were merged into each entry, by aliases, categories or while grouping by
+    /// domain.
+    members: HashMap<String, Vec<String>>,
+    /// Usage of each app on each of the past [`TREND_DAYS`] days, oldest first.
+    trends: HashMap<String, Vec<u64>>,
+    /// Names from the desktop entries of the entries that have one, resolved whenever the
+    /// list is fetched so drawing never has to look them up.
+    display_names: HashMap<String, String>,
+    /// Show app ids instead of the names from desktop entries.
+    show_app_ids: bool,
+    /// Whether moving past the last entry selects the first one and the other way around.
+    wrap_selection: bool,
+    /// How many entries fit into the list the last time it was drawn, paging moves by that.
+    page_size: usize,
+    /// Where the entries were drawn last time, to tell which one was clicked.
+    area: Rect,
+}
+
+/// How the app list is ordered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    #[default]
+    MostUsed,
+    LeastUsed,
+    NameAscending,
+    NameDescending,
+}
+
+impl SortOrder {
+    fn next(self) -> Self {
+        match self {
+            SortOrder::MostUsed => SortOrder::LeastUsed,
+            SortOrder::LeastUsed => SortOrder::NameAscending,
+            SortOrder::NameAscending => SortOrder::NameDescending,
+            SortOrder::NameDescending => SortOrder::MostUsed,
+        }
+    }
+
+    fn sort(self, items: &mut [(String, u64)]) {
+        let by_name = |a: &(String, u64), b: &(String, u64)| {
+            a.0.to_lowercase()
+                .cmp(&b.0.to_lowercase())
+                .then_with(|| a.0.cmp(&b.0))
+        };
+        match self {
+            SortOrder::MostUsed => items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| by_name(a, b))),
+            SortOrder::LeastUsed => items.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| by_name(a, b))),
+            SortOrder::NameAscending => items.sort_by(by_name),
+            SortOrder::NameDescending => items.sort_by(|a, b| by_name(b, a)),
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SortOrder::MostUsed => "most used",
+            SortOrder::LeastUsed => "least used",
+            SortOrder::NameAscending => "A-Z",
+            SortOrder::NameDescending => "Z-A",
+        })
+    }
+}
+
+/// How many window titles the detail pane lists at most.
+const MAX_TITLES: usize = 10;
+
+/// How many days the sparkline of each list entry covers.
+const TREND_DAYS: usize = 7;
+
+impl AppList {
+    /// Updates `items` after `all_items`, `filter` or `sort` changed.
+    ///
+    /// The selected entry stays selected if it is still shown, otherwise the selection is
+    /// kept within the list.
+    fn update_items(&mut self) {
+        let selected_name = self
+            .state
+            .selected()
+            .and_then(|i| self.items.get(i))
+            .map(|x| x.0.clone());
+
+        self.sort.sort(&mut self.all_items);
+        let filter = self.filter.to_lowercase();
+        self.items = self
+            .all_items
+            .iter()
+            .filter(|(name, _)| {
+                name.to_lowercase().contains(&filter)
+                    || self
+                        .display_names
+                        .get(name)
+                        .is_some_and(|x| x.to_lowercase().contains(&filter))
+            })
+            .cloned()
+            .collect();
+
+        if let Some(position) =
+            selected_name.and_then(|name| self.items.iter().position(|x| x.0 == name))
+        {
+            self.state.select(Some(position));
+        } else if let Some(selected) = self.state.selected() {
+            self.state.select(
+                self.items
+                    .len()
+                    .checked_sub(1)
+                    .map(|last| selected.min(last)),
+            );
+        }
+    }
+
+    fn select_next(&mut self) {
+        match self.state.selected() {
+            // `select_last` leaves an index past the end until the list is drawn
+            Some(i) if self.wrap_selection && i + 1 >= self.items.len() => {
+                self.state.select_first()
+            }
+            _ => self.state.select_next(),
+        }
+    }
+
+    fn select_previous(&mut self) {
+        match self.state.selected() {
+            Some(0) if self.wrap_selection => self.state.select_last(),
+            _ => self.state.select_previous(),
+        }
+    }
+
+    /// Moves the selection by a page, stopping at the ends of the list. Drawing the list
+    /// scrolls it along so the selection stays visible.
+    fn select_page(&mut self, down: bool) {
+        let Some(last) = self.items.len().checked_sub(1) else {
+            return;
+        };
+        let selected = self.state.selected().unwrap_or(0).min(last);
+        self.state.select(Some(if down {
+            (selected + self.page_size).min(last)
+        } else {
+            selected.saturating_sub(self.page_size)
+        }));
+    }
+
+    /// Selects the entry drawn at the cell, if there is one.
+    fn select_at(&mut self, column: u16, row: u16) {
+        if !self.area.contains(Position::new(column, row)) {
+            return;
+        }
+        let i = self.state.offset() + usize::from(row - self.area.y);
+        if i < self.items.len() {
+            self.state.select(Some(i));
+        }
+    }
+
+    /// What to show for the entry, the name from its desktop entry unless there is none or
+    /// app ids are asked for.
+    fn display_name<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.show_app_ids {
+            return name;
+        }
+        self.display_names.get(name).map_or(name, String::as_str)
+    }
+
+    /// Returns the app ids the entry is made up of.
+    fn members_of(&self, name: &str) -> Vec<String> {
+        self.members
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| vec![name.to_string()])
+    }
 }
 
-#[derive(Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 enum AppListTime {
     #[default]
     Today,
+    /// Since the start of the week, which day that is depends on the locale.
     ThisWeek,
+    /// Today and the 6 days before it.
+    Last7Days,
+    /// Since the 1st of the month.
     ThisMonth,
+    /// Today and the 27 days before it.
+    Last28Days,
     AllTime,
+    /// The days from `start` up to and including `end`.
+    Custom {
+        start: NaiveDate,
+        end: NaiveDate,
+    },
 }
 
 impl AppListTime {
+    /// The next wider range, staying at all time once it is reached.
     fn next(&self) -> Self {
         match self {
-            AppListTime::Today => AppListTime::Today,
-            AppListTime::ThisWeek => AppListTime::Today,
-            AppListTime::ThisMonth => AppListTime::ThisWeek,
-            AppListTime::AllTime => AppListTime::ThisMonth,
+            AppListTime::Today => AppListTime::ThisWeek,
+            AppListTime::ThisWeek => AppListTime::Last7Days,
+            AppListTime::Last7Days => AppListTime::ThisMonth,
+            AppListTime::ThisMonth => AppListTime::Last28Days,
+            AppListTime::Last28Days => AppListTime::AllTime,
+            AppListTime::AllTime => AppListTime::AllTime,
+            AppListTime::Custom { .. } => AppListTime::AllTime,
         }
     }
 
+    /// The next narrower range, staying at today once it is reached.
     fn prev(&self) -> Self {
         match self {
-            AppListTime::Today => AppListTime::ThisWeek,
-            AppListTime::ThisWeek => AppListTime::ThisMonth,
-            AppListTime::ThisMonth => AppListTime::AllTime,
-            AppListTime::AllTime => AppListTime::AllTime,
+            AppListTime::Today => AppListTime::Today,
+            AppListTime::ThisWeek => AppListTime::Today,
+            AppListTime::Last7Days => AppListTime::ThisWeek,
+            AppListTime::ThisMonth => AppListTime::Last7Days,
+            AppListTime::Last28Days => AppListTime::ThisMonth,
+            AppListTime::AllTime => AppListTime::Last28Days,
+            AppListTime::Custom { .. } => AppListTime::Today,
         }
     }
 
+    /// Parses the dates entered after pressing `d`, either a single day or a start and an end
+    /// day separated by whitespace, both as YYYY-MM-DD.
+    fn parse_custom(text: &str) -> Result<Self, String> {
+        let parse = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("{date} is not a date like 2025-01-31"))
+        };
+
+        let mut dates = text.split_whitespace();
+        let start = parse(dates.next().ok_or("no dates entered")?)?;
+        let end = dates.next().map(parse).transpose()?.unwrap_or(start);
+        if dates.next().is_some() {
+            return Err("expected a start and an end date".to_string());
+        }
+        if start > end {
+            return Err(format!("{start} is after {end}"));
+        }
+        Ok(AppListTime::Custom { start, end })
+    }
+
     fn timestamps(&self) -> Option<(u64, u64)> {
-        let now = Local::now();
-        let start_of_today = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
-        let end_of_today = start_of_today + chrono::Duration::days(1);
+        self.days(Local::now().date_naive(), locale_week_start())
+            .map(|(start, end)| {
+                (
+                    local_millis(start.and_time(NaiveTime::MIN)),
+                    local_millis(end.and_time(NaiveTime::MIN)),
+                )
+            })
+    }
+
+    /// The first day of the range and the day after its last one, as seen on `today`.
+    fn days(&self, today: NaiveDate, week_start: Weekday) -> Option<(NaiveDate, NaiveDate)> {
+        let tomorrow = today + chrono::Duration::days(1);
+        let start = match self {
+            AppListTime::Today => today,
+            AppListTime::ThisWeek => today.week(week_start).first_day(),
+            AppListTime::Last7Days => tomorrow - chrono::Duration::days(7),
+            AppListTime::ThisMonth => today.with_day(1).unwrap(),
+            AppListTime::Last28Days => tomorrow - chrono::Duration::days(28),
+            AppListTime::AllTime => return None,
+            AppListTime::Custom { start, end } => {
+                return Some((*start, *end + chrono::Duration::days(1)));
+            }
+        };
+        Some((start, tomorrow))
+    }
 
+    /// The days right before the range, as many as it has, or for this week and month, which
+    /// are still going on, the same days of the week or month before. All time has nothing
+    /// before it.
+    fn previous_days(
+        &self,
+        today: NaiveDate,
+        week_start: Weekday,
+    ) -> Option<(NaiveDate, NaiveDate)> {
+        let (start, end) = self.days(today, week_start)?;
         match self {
-            AppListTime::Today => Some((
-                start_of_today.and_utc().timestamp_millis() as u64,
-                end_of_today.and_utc().timestamp_millis() as u64,
+            AppListTime::ThisMonth => Some((
+                start.checked_sub_months(Months::new(1))?,
+                end.checked_sub_months(Months::new(1))?,
             )),
             AppListTime::ThisWeek => {
-                let one_week_ago = end_of_today - chrono::Duration::weeks(1);
-                Some((
-                    one_week_ago.and_utc().timestamp_millis() as u64,
-                    end_of_today.and_utc().timestamp_millis() as u64,
-                ))
-            }
-            AppListTime::ThisMonth => {
-                let one_month_ago = end_of_today - chrono::Duration::weeks(4);
-                Some((
-                    one_month_ago.and_utc().timestamp_millis() as u64,
-                    end_of_today.and_utc().timestamp_millis() as u64,
-                ))
+                let week = chrono::Duration::weeks(1);
+                Some((start - week, end - week))
             }
-            AppListTime::AllTime => None,
+            _ => Some((start - (end - start), start)),
         }
     }
 }
 
 impl std::fmt::Display for AppListTime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                AppListTime::Today => "Today",
-                AppListTime::ThisWeek => "Last Week",
-                AppListTime::ThisMonth => "Last Month",
-                AppListTime::AllTime => "All Time",
-            }
-        )
+        match self {
+            AppListTime::Today => write!(f, "Today"),
+            AppListTime::ThisWeek => write!(f, "This Week"),
+            AppListTime::Last7Days => write!(f, "Last 7 Days"),
+            AppListTime::ThisMonth => write!(f, "This Month"),
+            AppListTime::Last28Days => write!(f, "Last 28 Days"),
+            AppListTime::AllTime => write!(f, "All Time"),
+            AppListTime::Custom { start, end } if start == end => write!(f, "{start}"),
+            AppListTime::Custom { start, end } => write!(f, "{start} to {end}"),
+        }
     }
 }
 
+/// The day weeks start on in the locale used for dates, see
+/// [`appusage_core::locale_week_start`].
+fn locale_week_start() -> Weekday {
+    Weekday::try_from(appusage_core::locale_week_start()).unwrap_or(Weekday::Mon)
+}
+
+/// Shows how long each wayland application was used.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// How many days the bar chart covers
+    #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(u16).range(1..=90))]
+    chart_days: u16,
+
+    /// Database to read, can be given multiple times to combine several databases. Without
+    /// it the one in `WAYLAND_APPUSAGE_DB` or the config is read
+    #[arg(long = "db", value_name = "PATH", global = true)]
+    databases: Vec<PathBuf>,
+
+    /// Don't use colors, this is also the case if `NO_COLOR` is set
+    #[arg(long)]
+    no_color: bool,
+
+    /// Move from the last app in the list to the first one and back with j and k
+    #[arg(long)]
+    wrap: bool,
+
+    /// Where the session length histogram splits its ranges
+    #[arg(long, value_delimiter = ',', default_value = "1m,5m,30m")]
+    session_buckets: Vec<humantime::Duration>,
+
+    /// Count sessions of the same app that are less than this apart as one in the session
+    /// length histogram
+    #[arg(long, default_value = "0s")]
+    merge_gap: humantime::Duration,
+
+    /// How often to fetch the latest usage while the tui is open, 0s turns it off
+    #[arg(long, value_name = "INTERVAL", default_value = "5s")]
+    refresh: humantime::Duration,
+
+    /// Print the usage of every app within the range as JSON instead of opening the tui
+    #[arg(long, value_name = "RANGE")]
+    export_json: Option<export::Range>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a single line about the app used the most today and exit
+    Status {
+        /// What to print, `{app}`, `{today}` (usage of that app today) and `{total}` (usage
+        /// of all apps today) get replaced
+        #[arg(long, default_value = status::DEFAULT_FORMAT)]
+        format: String,
+    },
+    /// Print every recorded session of an app on a day and exit
+    Sessions {
+        app: String,
+
+        /// Day to print, as YYYY-MM-DD, defaults to today
+        date: Option<chrono::NaiveDate>,
+
+        /// Print a JSON array instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the databases for corruption and rows whose times contradict each other, then exit
+    Check {
+        /// Delete rows that end before they start and shorten durations longer than their rows
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Write the sessions of a day to an SVG image and exit
+    Timeline {
+        /// Day to draw, as YYYY-MM-DD, defaults to today
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+
+        /// Where to write the image
+        #[arg(long, short, default_value = "timeline.svg")]
+        output: PathBuf,
+    },
+    /// Print the most used apps of a time range and exit
+    Top {
+        #[arg(long, default_value = "today")]
+        range: export::Range,
+
+        /// How many apps to print
+        #[arg(long, short = 'n', default_value_t = 10)]
+        limit: usize,
+
+        /// Print JSON, like --export-json, instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Sum up the apps by the categories they are in, see `categories` in the config
+        #[arg(long)]
+        categories: bool,
+    },
+    /// Delete old sessions from the first database and exit
+    Prune {
+        /// Delete sessions that ended more than this many days ago, defaults to
+        /// `retention_days` from the config
+        #[arg(long, value_name = "DAYS", value_parser = clap::value_parser!(u32).range(1..))]
+        older_than: Option<u32>,
+
+        /// Delete them outright instead of keeping their daily usage in the archive, all
+        /// time totals will shrink
+        #[arg(long)]
+        no_archive: bool,
+    },
+    /// Compact the first database, giving space freed by pruning back, and exit
+    Vacuum,
+    /// Print the busiest day, the longest session and when recording started, and exit
+    Stats,
+    /// Copy the sessions of another database into the first one, skipping those it already
+    /// has, and exit
+    Import {
+        /// Database to take the sessions from, it is only read and may be from an older version
+        path: PathBuf,
+    },
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    // this has to happen before the terminal is switched to raw mode, otherwise the error
+    // would end up garbled on the alternate screen
+    let config = match appusage_core::config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // status bars run this every few seconds, so it only reads what is there without the
+    // checks and upgrades everything else starts with
+    if let Some(Command::Status { format }) = &args.command {
+        if let Err(e) = status::run(&args.databases, &config, format) {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let (conn, sources) = match open_databases(&args.databases, &config) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let domain_rules = match DomainRules::from_config(&config.web_apps) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(command) = &args.command {
+        let result = match command {
+            Command::Status { .. } => unreachable!("handled before opening the databases"),
+            Command::Sessions { app, date, json } => sessions::print_sessions(
+                &conn,
+                app,
+                date.unwrap_or_else(|| Local::now().date_naive()),
+                *json,
+            ),
+            Command::Check { repair } => check::run(&conn, &sources, *repair),
+            Command::Timeline { date, output } => timeline::export(
+                &conn,
+                date.unwrap_or_else(|| Local::now().date_naive()),
+                output,
+            ),
+            Command::Top {
+                range,
+                limit,
+                json,
+                categories,
+            } => export::print_top(
+                &conn,
+                *range,
+                *limit,
+                *json,
+                categories
+                    .then(|| Categories::from_config(&config))
+                    .as_ref(),
+            ),
+            Command::Prune {
+                older_than,
+                no_archive,
+            } => match older_than.or(config.retention_days) {
+                Some(days) => prune::run(&conn, days, config.archive_pruned && !no_archive),
+                None => Err("give --older-than or set retention_days in the config".into()),
+            },
+            Command::Vacuum => vacuum::run(&conn),
+            Command::Stats => stats::print_stats(&conn),
+            Command::Import { path } => import::run(&conn, path),
+        };
+        if let Err(e) = result {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(range) = args.export_json {
+        if let Err(e) = export::print_json(&conn, range) {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let labels = sources.into_iter().map(|(_, label)| label).collect();
+    let aliases = Aliases::from_config(&config);
+    let categories = Categories::from_config(&config);
+    let targets = Targets::from_config(&config);
+    let colors = match color::Colors::detect(args.no_color, &config.colors) {
+        Ok(colors) => colors,
+        Err(e) => {
+            eprintln!("appusage: {e}");
+            std::process::exit(1);
+        }
+    };
+    // the first queries run before the terminal is taken over, so their errors are printed
+    // like any other
+    let mut app = match App::new(
+        conn,
+        labels,
+        aliases,
+        categories,
+        domain_rules,
+        targets,
+        colors,
+        &args,
+    ) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("appusage: failed to read usage: {e}");
+            std::process::exit(1);
+        }
+    };
+
     let mut terminal = ratatui::init();
-    let app_result = App::new().run(&mut terminal);
+    capture_mouse();
+    let app_result = app.run(&mut terminal);
+    // has to happen before anything is printed, also when the app failed
+    release_mouse();
     ratatui::restore();
 
-    Ok(app_result?)
+    if let Err(e) = app_result {
+        eprintln!("appusage: {e}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Has the terminal report clicks and scrolling, until [`release_mouse`] or a panic. Without
+/// it the terminal keeps scrolling through its own history instead.
+fn capture_mouse() {
+    // not getting mouse events only means it has to be used with the keyboard
+    let _ = ratatui::crossterm::execute!(io::stdout(), EnableMouseCapture);
+
+    // the panic hook of `ratatui::init` only restores what it changed itself
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        release_mouse();
+        hook(info);
+    }));
+}
+
+fn release_mouse() {
+    let _ = ratatui::crossterm::execute!(io::stdout(), DisableMouseCapture);
+}
+
+/// Opens the first database and attaches the rest of them, returning the connection along
+/// with the schema and a label of each loaded database. Without any paths the configured
+/// database is used.
+fn open_databases(
+    paths: &[PathBuf],
+    config: &appusage_core::config::Config,
+) -> Result<(Connection, Vec<(String, String)>), Box<dyn Error>> {
+    let (conn, sources, source_paths) = attach_databases(paths, config)?;
+
+    for ((schema, label), path) in sources.iter().zip(&source_paths) {
+        if let Some(problem) = db::check_integrity(&conn, schema, false)? {
+            return Err(format!(
+                "database {label} is corrupt ({problem}), run `appusage-daemon --recover` to \
+                 move it aside and salvage what is still readable"
+            )
+            .into());
+        }
+
+        // an older daemon may still be running, so the tui brings the schema up to date
+        // itself instead of relying on it
+        let migrate_conn = Connection::open(path)?;
+        appusage_core::configure(&migrate_conn)?;
+        if let Err(e) = appusage_core::schema::migrate(&migrate_conn) {
+            return Err(format!("could not upgrade database {label}: {e}").into());
+        }
+
+        let missing = db::missing_columns(&conn, schema)?;
+        if !missing.is_empty() {
+            return Err(format!(
+                "database {label} is not an appusage database or is from an incompatible \
+                 version, it is missing the columns {}",
+                missing.join(", ")
+            )
+            .into());
+        }
+    }
+
+    db::create_usage_view(&conn, &sources)?;
+
+    // tags are the only thing the tui writes, wait for the daemon instead of failing if it
+    // happens to be writing at the same time
+    appusage_core::configure(&conn)?;
+    db::create_tag_table(&conn)?;
+
+    Ok((conn, sources))
+}
+
+/// Opens and attaches the databases like [`open_databases`] without checking or upgrading
+/// them, also returning the path of each.
+fn attach_databases(
+    paths: &[PathBuf],
+    config: &appusage_core::config::Config,
+) -> Result<(Connection, Vec<(String, String)>, Vec<PathBuf>), Box<dyn Error>> {
+    let (main_path, extra_paths) = match paths.split_first() {
+        Some((first, rest)) => ((first.clone(), first.display().to_string()), rest),
+        None => ((config.db_path()?, "default".to_string()), &[][..]),
+    };
+
+    let conn = Connection::open(&main_path.0)?;
+
+    let mut sources = vec![("main".to_string(), main_path.1)];
+    let mut source_paths = vec![main_path.0];
+    for (i, path) in extra_paths.iter().enumerate() {
+        if !path.exists() {
+            return Err(format!("database {} does not exist", path.display()).into());
+        }
+        let schema = format!("source{i}");
+        db::attach(&conn, &schema, path)?;
+        sources.push((schema, path.display().to_string()));
+        source_paths.push(path.clone());
+    }
+
+    Ok((conn, sources, source_paths))
 }
 
 impl App {
-    fn new() -> Self {
-        let db_path = xdg::BaseDirectories::with_prefix("wayland-appusage")
-            .unwrap()
-            .place_data_file("app_usage.db")
-            .unwrap();
-        let conn = Connection::open(db_path).unwrap();
+    fn new(
+        conn: Connection,
+        sources: Vec<String>,
+        aliases: Aliases,
+        categories: Categories,
+        domain_rules: DomainRules,
+        targets: Targets,
+        colors: color::Colors,
+        args: &Args,
+    ) -> Result<Self, rusqlite::Error> {
         let time_to_show = AppListTime::default();
-        let apps = db::list_apps(&conn, time_to_show.timestamps()).unwrap();
+        let mut cache = cache::QueryCache::default();
+        let (apps, members) = aliases.merge(&cache.list_apps(&conn, time_to_show)?);
 
-        Self {
+        let mut app = Self {
             exit: false,
             connection: conn,
             app_list: AppList {
-                items: apps,
+                items: apps.clone(),
+                all_items: apps,
+                filter: String::new(),
+                sort: SortOrder::default(),
                 state: ListState::default(),
                 time_to_show,
+                members,
+                trends: HashMap::new(),
+                display_names: HashMap::new(),
+                show_app_ids: false,
+                wrap_selection: args.wrap,
+                page_size: 1,
+                area: Rect::default(),
             },
-        }
+            chart: ChartKind::default(),
+            bar_direction: Direction::Vertical,
+            chart_days: args.chart_days,
+            input: None,
+            sources,
+            ui_state: UiState::load(),
+            desktop_entries: desktop::Resolver::default(),
+            aliases,
+            categories,
+            domain_rules,
+            targets,
+            by_category: false,
+            group_by_domain: false,
+            show_trends: false,
+            cache,
+            colors,
+            show_weekdays: false,
+            show_titles: false,
+            show_hours: false,
+            session_buckets: {
+                let mut buckets = args
+                    .session_buckets
+                    .iter()
+                    .map(|x| x.as_millis() as u64)
+                    .collect::<Vec<_>>();
+                buckets.sort_unstable();
+                buckets.dedup();
+                buckets
+            },
+            merge_gap: args.merge_gap.as_millis() as u64,
+            refresh_interval: Some(*args.refresh).filter(|x| !x.is_zero()),
+            last_refresh: time::Instant::now(),
+            daemon: focus::Daemon::default(),
+            focus: None,
+            this_hour: None,
+        };
+        app.resolve_display_names();
+        Ok(app)
     }
 }
 
 impl App {
     /// runs the application's main loop until the user quits
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        self.focus = self.daemon.current_focus();
+        self.refetch_this_hour();
         while !self.exit {
             terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events()?;
+
+            // waiting for input is cut short when the next refresh is due, so keys are still
+            // handled right away
+            match self.refresh_interval {
+                Some(interval) => {
+                    let timeout = interval.saturating_sub(self.last_refresh.elapsed());
+                    if event::poll(timeout)? {
+                        self.handle_events()?;
+                    } else {
+                        self.refresh();
+                    }
+                }
+                None => self.handle_events()?,
+            }
         }
         Ok(())
     }
 
+    /// Drops everything that was cached and fetches the current range again.
+    fn refresh(&mut self) {
+        self.cache.invalidate();
+        self.refetch_applist();
+        self.focus = self.daemon.current_focus();
+        self.refetch_this_hour();
+        self.last_refresh = time::Instant::now();
+    }
+
+    /// Finds the entry used the most since the current clock hour started.
+    fn refetch_this_hour(&mut self) {
+        let now = Local::now();
+        let now_millis = now.timestamp_millis() as u64;
+        let start = start_of_hour(&now);
+        let mut items = db::list_apps(&self.connection, Some((start, now_millis))).unwrap();
+
+        // the session of the focused app is only written once it ends
+        if let Some(focus) = &self.focus {
+            let since = focus
+                .since
+                .duration_since(time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let running = now_millis.saturating_sub(since.max(start));
+            match items.iter_mut().find(|x| x.0 == focus.app_id) {
+                Some(item) => item.1 += running,
+                None => items.push((focus.app_id.clone(), running)),
+            }
+        }
+
+        let (items, _) = self.group(&items, Some((start, now_millis)));
+        self.this_hour = items
+            .into_iter()
+            .filter(|x| x.1 > 0)
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)));
+    }
+
     fn refetch_applist(&mut self) {
-        self.app_list.items =
-            db::list_apps(&self.connection, self.app_list.time_to_show.timestamps()).unwrap();
+        let items = self
+            .cache
+            .list_apps(&self.connection, self.app_list.time_to_show)
+            .unwrap();
+
+        (self.app_list.all_items, self.app_list.members) =
+            self.group(&items, self.app_list.time_to_show.timestamps());
+        self.resolve_display_names();
+        self.app_list.update_items();
+
+        self.refetch_trends();
+    }
+
+    /// Turns the usage of each app id in `time_range` into that of the entries of the list,
+    /// merged by category, alias or domain, along with the app ids that went into each entry.
+    fn group(
+        &self,
+        items: &[(String, u64)],
+        time_range: Option<(u64, u64)>,
+    ) -> (Vec<(String, u64)>, HashMap<String, Vec<String>>) {
+        // categories list raw app ids, so they go by those instead of any other grouping
+        if self.by_category {
+            return self.categories.group(items);
+        }
+
+        let (items, members) = self.aliases.merge(items);
+        if !self.group_by_domain {
+            return (items, members);
+        }
+        // browsers are split by the titles of all the app ids merged into them
+        let (grouped, domains) = self.domain_rules.group(&items, |name| {
+            members
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| vec![name.to_string()])
+                .iter()
+                .flat_map(|app_id| {
+                    db::get_titles_for_app(&self.connection, app_id, time_range).unwrap()
+                })
+                .collect()
+        });
+        // every domain entry is made up of the app ids of the entries that went into it
+        let members = domains
+            .into_iter()
+            .map(|(domain, names)| {
+                let app_ids = names
+                    .iter()
+                    .flat_map(|name| {
+                        members
+                            .get(name)
+                            .cloned()
+                            .unwrap_or_else(|| vec![name.clone()])
+                    })
+                    .collect();
+                (domain, app_ids)
+            })
+            .collect();
+        (grouped, members)
+    }
+
+    /// Looks up the desktop entry names of every entry in the list, which only reads the
+    /// desktop entries the first time.
+    fn resolve_display_names(&mut self) {
+        self.app_list.display_names = self
+            .app_list
+            .all_items
+            .iter()
+            .filter_map(|(name, _)| {
+                let display_name = unidentified_display_name(name)
+                    .or_else(|| self.desktop_entries.resolve_app(name).name)?;
+                Some((name.clone(), display_name))
+            })
+            .collect();
+    }
+
+    /// Fetches the daily usage of every app over the past days for the sparklines, all at
+    /// once since there is one for each row.
+    fn refetch_trends(&mut self) {
+        if !self.show_trends {
+            self.app_list.trends.clear();
+            return;
+        }
+
+        let first_day = Local::now().date_naive() - chrono::Duration::days(TREND_DAYS as i64 - 1);
+
+        self.app_list.trends =
+            db::get_daily_usage_per_app(&self.connection, first_day, TREND_DAYS).unwrap();
     }
 
     fn draw(&mut self, frame: &mut Frame) {
@@ -153,11 +1043,32 @@ impl App {
         match event::read()? {
             // it's important to check that the event is a key press event as
             // crossterm also emits key release and repeat events on Windows.
+            Event::Key(key_event)
+                if key_event.kind == KeyEventKind::Press && self.input.is_some() =>
+            {
+                self.handle_input_key(key_event.code)
+            }
+            Event::Mouse(mouse_event) if self.input.is_none() => match mouse_event.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    self.app_list.select_at(mouse_event.column, mouse_event.row)
+                }
+                MouseEventKind::ScrollDown => self.app_list.select_next(),
+                MouseEventKind::ScrollUp => self.app_list.select_previous(),
+                _ => {}
+            },
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                 match key_event.code {
                     KeyCode::Char('q') => self.exit(),
-                    KeyCode::Char('j') | KeyCode::Down => self.app_list.state.select_next(),
-                    KeyCode::Char('k') | KeyCode::Up => self.app_list.state.select_previous(),
+                    KeyCode::Char('d') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.app_list.select_page(true)
+                    }
+                    KeyCode::Char('u') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.app_list.select_page(false)
+                    }
+                    KeyCode::PageDown => self.app_list.select_page(true),
+                    KeyCode::PageUp => self.app_list.select_page(false),
+                    KeyCode::Char('j') | KeyCode::Down => self.app_list.select_next(),
+                    KeyCode::Char('k') | KeyCode::Up => self.app_list.select_previous(),
                     KeyCode::Char('g') | KeyCode::Home => self.app_list.state.select_first(),
                     KeyCode::Char('G') | KeyCode::End => self.app_list.state.select_last(),
                     KeyCode::Char('h') | KeyCode::Left => {
@@ -168,6 +1079,56 @@ impl App {
                         self.app_list.time_to_show = self.app_list.time_to_show.next();
                         self.refetch_applist();
                     }
+                    KeyCode::Char('o') => {
+                        self.bar_direction = match self.bar_direction {
+                            Direction::Vertical => Direction::Horizontal,
+                            Direction::Horizontal => Direction::Vertical,
+                        }
+                    }
+                    KeyCode::Char('r') => self.refresh(),
+                    KeyCode::Char('w') => self.chart = self.chart.next(),
+                    KeyCode::Char('%') => self.toggle_percentage(),
+                    KeyCode::Char('a') => self.show_weekdays = !self.show_weekdays,
+                    KeyCode::Char('T') => self.show_titles = !self.show_titles,
+                    KeyCode::Char('H') => self.show_hours = !self.show_hours,
+                    KeyCode::Char('i') => self.app_list.show_app_ids = !self.app_list.show_app_ids,
+                    KeyCode::Char('p') => {
+                        self.show_trends = !self.show_trends;
+                        self.refetch_trends();
+                    }
+                    KeyCode::Char('D') => {
+                        self.group_by_domain = !self.group_by_domain;
+                        self.refetch_applist();
+                    }
+                    KeyCode::Char('c') => {
+                        self.by_category = !self.by_category;
+                        self.refetch_applist();
+                    }
+                    KeyCode::Char('s') => {
+                        self.app_list.sort = self.app_list.sort.next();
+                        self.app_list.update_items();
+                    }
+                    KeyCode::Char('/') => {
+                        self.input = Some(Input {
+                            kind: InputKind::Filter,
+                            text: self.app_list.filter.clone(),
+                            error: None,
+                        })
+                    }
+                    KeyCode::Char('t') if self.app_list.state.selected().is_some() => {
+                        self.input = Some(Input {
+                            kind: InputKind::ToggleTag,
+                            text: String::new(),
+                            error: None,
+                        })
+                    }
+                    KeyCode::Char('d') => {
+                        self.input = Some(Input {
+                            kind: InputKind::DateRange,
+                            text: String::new(),
+                            error: None,
+                        })
+                    }
                     _ => {}
                 }
             }
@@ -176,27 +1137,119 @@ impl App {
         Ok(())
     }
 
+    fn handle_input_key(&mut self, code: KeyCode) {
+        let Some(input) = self.input.as_mut() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => {
+                if let Some(InputKind::Filter) = self.input.take().map(|x| x.kind) {
+                    self.app_list.filter.clear();
+                    self.app_list.update_items();
+                }
+            }
+            KeyCode::Backspace => {
+                input.text.pop();
+            }
+            KeyCode::Char(c) => input.text.push(c),
+            KeyCode::Enter => {
+                let Some(input) = self.input.take() else {
+                    return;
+                };
+                match input.kind {
+                    InputKind::ToggleTag => self.toggle_tag(input.text.trim()),
+                    // it is applied while typing already
+                    InputKind::Filter => {}
+                    InputKind::DateRange => match AppListTime::parse_custom(&input.text) {
+                        Ok(time) => {
+                            self.app_list.time_to_show = time;
+                            self.refetch_applist();
+                        }
+                        // keep the prompt open so the dates can be fixed
+                        Err(e) => {
+                            self.input = Some(Input {
+                                error: Some(e),
+                                ..input
+                            })
+                        }
+                    },
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(Input {
+            kind: InputKind::Filter,
+            text,
+            ..
+        }) = &self.input
+        {
+            if *text != self.app_list.filter {
+                self.app_list.filter = text.clone();
+                self.app_list.update_items();
+            }
+        }
+    }
+
+    fn toggle_tag(&mut self, tag: &str) {
+        let Some(selected) = self.app_list.state.selected() else {
+            return;
+        };
+        if tag.is_empty() {
+            return;
+        }
+        // tags belong to app ids, a grouped entry has the tag if any of its apps has it
+        let members = self.app_list.members_of(&self.app_list.items[selected].0);
+        let tagged = members.iter().any(|member| {
+            db::get_tags(&self.connection, member)
+                .unwrap()
+                .iter()
+                .any(|x| x == tag)
+        });
+        for member in &members {
+            if tagged {
+                db::remove_tag(&self.connection, member, tag).unwrap();
+            } else {
+                db::add_tag(&self.connection, member, tag).unwrap();
+            }
+        }
+    }
+
     fn exit(&mut self) {
         self.exit = true;
     }
 
-    fn get_week_data(&self) -> Vec<(String, u64)> {
-        let now = Local::now();
+    fn toggle_percentage(&mut self) {
+        self.ui_state.show_percentage = !self.ui_state.show_percentage;
+        // not being able to remember the choice shouldn't get in the way
+        let _ = self.ui_state.save();
+    }
+
+    fn get_week_data(&mut self) -> Vec<(String, u64)> {
+        if let Some(week_data) = self.cache.week_data() {
+            return week_data;
+        }
+        let week_data = self.fetch_week_data();
+        self.cache.set_week_data(week_data.clone());
+        week_data
+    }
+
+    fn fetch_week_data(&self) -> Vec<(String, u64)> {
+        let now = Local::now();
         let start_of_today = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
+        let days = self.chart_days as usize;
 
-        // TODO cache this!!!
-        (0..7)
+        (0..days as i64)
             .map(|i| {
                 let day = start_of_today - chrono::Duration::days(i);
                 (
-                    day.weekday().to_string(),
+                    day_label(day.date(), days),
                     db::get_data_for_time(
                         &self.connection,
                         (
-                            day.and_utc().timestamp_millis() as u64,
-                            (day + chrono::Duration::days(1))
-                                .and_utc()
-                                .timestamp_millis() as u64,
+                            local_millis(day),
+                            local_millis(day + chrono::Duration::days(1)),
                         ),
                     )
                     .unwrap(),
@@ -205,25 +1258,120 @@ impl App {
             .collect()
     }
 
+    /// Like [`Self::fetch_week_data`], only for the app ids of a single entry of the list and
+    /// over the given number of days.
+    fn fetch_app_week_data(&self, members: &[String], days: usize) -> Vec<(String, u64)> {
+        let today = Local::now().date_naive();
+        let first_day = today - chrono::Duration::days(days as i64 - 1);
+
+        let mut totals = vec![0; days];
+        for member in members {
+            let member_days =
+                db::get_daily_totals(&self.connection, Some(member), first_day, days).unwrap();
+            for (total, usage) in totals.iter_mut().zip(member_days) {
+                *total += usage;
+            }
+        }
+
+        // newest first like the week data
+        totals
+            .into_iter()
+            .enumerate()
+            .map(|(i, usage)| {
+                let day = first_day + chrono::Duration::days(i as i64);
+                (day_label(day, days), usage)
+            })
+            .rev()
+            .collect()
+    }
+
+    /// Returns the days shown in the bar chart on which the daemon wasn't running at all, so
+    /// missing usage there doesn't mean nothing was used.
+    fn get_uncovered_days(&self, days: usize) -> Vec<String> {
+        // databases from before runs were recorded have no coverage info at all
+        let Ok(Some(first_run)) = db::get_first_daemon_run(&self.connection) else {
+            return vec![];
+        };
+
+        let start_of_today = Local::now().date_naive().and_hms_opt(0, 0, 0).unwrap();
+
+        (0..days as i64)
+            .rev()
+            .map(|i| start_of_today - chrono::Duration::days(i))
+            .filter(|day| {
+                let start = local_millis(*day);
+                let end = local_millis(*day + chrono::Duration::days(1));
+                end > first_run
+                    && !db::has_daemon_coverage(&self.connection, (start, end)).unwrap_or(true)
+            })
+            .map(|day| day.format("%m-%d").to_string())
+            .collect()
+    }
+
+    /// Shows the usage on each of the days in `week_data`, newest first, of all apps or just
+    /// the selected one.
+    ///
+    /// Bars are made thinner and their gaps dropped as room runs out, when there isn't even a
+    /// cell for each of them a message says so instead.
     fn render_bars(&mut self, week_data: Vec<(String, u64)>, area: Rect, buf: &mut Buffer) {
-        let block = Block::bordered().title("Past Week");
+        let mut block = self.colors.block().title(if week_data.len() == 7 {
+            "Past Week".to_string()
+        } else {
+            format!("Past {} Days", week_data.len())
+        });
 
-        let width = block.inner(area).width;
-        let gap_size = 2;
-        let item_count = 7;
-        let total_reserved = gap_size * (item_count - 1) + 2;
-        let space_per_item = (width - total_reserved) / item_count;
+        let uncovered = self.get_uncovered_days(week_data.len());
+        if !uncovered.is_empty() {
+            block = block.title_bottom(self.colors.paint(
+                truncate_with_ellipsis(
+                    &format!("no daemon coverage on {}", uncovered.join(", ")),
+                    area.width.saturating_sub(3) as usize,
+                ),
+                Color::Yellow,
+                "!",
+            ));
+        }
 
+        let inner = block.inner(area);
+        let item_count = u16::try_from(week_data.len().max(1)).unwrap_or(u16::MAX);
+        // bars are stacked on top of each other when horizontal, so the available height is
+        // what gets shared then
+        let (available, preferred_gap, margin) = match self.bar_direction {
+            Direction::Vertical => (inner.width, if item_count > 7 { 1 } else { 2 }, 2),
+            Direction::Horizontal => (inner.height, 1, 0),
+        };
+        // a bar can't get thinner than a cell, so without room for that none is drawn at all
+        if available < item_count {
+            Paragraph::new("Too narrow for the chart")
+                .wrap(Wrap { trim: true })
+                .block(block)
+                .render(area, buf);
+            return;
+        }
+        // the gaps are dropped first when room runs out
+        let gap_size = if available >= item_count.saturating_mul(preferred_gap + 1) - preferred_gap
+        {
+            preferred_gap
+        } else {
+            0
+        };
+        let total_reserved = (gap_size.saturating_mul(item_count - 1)).saturating_add(margin);
+        let space_per_item = (available.saturating_sub(total_reserved) / item_count).max(1);
+
+        // the first day is today, which stands out
         let bars: Vec<_> = week_data
             .iter()
-            .map(|(day, value)| {
-                Bar::default()
-                    .value(*value)
-                    .label(day.clone().into())
-                    .text_value(
-                        humantime::format_duration(time::Duration::from_secs(*value / 1000))
-                            .to_string(),
-                    )
+            .enumerate()
+            .map(|(i, (day, value))| {
+                let bar = Bar::default().value(*value).text_value(
+                    humantime::format_duration(time::Duration::from_secs(*value / 1000))
+                        .to_string(),
+                );
+                match (i, self.colors.enabled()) {
+                    (0, true) => bar.label(day.clone().into()).style(self.colors.today()),
+                    (0, false) => bar.label(format!("*{day}").into()),
+                    _ => bar.label(day.clone().into()),
+                }
             })
             .rev()
             .collect();
@@ -233,10 +1381,362 @@ impl App {
             .data(BarGroup::default().bars(&bars))
             .bar_width(space_per_item)
             .bar_gap(gap_size)
-            .direction(Direction::Vertical)
+            .bar_style(self.colors.bars())
+            .direction(self.bar_direction)
+            .render(area, buf);
+    }
+
+    /// Shows when the first session started and the last one ended on each of the past days.
+    fn render_active_hours(&mut self, area: Rect, buf: &mut Buffer) {
+        let block = self.colors.block().title("Active Hours");
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let days = inner.height.min(31) as i64;
+        let now = Local::now();
+        let first_day = now.date_naive() - chrono::Duration::days(days - 1);
+        let first_day_start = local_millis(first_day.and_time(NaiveTime::MIN));
+
+        let spans = db::get_daily_activity_spans(
+            &self.connection,
+            (first_day_start, now.timestamp_millis() as u64),
+        )
+        .unwrap();
+
+        let to_local = |millis: u64| {
+            Local
+                .timestamp_millis_opt(millis as i64)
+                .single()
+                .unwrap_or(now)
+        };
+
+        // "Mon 05-03 08:12-23:40 " followed by a bar spanning the whole day
+        let label_width = 23;
+        let bar_width = (inner.width as usize).saturating_sub(label_width);
+
+        let lines = spans
+            .iter()
+            .map(|(day, first, last)| {
+                let (first, last) = (to_local(*first), to_local(*last));
+                let label = format!(
+                    "{} {} {}-{}",
+                    first.format("%a"),
+                    day.get(5..).unwrap_or(day.as_str()),
+                    first.format("%H:%M"),
+                    last.format("%H:%M"),
+                );
+
+                // the last session of a day may end after midnight
+                let seconds_in_day = 24 * 60 * 60;
+                let first_seconds = first.num_seconds_from_midnight() as usize;
+                let last_seconds = if last.date_naive() > first.date_naive() {
+                    seconds_in_day
+                } else {
+                    last.num_seconds_from_midnight() as usize
+                };
+
+                let from = first_seconds * bar_width / seconds_in_day;
+                // a single short session still gets at least one cell
+                let to = (last_seconds * bar_width / seconds_in_day)
+                    .max(from + 1)
+                    .min(bar_width);
+
+                format!(
+                    "{label:<label_width$}{}{}{}",
+                    " ".repeat(from),
+                    "█".repeat(to.saturating_sub(from)),
+                    " ".repeat(bar_width.saturating_sub(to)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        if lines.is_empty() {
+            Paragraph::new("No usage recorded").render(inner, buf);
+        } else {
+            Paragraph::new(lines.join("\n")).render(inner, buf);
+        }
+    }
+
+    /// Shows how many sessions fell into each length range, over the time the list shows.
+    fn render_session_lengths(&mut self, area: Rect, buf: &mut Buffer) {
+        let block = self
+            .colors
+            .block()
+            .title(format!("Session Lengths ({})", self.app_list.time_to_show));
+
+        let counts = if self.merge_gap > 0 {
+            let sessions = db::get_merged_sessions(
+                &self.connection,
+                self.merge_gap,
+                self.app_list.time_to_show.timestamps(),
+            )
+            .unwrap();
+            let mut counts = vec![0; self.session_buckets.len() + 1];
+            for (_, start, end) in sessions {
+                let length = end.saturating_sub(start);
+                counts[self
+                    .session_buckets
+                    .partition_point(|bound| *bound <= length)] += 1;
+            }
+            counts
+        } else {
+            db::get_session_length_counts(
+                &self.connection,
+                &self.session_buckets,
+                self.app_list.time_to_show.timestamps(),
+            )
+            .unwrap()
+        };
+
+        let format_bound =
+            |millis: u64| humantime::format_duration(time::Duration::from_secs(millis / 1000));
+        let labels = (0..counts.len()).map(|i| {
+            match (
+                i.checked_sub(1).map(|i| self.session_buckets[i]),
+                self.session_buckets.get(i),
+            ) {
+                (None, Some(upper)) => format!("<{}", format_bound(*upper)),
+                (Some(lower), Some(upper)) => {
+                    format!("{}-{}", format_bound(lower), format_bound(*upper))
+                }
+                (Some(lower), None) => format!("{}+", format_bound(lower)),
+                (None, None) => "all".to_string(),
+            }
+        });
+
+        let bars: Vec<_> = counts
+            .iter()
+            .zip(labels)
+            .map(|(count, label)| Bar::default().value(*count).label(label.into()))
+            .collect();
+
+        let item_count = bars.len().max(1) as u16;
+        let space_per_item = match self.bar_direction {
+            Direction::Vertical => {
+                (block.inner(area).width.saturating_sub(item_count - 1) / item_count).max(1)
+            }
+            Direction::Horizontal => 1,
+        };
+
+        BarChart::default()
+            .block(block)
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(space_per_item)
+            .bar_gap(1)
+            .bar_style(self.colors.bars())
+            .direction(self.bar_direction)
             .render(area, buf);
     }
 
+    /// Shows for how many days in a row the selected app met its daily goal or limit, or all
+    /// apps together their total limit when it has none.
+    fn render_streaks(&mut self, area: Rect, buf: &mut Buffer) {
+        let selected = self
+            .app_list
+            .state
+            .selected()
+            .and_then(|i| self.app_list.items.get(i))
+            .map(|(name, _)| (name.clone(), self.app_list.members_of(name)));
+
+        // an entry merged from several app ids goes by the first of them with a target
+        let app_target = selected.and_then(|(name, members)| {
+            std::iter::once(&name)
+                .chain(&members)
+                .find_map(|x| self.targets.of_app(x))
+                .map(|target| {
+                    (
+                        self.app_list.display_name(&name).to_string(),
+                        members,
+                        target,
+                    )
+                })
+        });
+        let (label, members, target) = match (app_target, self.targets.total()) {
+            (Some(found), _) => found,
+            (None, Some(target)) => ("All apps".to_string(), vec![], target),
+            (None, None) => {
+                Paragraph::new("No targets, set daily_goals or daily_limits in the config")
+                    .block(self.colors.block().title("Streaks"))
+                    .render(area, buf);
+                return;
+            }
+        };
+
+        let first_day = Local::now().date_naive() - chrono::Duration::days(STREAK_DAYS as i64 - 1);
+
+        let mut days = vec![0; STREAK_DAYS];
+        let totals = if members.is_empty() {
+            vec![db::get_daily_totals(&self.connection, None, first_day, STREAK_DAYS).unwrap()]
+        } else {
+            members
+                .iter()
+                .map(|member| {
+                    db::get_daily_totals(&self.connection, Some(member), first_day, STREAK_DAYS)
+                        .unwrap()
+                })
+                .collect()
+        };
+        for member_days in totals {
+            for (day, usage) in days.iter_mut().zip(member_days) {
+                *day += usage;
+            }
+        }
+
+        // days before anything was recorded would all count as staying under a limit
+        let first_recorded = days.iter().position(|x| *x > 0).unwrap_or(days.len());
+        let streaks = streak::streaks(&days[first_recorded..], target);
+
+        let plural = |n: usize| if n == 1 { "day" } else { "days" };
+        let text = format!(
+            "{label}: {target}\n\nCurrent streak: {} {}\nBest streak:    {} {}",
+            streaks.current,
+            plural(streaks.current),
+            streaks.best,
+            plural(streaks.best),
+        );
+        Paragraph::new(text)
+            .block(
+                self.colors
+                    .block()
+                    .title(format!("Streaks (last {STREAK_DAYS} days)")),
+            )
+            .render(area, buf);
+    }
+
+    /// Shows how the usage of each entry in the list changed against the same time range
+    /// right before it, the biggest changes first.
+    fn render_comparison(&mut self, area: Rect, buf: &mut Buffer) {
+        let time = self.app_list.time_to_show;
+        let Some((start, end)) = time.previous_days(Local::now().date_naive(), locale_week_start())
+        else {
+            Paragraph::new("There is nothing before all time to compare with")
+                .block(self.colors.block().title("Comparison"))
+                .render(area, buf);
+            return;
+        };
+        let last_day = end - chrono::Duration::days(1);
+        let block = self.colors.block().title(if start == last_day {
+            format!("{time} vs {start}")
+        } else {
+            format!("{time} vs {start} to {last_day}")
+        });
+
+        let range = Some((
+            local_millis(start.and_time(NaiveTime::MIN)),
+            local_millis(end.and_time(NaiveTime::MIN)),
+        ));
+        let previous = db::list_apps(&self.connection, range).unwrap();
+        let rows = compare::compare(&self.app_list.all_items, &self.group(&previous, range).0);
+        if rows.is_empty() {
+            Paragraph::new("No usage in either range")
+                .block(block)
+                .render(area, buf);
+            return;
+        }
+
+        let format = |millis: u64| {
+            humantime::format_duration(time::Duration::from_secs(millis / 1000)).to_string()
+        };
+        let name_width = rows
+            .iter()
+            .map(|x| self.app_list.display_name(&x.name).width())
+            .max()
+            .unwrap_or(0)
+            .min(30);
+        let lines = rows
+            .iter()
+            .take(block.inner(area).height as usize)
+            .map(|row| {
+                let change = if row.is_new() {
+                    "new".to_string()
+                } else if row.is_gone() {
+                    "gone".to_string()
+                } else {
+                    let arrow = match row.delta() {
+                        0 => "=",
+                        1.. => "▲",
+                        _ => "▼",
+                    };
+                    format!("{arrow} {}", format(row.delta().unsigned_abs()))
+                };
+                let name =
+                    truncate_with_ellipsis(self.app_list.display_name(&row.name), name_width);
+                // names are padded by width, which `format!` can't do for wide characters
+                let padding = " ".repeat(name_width.saturating_sub(name.width()));
+                Line::from(format!(
+                    "{name}{padding}  {} (was {})  {change}",
+                    format(row.current),
+                    format(row.previous)
+                ))
+            })
+            .collect::<Vec<_>>();
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+
+    fn render_window_count(&mut self, area: Rect, buf: &mut Buffer) {
+        let block = self.colors.block().title("Open Windows Today");
+
+        let now = Local::now();
+        let start_of_today = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
+        let end_of_today = start_of_today + chrono::Duration::days(1);
+        let start_millis = local_millis(start_of_today);
+
+        // a missing table just means the daemon never sampled anything
+        let samples =
+            db::get_window_counts(&self.connection, (start_millis, local_millis(end_of_today)))
+                .unwrap_or_default();
+
+        if samples.is_empty() {
+            Paragraph::new("No samples, start the daemon with --track-window-count")
+                .block(block)
+                .render(area, buf);
+            return;
+        }
+
+        let hours_since_midnight =
+            |time: u64| time.saturating_sub(start_millis) as f64 / 3_600_000.0;
+
+        // draw it as a step function, the count holds until the next sample
+        let mut points = Vec::with_capacity(samples.len() * 2 + 1);
+        for (time, count) in &samples {
+            let x = hours_since_midnight(*time);
+            if let Some(&(_, previous)) = points.last() {
+                points.push((x, previous));
+            }
+            points.push((x, *count as f64));
+        }
+        let now_x = hours_since_midnight(now.timestamp_millis() as u64);
+        if let Some(&(_, last)) = points.last() {
+            points.push((now_x, last));
+        }
+
+        let max_count = samples
+            .iter()
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        Chart::new(vec![
+            Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .data(&points),
+        ])
+        .block(block)
+        .x_axis(
+            Axis::default()
+                .bounds([0.0, 24.0])
+                .labels(["00:00", "06:00", "12:00", "18:00", "24:00"]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, max_count as f64])
+                .labels(["0".to_string(), max_count.to_string()]),
+        )
+        .render(area, buf);
+    }
+
     fn render_legend(&mut self, week_data: Vec<(String, u64)>, area: Rect, buf: &mut Buffer) {
         let legend_items = week_data
             .iter()
@@ -250,46 +1750,386 @@ impl App {
             .rev();
 
         List::new(legend_items)
-            .block(Block::default().borders(Borders::ALL))
+            .block(self.colors.block())
+            .render(area, buf);
+    }
+
+    /// Sums up the whole time range in a single line, how long anything was used and how long
+    /// a day on average, counting only days with any usage.
+    fn render_summary(&mut self, area: Rect, buf: &mut Buffer) {
+        let (total, active_days) = self
+            .cache
+            .tracked_summary(&self.connection, self.app_list.time_to_show)
+            .unwrap();
+        let format =
+            |millis: u64| humantime::format_duration(time::Duration::from_secs(millis / 1000));
+        let text = match total.checked_div(active_days) {
+            Some(per_day) => format!(
+                "Total: {}, {} a day over {active_days} active {}",
+                format(total),
+                format(per_day),
+                if active_days == 1 { "day" } else { "days" }
+            ),
+            None => "Total: nothing recorded".to_string(),
+        };
+        Paragraph::new(truncate_with_ellipsis(&text, area.width as usize).into_owned())
             .render(area, buf);
     }
 
+    /// Shows how often focus moved from one window to another today, with a sparkline of the
+    /// past days after it.
+    fn render_switches(&mut self, area: Rect, buf: &mut Buffer) {
+        let switches = self.cache.focus_switches(&self.connection).unwrap();
+        let today = switches.last().copied().unwrap_or(0);
+        let text = format!("Focus switches today: {today}");
+        let text = truncate_with_ellipsis(&text, area.width as usize).into_owned();
+
+        let trend_x = text.width() as u16 + 1;
+        Paragraph::new(text).render(area, buf);
+        if area.width >= trend_x + TREND_DAYS as u16 {
+            Sparkline::default().data(&switches).render(
+                Rect::new(area.x + trend_x, area.y, TREND_DAYS as u16, 1),
+                buf,
+            );
+        }
+    }
+
     fn render_list(&mut self, area: Rect, buf: &mut Buffer) {
-        let name_items = self
+        // the summary only takes a row from the list once it has a few to spare, and the
+        // focus switches another one once there are even more
+        let area = if area.height >= MIN_SWITCHES_LIST_HEIGHT {
+            let [summary_area, switches_area, list_area] = Layout::vertical([
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Fill(1),
+            ])
+            .areas(area);
+            self.render_summary(summary_area, buf);
+            self.render_switches(switches_area, buf);
+            list_area
+        } else if area.height >= MIN_SUMMARY_LIST_HEIGHT {
+            let [summary_area, list_area] =
+                Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(area);
+            self.render_summary(summary_area, buf);
+            list_area
+        } else {
+            area
+        };
+
+        // shares are of the whole range, so filtering doesn't inflate them
+        let total = self.app_list.all_items.iter().map(|x| x.1).sum::<u64>();
+        let times = self
             .app_list
             .items
             .iter()
-            .map(|x| x.0.clone())
+            .map(|x| {
+                let duration = humantime::format_duration(time::Duration::from_secs(x.1 / 1000));
+                if self.ui_state.show_percentage {
+                    // padded to the widest percentage so they line up on the right
+                    format!("{duration} {:>6}", format_percentage(x.1, total))
+                } else {
+                    duration.to_string()
+                }
+            })
             .collect::<Vec<_>>();
 
-        let time_items = self
+        // both lists are drawn over the same area, so names have to leave room for the
+        // widest time plus the border, the highlight symbol and a single space
+        let time_width = times.iter().map(|x| x.width()).max().unwrap_or(0);
+        let trend_width = if self.show_trends { TREND_DAYS + 1 } else { 0 };
+        let name_width = (area.width as usize).saturating_sub(time_width + trend_width + 4);
+
+        let name_items = self
             .app_list
             .items
             .iter()
             .map(|x| {
-                ListItem::new(
-                    Text::from(
-                        humantime::format_duration(time::Duration::from_secs(x.1 / 1000))
-                            .to_string(),
-                    )
-                    .right_aligned(),
-                )
+                truncate_with_ellipsis(self.app_list.display_name(&x.0), name_width).into_owned()
             })
             .collect::<Vec<_>>();
 
+        let time_items = times
+            .into_iter()
+            .map(|x| ListItem::new(Text::from(x).right_aligned()))
+            .collect::<Vec<_>>();
+
+        let mut block = self
+            .colors
+            .block()
+            .title_alignment(Alignment::Center)
+            .title(format!(
+                "Top {}{} [{}]{}",
+                self.app_list.time_to_show,
+                if self.by_category {
+                    " (by category)"
+                } else if self.group_by_domain {
+                    " (by domain)"
+                } else {
+                    ""
+                },
+                self.app_list.sort,
+                if self.app_list.filter.is_empty() {
+                    String::new()
+                } else {
+                    format!(" /{}", self.app_list.filter)
+                }
+            ));
+        if let Some(status) = self.recording_status() {
+            block = block.title(status.right_aligned());
+        }
+        if self.sources.len() > 1 {
+            block = block.title_bottom(
+                truncate_with_ellipsis(
+                    &format!("Combined: {}", self.sources.join(", ")),
+                    area.width.saturating_sub(2) as usize,
+                )
+                .into_owned(),
+            );
+        }
+
+        if let Some((name, duration)) = &self.this_hour {
+            let text = format!(
+                "This hour: {} {}",
+                self.app_list.display_name(name),
+                humantime::format_duration(time::Duration::from_secs(duration / 1000))
+            );
+            block = block.title_bottom(
+                Line::from(
+                    truncate_with_ellipsis(&text, area.width.saturating_sub(2) as usize)
+                        .into_owned(),
+                )
+                .right_aligned(),
+            );
+        }
+
+        self.app_list.area = block.inner(area);
+        self.app_list.page_size = (self.app_list.area.height as usize).max(1);
+
         let [name_list, time_list] = [List::new(name_items), List::new(time_items)].map(|x| {
-            x.block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title_alignment(Alignment::Center)
-                    .title(format!("Top {}", self.app_list.time_to_show)),
-            )
-            .highlight_symbol(">")
-            .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
+            x.block(block.clone())
+                .highlight_symbol(">")
+                .highlight_style(self.colors.selected())
+                .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
         });
 
         ratatui::widgets::StatefulWidget::render(time_list, area, buf, &mut self.app_list.state);
         ratatui::widgets::StatefulWidget::render(name_list, area, buf, &mut self.app_list.state);
+
+        if self.show_trends {
+            self.render_trends(block.inner(area), time_width, buf);
+        }
+    }
+
+    /// Shows which app is focused and for how long if the daemon can be asked, otherwise
+    /// guesses whether usage is being recorded right now from how recently the last session
+    /// ended.
+    fn recording_status(&self) -> Option<Line<'static>> {
+        if let Some(focus) = &self.focus {
+            let focused_for = time::SystemTime::now()
+                .duration_since(focus.since)
+                .unwrap_or_default();
+            return Some(self.colors.paint(
+                format!(
+                    "● {} {}",
+                    self.app_list.display_name(&focus.app_id),
+                    humantime::format_duration(time::Duration::from_secs(focused_for.as_secs()))
+                ),
+                Color::Green,
+                "",
+            ));
+        }
+
+        let last_end = db::get_last_end_time(&self.connection).ok()??;
+        let now = Local::now().timestamp_millis() as u64;
+
+        Some(if now.saturating_sub(last_end) < RECORDING_WINDOW_MS {
+            self.colors.paint("● recording", Color::Green, "")
+        } else {
+            self.colors.paint("○ idle", Color::DarkGray, "")
+        })
+    }
+
+    /// Draws a sparkline of the past days into every visible row of the list, right before
+    /// the time column.
+    fn render_trends(&self, inner: Rect, time_width: usize, buf: &mut Buffer) {
+        let reserved = (TREND_DAYS + 1 + time_width) as u16;
+        if inner.width < reserved + 2 {
+            return;
+        }
+        let x = inner.right() - reserved;
+
+        let offset = self.app_list.state.offset();
+        for (row, (name, _)) in self
+            .app_list
+            .items
+            .iter()
+            .skip(offset)
+            .take(inner.height as usize)
+            .enumerate()
+        {
+            let mut trend = vec![0; TREND_DAYS];
+            for member in self.app_list.members_of(name) {
+                if let Some(days) = self.app_list.trends.get(&member) {
+                    for (total, day) in trend.iter_mut().zip(days) {
+                        *total += day;
+                    }
+                }
+            }
+
+            Sparkline::default().data(&trend).render(
+                Rect::new(x, inner.y + row as u16, TREND_DAYS as u16, 1),
+                buf,
+            );
+        }
+    }
+
+    /// A bar for each hour of the day showing how much of the range's usage fell into it.
+    fn hourly_breakdown(&self, members: &[String]) -> String {
+        let mut totals = [0; 24];
+        for member in members {
+            for (total, x) in totals.iter_mut().zip(
+                db::get_hourly_distribution(
+                    &self.connection,
+                    member,
+                    self.app_list.time_to_show.timestamps(),
+                )
+                .unwrap(),
+            ) {
+                *total += x;
+            }
+        }
+
+        let max = totals.iter().copied().max().unwrap_or(0);
+        if max == 0 {
+            return "\nBy hour: no usage".to_string();
+        }
+        let levels = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let bars = totals
+            .iter()
+            .map(|total| {
+                if *total == 0 {
+                    ' '
+                } else {
+                    levels[(total * (levels.len() as u64 - 1)).div_ceil(max) as usize]
+                }
+            })
+            .collect::<String>();
+
+        format!(
+            "\n{} by hour:\n0     6     12    18\n{}",
+            self.app_list.time_to_show,
+            bars.trim_end()
+        )
+    }
+
+    /// Lists the window titles the apps were used under within the shown range, longest
+    /// first.
+    fn title_breakdown(&self, members: &[String]) -> String {
+        let mut totals = HashMap::<String, u64>::new();
+        for member in members {
+            for (title, duration) in db::get_titles_for_app(
+                &self.connection,
+                member,
+                self.app_list.time_to_show.timestamps(),
+            )
+            .unwrap()
+            {
+                *totals.entry(title).or_default() += duration;
+            }
+        }
+        if totals.is_empty() {
+            return "\nBy title: none recorded".to_string();
+        }
+
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let shown = totals.len().min(MAX_TITLES);
+        format!("\n{} by title:", self.app_list.time_to_show)
+            + &totals[..shown]
+                .iter()
+                .map(|(title, duration)| {
+                    format!(
+                        "\n  {title}: {}",
+                        humantime::format_duration(time::Duration::from_secs(duration / 1000))
+                    )
+                })
+                .collect::<String>()
+            + &if totals.len() > shown {
+                format!("\n  and {} more", totals.len() - shown)
+            } else {
+                String::new()
+            }
+    }
+
+    /// Returns a line of bars showing the average usage of the apps on each weekday, averaged
+    /// over every such weekday since they were first used.
+    fn weekday_breakdown(&self, members: &[String]) -> String {
+        let mut totals = [0; 7];
+        let mut first_use = None::<u64>;
+        for member in members {
+            for (total, x) in totals
+                .iter_mut()
+                .zip(db::get_weekday_totals(&self.connection, member).unwrap())
+            {
+                *total += x;
+            }
+            if let Some(x) = db::get_first_use(&self.connection, member).unwrap() {
+                first_use = Some(first_use.map_or(x, |y| y.min(x)));
+            }
+        }
+
+        let Some(first_day) = first_use
+            .and_then(|x| Local.timestamp_millis_opt(x as i64).single())
+            .map(|x| x.date_naive())
+        else {
+            return "\nBy weekday: no usage yet".to_string();
+        };
+
+        // starting on monday reads more naturally
+        let order = [1, 2, 3, 4, 5, 6, 0];
+        let averages = weekday_averages(totals, first_day, Local::now().date_naive());
+        let max = averages.iter().flatten().copied().max().unwrap_or(0).max(1);
+        let levels = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let bars = order
+            .iter()
+            .map(|i| match averages[*i] {
+                Some(average) => {
+                    let level = (average * (levels.len() as u64 - 1) / max) as usize;
+                    format!(" {} ", levels[level])
+                }
+                // weekdays that didn't come up yet, when the history is shorter than a week
+                None => " - ".to_string(),
+            })
+            .collect::<String>();
+
+        format!(
+            "\nAverage by weekday:\n Mo Tu We Th Fr Sa Su\n{}",
+            bars.trim_end()
+        )
+    }
+
+    /// Draws the prompt over the bottom of `area`, if one is open.
+    fn render_input(&self, area: Rect, buf: &mut Buffer) {
+        let Some(ref input) = self.input else {
+            return;
+        };
+
+        let [_, input_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(3)]).areas(area);
+        let title = match input.kind {
+            InputKind::ToggleTag => "Add or remove tag (enter to confirm, esc to cancel)",
+            InputKind::Filter => "Filter apps (enter to keep, esc to clear)",
+            InputKind::DateRange => "Show dates, like 2025-01-01 2025-01-31 (esc to cancel)",
+        };
+        let mut block = self.colors.block().title(title);
+        if let Some(ref error) = input.error {
+            block = block.title_bottom(self.colors.paint(error.as_str(), Color::Red, "error: "));
+        }
+
+        Clear.render(input_area, buf);
+        Paragraph::new(input.text.as_str())
+            .block(block)
+            .render(input_area, buf);
     }
 
     fn render_item(&mut self, area: Rect, buf: &mut Buffer) {
@@ -297,57 +2137,291 @@ impl App {
             return;
         };
 
-        let selected_app = self.app_list.items[selected_num].clone();
+        let Some(selected_app) = self.app_list.items.get(selected_num).cloned() else {
+            return;
+        };
 
         // Line::from(selected_app).render(area, buf);
-        let block = Block::new()
-            .borders(Borders::ALL)
-            .title(selected_app.0.clone());
+        let block = self.colors.block().title(
+            truncate_with_ellipsis(
+                self.app_list.display_name(&selected_app.0),
+                area.width.saturating_sub(2) as usize,
+            )
+            .into_owned(),
+        );
 
         let inner = block.inner(area);
+        // the chart only gets a share of the pane once the text has room left as well
+        let (text_area, chart_area) = if inner.height >= MIN_APP_CHART_PANE_HEIGHT {
+            let [text_area, chart_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(APP_CHART_HEIGHT)])
+                    .areas(inner);
+            (text_area, Some(chart_area))
+        } else {
+            (inner, None)
+        };
 
         let now = Local::now();
         let start_of_today = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
         let end_of_today = start_of_today + chrono::Duration::days(1);
 
-        let usage_today = db::get_data_for_app_and_time(
-            &self.connection,
-            selected_app.0.clone(),
-            (
-                start_of_today.and_utc().timestamp_millis() as u64,
-                end_of_today.and_utc().timestamp_millis() as u64,
-            ),
-        )
-        .unwrap();
+        // an entry grouped by domain is the sum of every app that went into it
+        let members = self.app_list.members_of(&selected_app.0);
+
+        let usage_today = members
+            .iter()
+            .map(|member| {
+                db::get_data_for_app_and_time(
+                    &self.connection,
+                    member.clone(),
+                    (local_millis(start_of_today), local_millis(end_of_today)),
+                )
+                .unwrap()
+            })
+            .sum::<u64>();
 
         let one_week_ago = end_of_today - chrono::Duration::weeks(1);
 
-        let usage_this_wek = db::get_data_for_app_and_time(
-            &self.connection,
-            selected_app.0.clone(),
-            (
-                one_week_ago.and_utc().timestamp_millis() as u64,
-                end_of_today.and_utc().timestamp_millis() as u64,
+        let usage_this_wek = members
+            .iter()
+            .map(|member| {
+                db::get_data_for_app_and_time(
+                    &self.connection,
+                    member.clone(),
+                    (local_millis(one_week_ago), local_millis(end_of_today)),
+                )
+                .unwrap()
+            })
+            .sum::<u64>();
+
+        let usage_all_time = members
+            .iter()
+            .map(|member| db::get_total_app_usage(&self.connection, member.clone()).unwrap())
+            .sum::<u64>();
+
+        // the fixed ranges are always shown above, a custom one gets its own line
+        let custom_range = match self.app_list.time_to_show {
+            range @ AppListTime::Custom { .. } => {
+                let usage = members
+                    .iter()
+                    .map(|member| {
+                        db::get_data_for_app_and_time(
+                            &self.connection,
+                            member.clone(),
+                            range.timestamps().unwrap(),
+                        )
+                        .unwrap()
+                    })
+                    .sum::<u64>();
+                format!(
+                    "\n{range}: {}",
+                    humantime::format_duration(time::Duration::from_secs(usage / 1000))
+                )
+            }
+            _ => String::new(),
+        };
+
+        // an alias can rename a single app id as well
+        let grouped_from = if members != [selected_app.0.as_str()] {
+            format!("\nGrouped from: {}", members.join(", "))
+        } else {
+            String::new()
+        };
+
+        // how fragmented the usage is, a merged entry averages over the sessions of all its
+        // apps
+        let range = self.app_list.time_to_show.timestamps();
+        let (session_count, session_total) = members
+            .iter()
+            .map(|member| {
+                let count = db::get_session_count(&self.connection, member, range).unwrap();
+                let average = db::get_average_session_length(&self.connection, member, range)
+                    .unwrap()
+                    .unwrap_or(0);
+                (count, average * count)
+            })
+            .fold((0, 0), |(count, total), x| (count + x.0, total + x.1));
+        let sessions = match session_total.checked_div(session_count) {
+            Some(average) => format!(
+                "\n{} sessions: {session_count}, {} on average",
+                self.app_list.time_to_show,
+                humantime::format_duration(time::Duration::from_secs(average / 1000))
             ),
-        )
-        .unwrap();
+            None => format!("\n{} sessions: none", self.app_list.time_to_show),
+        };
 
-        let usage_all_time = db::get_total_app_usage(&self.connection, selected_app.0).unwrap();
+        let mut tags = members
+            .iter()
+            .flat_map(|member| db::get_tags(&self.connection, member).unwrap())
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
 
-        Paragraph::new(format!(
-            "Today: {}\nThis week: {}\nAll time: {}",
-            humantime::format_duration(time::Duration::from_secs(usage_today / 1000)),
-            humantime::format_duration(time::Duration::from_secs(usage_this_wek / 1000)),
-            humantime::format_duration(time::Duration::from_secs(usage_all_time / 1000)),
-        ))
-        .render(inner, buf);
+        let by_source = if self.sources.len() > 1 {
+            let mut usage = HashMap::<String, u64>::new();
+            for member in &members {
+                for (source, duration) in db::get_app_usage_by_source(
+                    &self.connection,
+                    member,
+                    self.app_list.time_to_show.timestamps(),
+                )
+                .unwrap()
+                {
+                    *usage.entry(source).or_default() += duration;
+                }
+            }
+            let usage = longest_first(usage);
+            let header = format!("\n{} by database:", self.app_list.time_to_show);
+            header
+                + &usage
+                    .iter()
+                    .map(|(source, value)| {
+                        format!(
+                            "\n  {source}: {}",
+                            humantime::format_duration(time::Duration::from_secs(value / 1000))
+                        )
+                    })
+                    .collect::<String>()
+        } else {
+            String::new()
+        };
+
+        // only worth showing on setups with several monitors
+        let mut usage_by_output = HashMap::<String, u64>::new();
+        for member in &members {
+            for (output, duration) in db::get_app_usage_by_output(
+                &self.connection,
+                member,
+                self.app_list.time_to_show.timestamps(),
+            )
+            .unwrap()
+            {
+                *usage_by_output.entry(output).or_default() += duration;
+            }
+        }
+        let usage_by_output = longest_first(usage_by_output);
+        let by_output = if usage_by_output.len() > 1 {
+            format!("\n{} by output:", self.app_list.time_to_show)
+                + &usage_by_output
+                    .iter()
+                    .map(|(output, value)| {
+                        format!(
+                            "\n  {output}: {}",
+                            humantime::format_duration(time::Duration::from_secs(value / 1000))
+                        )
+                    })
+                    .collect::<String>()
+        } else {
+            String::new()
+        };
+
+        let weekdays = if self.show_weekdays {
+            self.weekday_breakdown(&members)
+        } else {
+            String::new()
+        };
+
+        let titles = if self.show_titles {
+            self.title_breakdown(&members)
+        } else {
+            String::new()
+        };
+
+        let hours = if self.show_hours {
+            self.hourly_breakdown(&members)
+        } else {
+            String::new()
+        };
+
+        // a grouped entry shows the desktop entry of the first of its apps that has one
+        let meta = members
+            .iter()
+            .map(|member| self.desktop_entries.resolve_app(member))
+            .find(|meta| meta.name.is_some() || meta.icon.is_some())
+            .unwrap_or_default();
+        let desktop_entry = match (meta.name, meta.icon) {
+            (Some(name), Some(icon)) => format!("\nName: {name}\nIcon: {icon}"),
+            (Some(name), None) => format!("\nName: {name}"),
+            (None, Some(icon)) => format!("\nIcon: {icon}"),
+            (None, None) => String::new(),
+        };
+
+        // the title may be truncated, so the full app id always goes first
+        Paragraph::new(
+            format!(
+                "{}{grouped_from}{desktop_entry}\nTags: {}\nToday: {}\nLast 7 days: {}\nAll time: {}",
+                selected_app.0,
+                if tags.is_empty() {
+                    "none, press t to add one".to_string()
+                } else {
+                    tags.join(", ")
+                },
+                humantime::format_duration(time::Duration::from_secs(usage_today / 1000)),
+                humantime::format_duration(time::Duration::from_secs(usage_this_wek / 1000)),
+                humantime::format_duration(time::Duration::from_secs(usage_all_time / 1000)),
+            ) + &custom_range
+                + &sessions
+                + &weekdays
+                + &hours
+                + &titles
+                + &by_source
+                + &by_output,
+        )
+        .wrap(Wrap { trim: false })
+        .render(text_area, buf);
+
+        if let Some(chart_area) = chart_area {
+            let days = self.app_chart_days(chart_area);
+            let week_data = self.fetch_app_week_data(&members, days);
+            self.render_bars(week_data, chart_area, buf);
+        }
 
         block.render(area, buf);
     }
+
+    /// How many days the chart of the selected app covers, at most as many as the main chart
+    /// and only as many as still get a bar wide enough for its label.
+    fn app_chart_days(&self, area: Rect) -> usize {
+        let inner = Block::bordered().inner(area);
+        let fitting = match self.bar_direction {
+            // two columns for a day of the month and one for the gap
+            Direction::Vertical => (inner.width + 1) / 3,
+            Direction::Horizontal => inner.height,
+        };
+        fitting.clamp(1, self.chart_days.max(1)) as usize
+    }
 }
 
+/// How recently a session has to have ended for the daemon to count as recording, this is
+/// the daemon's idle timeout, so a longer gap means the user went idle or it isn't running.
+const RECORDING_WINDOW_MS: u64 = 30_000;
+
+/// Rows of the chart of the selected app in the detail pane, which is only shown when the
+/// pane has at least `MIN_APP_CHART_PANE_HEIGHT` rows inside its border.
+const APP_CHART_HEIGHT: u16 = 10;
+const MIN_APP_CHART_PANE_HEIGHT: u16 = 20;
+
+/// Rows the app list needs before a summary of the time range is shown above it.
+const MIN_SUMMARY_LIST_HEIGHT: u16 = 6;
+/// Rows the app list needs before today's focus switches are shown below the summary.
+const MIN_SWITCHES_LIST_HEIGHT: u16 = 10;
+
+/// How far back the streaks chart looks for days meeting a target.
+const STREAK_DAYS: usize = 365;
+
+/// Below this many columns or rows the chart and detail pane are dropped and only the list
+/// is shown, since there is no room to split the screen.
+const MIN_FULL_LAYOUT_WIDTH: u16 = 40;
+const MIN_FULL_LAYOUT_HEIGHT: u16 = 15;
+
 impl Widget for &mut App {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width < MIN_FULL_LAYOUT_WIDTH || area.height < MIN_FULL_LAYOUT_HEIGHT {
+            self.render_list(area, buf);
+            self.render_input(area, buf);
+            return;
+        }
+
         let [top_area, bottom_area] =
             Layout::vertical([Constraint::Max(20), Constraint::Fill(1)]).areas(area);
         let [left_area, right_area] =
@@ -357,12 +2431,773 @@ impl Widget for &mut App {
         // let [chart_area, list_area] =
         //     Layout::vertical([Constraint::Min(20), Constraint::Percentage(100)]).areas(left_area);
 
-        let week_data = self.get_week_data();
-        self.render_bars(week_data.clone(), top_area, buf);
+        match self.chart {
+            ChartKind::PastWeek => {
+                let week_data = self.get_week_data();
+                self.render_bars(week_data.clone(), top_area, buf);
+            }
+            ChartKind::WindowCount => self.render_window_count(top_area, buf),
+            ChartKind::ActiveHours => self.render_active_hours(top_area, buf),
+            ChartKind::SessionLengths => self.render_session_lengths(top_area, buf),
+            ChartKind::Streaks => self.render_streaks(top_area, buf),
+            ChartKind::Comparison => self.render_comparison(top_area, buf),
+        }
         // self.render_bars(week_data.clone(), chart_area, buf);
         // self.render_legend(week_data, legend_area, buf);
 
         self.render_list(left_area, buf);
         self.render_item(right_area, buf);
+        self.render_input(area, buf);
+    }
+}
+
+/// Names a window the daemon recorded without an app id after its title, marked so it isn't
+/// mistaken for an app of that name.
+fn unidentified_display_name(app_id: &str) -> Option<String> {
+    if !appusage_core::config::is_unidentified(app_id) {
+        return None;
+    }
+    Some(match app_id.split_once(':') {
+        Some((_, title)) => format!("{title} [no app id]"),
+        None => "[no app id]".to_string(),
+    })
+}
+
+/// Shortens `text` so it takes up at most `max_width` terminal columns, replacing the part
+/// that got cut off with an ellipsis.
+fn truncate_with_ellipsis(text: &str, max_width: usize) -> Cow<'_, str> {
+    if text.width() <= max_width {
+        return Cow::Borrowed(text);
+    }
+    let Some(budget) = max_width.checked_sub(1) else {
+        return Cow::Borrowed("");
+    };
+
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let width = c.width().unwrap_or(0);
+        if used + width > budget {
+            break;
+        }
+        used += width;
+        truncated.push(c);
+    }
+    truncated.push('…');
+
+    Cow::Owned(truncated)
+}
+
+/// Returns the start and end of `date` in local time, in ms.
+fn local_day_bounds(date: chrono::NaiveDate) -> Option<(u64, u64)> {
+    Some((
+        local_millis(date.and_time(NaiveTime::MIN)),
+        local_millis(date.succ_opt()?.and_time(NaiveTime::MIN)),
+    ))
+}
+
+/// Labels a day in a bar chart of `days` days, weekday names repeat once it covers more than
+/// a week.
+fn day_label(day: NaiveDate, days: usize) -> String {
+    if days <= 7 {
+        day.weekday().to_string()
+    } else {
+        day.format("%d").to_string()
+    }
+}
+
+/// Where the clock hour `now` is in started, in ms since the epoch.
+///
+/// This goes back by as many minutes and seconds as the local clock shows instead of setting
+/// them to zero, which would be ambiguous in the hour that repeats when the clocks go back.
+fn start_of_hour<Tz: TimeZone>(now: &DateTime<Tz>) -> u64 {
+    let local = now.naive_local();
+    let into_hour = chrono::Duration::minutes(local.minute().into())
+        + chrono::Duration::seconds(local.second().into())
+        + chrono::Duration::nanoseconds(local.nanosecond().into());
+    (now.clone() - into_hour).timestamp_millis().max(0) as u64
+}
+
+/// Converts a local time to ms since the unix epoch.
+///
+/// A time that happens twice when the clocks go back means its first occurrence, one that
+/// is skipped when they go forward means the moment they jumped. Either way the end of one
+/// day is exactly the start of the next, so no usage is counted on both or on neither.
+fn local_millis(time: NaiveDateTime) -> u64 {
+    local_millis_in(&Local, time)
+}
+
+fn local_millis_in<Tz: TimeZone>(tz: &Tz, time: NaiveDateTime) -> u64 {
+    let resolved = match tz.from_local_datetime(&time) {
+        LocalResult::Single(x) | LocalResult::Ambiguous(x, _) => x,
+        // the first time that exists after the skipped one is where the clocks landed
+        LocalResult::None => (1..=24 * 60)
+            .find_map(|minutes| {
+                tz.from_local_datetime(&(time + chrono::Duration::minutes(minutes)))
+                    .earliest()
+            })
+            .unwrap_or_else(|| time.and_utc().with_timezone(tz)),
+    };
+    resolved.timestamp_millis().max(0) as u64
+}
+
+/// Average usage per weekday (indexed from sunday) between `first_day` and `today`, counting
+/// days the app wasn't used. Weekdays that didn't come up in that span have no average.
+fn weekday_averages(totals: [u64; 7], first_day: NaiveDate, today: NaiveDate) -> [Option<u64>; 7] {
+    let mut occurrences = [0; 7];
+    for day in first_day.iter_days().take_while(|day| *day <= today) {
+        occurrences[day.weekday().num_days_from_sunday() as usize] += 1;
+    }
+    std::array::from_fn(|i| totals[i].checked_div(occurrences[i]))
+}
+
+/// Returns the totals sorted from the longest to the shortest, ties by name.
+fn longest_first(totals: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+/// Formats `value` as a percentage of `total`, an empty total counts as zero percent.
+fn format_percentage(value: u64, total: u64) -> String {
+    if total == 0 {
+        return "0.0%".to_string();
+    }
+    format!("{:.1}%", value as f64 * 100.0 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    fn test_app() -> App {
+        let conn = Connection::open_in_memory().unwrap();
+        appusage_core::schema::migrate(&conn).unwrap();
+        db::create_usage_view(&conn, &[("main".to_string(), "test".to_string())]).unwrap();
+        db::create_tag_table(&conn).unwrap();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        for (app_name, duration) in [
+            ("firefox", 3_600_000),
+            ("a-very-long-app-id.that.keeps.going", 60_000),
+        ] {
+            conn.execute(
+                "insert into app_usage (app_name, start_time, end_time, duration) values (?1, ?2, ?3, ?4)",
+                rusqlite::params![app_name, now - duration, now, duration],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "insert into window_counts (time, count) values (?1, 3)",
+            [now],
+        )
+        .unwrap();
+
+        let mut app = App::new(
+            conn,
+            vec!["test".to_string()],
+            Aliases::default(),
+            Categories::default(),
+            DomainRules::default(),
+            Targets::default(),
+            color::Colors::detect(false, &Default::default()).unwrap(),
+            &Args::parse_from(["appusage"]),
+        )
+        .unwrap();
+        app.app_list.state.select(Some(0));
+        app
+    }
+
+    fn render(app: &mut App, width: u16, height: u16) {
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        Widget::render(app, area, &mut buf);
+    }
+
+    #[test]
+    fn renders_every_view_at_any_size() {
+        let mut app = test_app();
+        for chart in [
+            ChartKind::PastWeek,
+            ChartKind::WindowCount,
+            ChartKind::ActiveHours,
+            ChartKind::SessionLengths,
+            ChartKind::Streaks,
+            ChartKind::Comparison,
+        ] {
+            for direction in [Direction::Vertical, Direction::Horizontal] {
+                app.chart = chart;
+                app.bar_direction = direction;
+                for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+                    render(&mut app, width, height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bars_fit_into_tiny_areas() {
+        let mut app = test_app();
+        let days = |n: usize| vec![("01".to_string(), 60_000); n];
+        for direction in [Direction::Vertical, Direction::Horizontal] {
+            app.bar_direction = direction;
+            for (width, height) in [(0, 0), (1, 1), (3, 3), (9, 9), (20, 6)] {
+                for week_data in [days(0), days(1), days(7), days(90)] {
+                    let area = Rect::new(0, 0, width, height);
+                    let mut buf = Buffer::empty(area);
+                    app.render_bars(week_data, area, &mut buf);
+                }
+            }
+        }
+
+        app.bar_direction = Direction::Vertical;
+        let area = Rect::new(0, 0, 20, 6);
+        let mut buf = Buffer::empty(area);
+        app.render_bars(days(30), area, &mut buf);
+        let text = (0..area.height)
+            .flat_map(|y| (0..area.width).map(move |x| (x, y)))
+            .map(|(x, y)| buf[(x, y)].symbol().to_string())
+            .collect::<String>();
+        assert!(text.contains("Too narrow"), "{text}");
+    }
+
+    #[test]
+    fn renders_merged_session_lengths() {
+        let mut app = test_app();
+        app.chart = ChartKind::SessionLengths;
+        app.merge_gap = 30_000;
+        for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+            render(&mut app, width, height);
+        }
+    }
+
+    #[test]
+    fn renders_weekday_breakdown() {
+        let mut app = test_app();
+        app.show_weekdays = true;
+        for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+            render(&mut app, width, height);
+        }
+    }
+
+    #[test]
+    fn renders_hourly_breakdown() {
+        let mut app = test_app();
+        app.show_hours = true;
+        assert_eq!(
+            app.hourly_breakdown(&["nothing".to_string()]),
+            "\nBy hour: no usage"
+        );
+        assert!(
+            app.hourly_breakdown(&["firefox".to_string()])
+                .starts_with("\nToday by hour:\n0     6     12    18\n")
+        );
+        for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+            render(&mut app, width, height);
+        }
+    }
+
+    #[test]
+    fn focused_app_is_shown_in_the_status() {
+        let mut app = test_app();
+        app.focus = Some(focus::Focus {
+            app_id: "firefox".to_string(),
+            since: SystemTime::now() - time::Duration::from_secs(90),
+        });
+        let status = app.recording_status().unwrap().to_string();
+        assert!(status.starts_with("● firefox 1m 3"), "{status}");
+    }
+
+    #[test]
+    fn renders_title_breakdown() {
+        let mut app = test_app();
+        app.show_titles = true;
+        assert_eq!(
+            app.title_breakdown(&["firefox".to_string()]),
+            "\nBy title: none recorded"
+        );
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        app.connection
+            .execute(
+                "insert into app_usage (app_name, title, start_time, end_time, duration) values ('firefox', 'Inbox', ?1, ?2, 120000)",
+                [now - 120_000, now],
+            )
+            .unwrap();
+        assert_eq!(
+            app.title_breakdown(&["firefox".to_string()]),
+            "\nToday by title:\n  Inbox: 2m"
+        );
+        for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+            render(&mut app, width, height);
+        }
+    }
+
+    #[test]
+    fn renders_prompt_at_any_size() {
+        let mut app = test_app();
+        app.input = Some(Input {
+            kind: InputKind::ToggleTag,
+            text: "work".to_string(),
+            error: None,
+        });
+        for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+            render(&mut app, width, height);
+        }
+    }
+
+    #[test]
+    fn renders_empty_list() {
+        let mut app = test_app();
+        app.app_list.items.clear();
+        app.app_list.all_items.clear();
+        app.app_list.state.select(None);
+        for (width, height) in [(1, 1), (5, 5), (80, 24)] {
+            render(&mut app, width, height);
+        }
+    }
+
+    #[test]
+    fn filter_narrows_list_while_typing() {
+        let mut app = test_app();
+        app.input = Some(Input {
+            kind: InputKind::Filter,
+            text: String::new(),
+            error: None,
+        });
+
+        for c in "FIRE".chars() {
+            app.handle_input_key(KeyCode::Char(c));
+        }
+        assert_eq!(app.app_list.items, vec![("firefox".to_string(), 3_600_000)]);
+        app.handle_input_key(KeyCode::Char('x'));
+        assert!(app.app_list.items.is_empty());
+        assert_eq!(app.app_list.state.selected(), None);
+        render(&mut app, 80, 24);
+
+        app.handle_input_key(KeyCode::Backspace);
+        app.handle_input_key(KeyCode::Enter);
+        assert!(app.input.is_none());
+        assert_eq!(app.app_list.filter, "FIRE");
+        assert_eq!(app.app_list.items.len(), 1);
+
+        app.input = Some(Input {
+            kind: InputKind::Filter,
+            text: app.app_list.filter.clone(),
+            error: None,
+        });
+        app.handle_input_key(KeyCode::Esc);
+        assert!(app.app_list.filter.is_empty());
+        assert_eq!(app.app_list.items.len(), 2);
+    }
+
+    #[test]
+    fn selection_pages_and_wraps() {
+        let mut app = test_app();
+        app.app_list.items = (0..30).map(|i| (format!("app{i}"), 1000)).collect();
+        render(&mut app, 80, 24);
+        let page = app.app_list.page_size;
+        assert!(page > 1 && page < 30, "{page}");
+
+        app.app_list.select_page(true);
+        assert_eq!(app.app_list.state.selected(), Some(page));
+        for _ in 0..10 {
+            app.app_list.select_page(true);
+        }
+        assert_eq!(app.app_list.state.selected(), Some(29));
+        app.app_list.select_page(false);
+        assert_eq!(app.app_list.state.selected(), Some(29 - page));
+
+        // stops at the ends unless asked to wrap
+        app.app_list.state.select(Some(29));
+        app.app_list.select_next();
+        render(&mut app, 80, 24);
+        assert_eq!(app.app_list.state.selected(), Some(29));
+        app.app_list.wrap_selection = true;
+        app.app_list.select_next();
+        assert_eq!(app.app_list.state.selected(), Some(0));
+        app.app_list.select_previous();
+        render(&mut app, 80, 24);
+        assert_eq!(app.app_list.state.selected(), Some(29));
+    }
+
+    #[test]
+    fn clicks_select_the_entry_under_them() {
+        let mut app = test_app();
+        render(&mut app, 80, 40);
+        let area = app.app_list.area;
+
+        app.app_list.select_at(area.x, area.y + 1);
+        assert_eq!(app.app_list.state.selected(), Some(1));
+        // below the last entry and outside the list
+        app.app_list.select_at(area.x, area.y + 5);
+        app.app_list.select_at(0, 0);
+        assert_eq!(app.app_list.state.selected(), Some(1));
+    }
+
+    #[test]
+    fn focus_switches_end_with_today() {
+        let mut app = test_app();
+        // the day the daemon counts them on
+        app.connection
+            .execute(
+                "insert into focus_switches (day, count)
+                    values (cast(strftime('%s', 'now', 'localtime') as integer) / 86400, 42)",
+                (),
+            )
+            .unwrap();
+        let switches = app.cache.focus_switches(&app.connection).unwrap();
+        assert_eq!(switches, [0, 0, 0, 0, 0, 0, 42]);
+        render(&mut app, 80, 40);
+    }
+
+    #[test]
+    fn hours_start_on_the_local_clock() {
+        // half an hour off from UTC, so its hours start half way through those of UTC
+        let tz = chrono::FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+        let now = tz.with_ymd_and_hms(2024, 3, 10, 12, 45, 30).unwrap();
+        let start = tz.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        assert_eq!(start_of_hour(&now), start.timestamp_millis() as u64);
+    }
+
+    #[test]
+    fn most_used_app_this_hour_includes_the_running_session() {
+        let mut app = test_app();
+        // firefox is still focused, and was for the whole hour so far on top of the hour
+        // its recorded session took
+        app.focus = Some(focus::Focus {
+            app_id: "firefox".to_string(),
+            since: time::SystemTime::now() - time::Duration::from_secs(2 * 3600),
+        });
+        let now = Local::now();
+        let into_hour = now.timestamp_millis() as u64 - start_of_hour(&now);
+        app.refetch_this_hour();
+
+        let (name, duration) = app.this_hour.clone().unwrap();
+        assert_eq!(name, "firefox");
+        assert!(duration + 1000 >= 2 * into_hour, "{duration} {into_hour}");
+        render(&mut app, 80, 40);
+    }
+
+    #[test]
+    fn windows_without_app_id_are_marked() {
+        assert_eq!(
+            unidentified_display_name("unidentified:Untitled 1").as_deref(),
+            Some("Untitled 1 [no app id]")
+        );
+        assert_eq!(
+            unidentified_display_name("unidentified").as_deref(),
+            Some("[no app id]")
+        );
+        assert_eq!(unidentified_display_name("firefox"), None);
+    }
+
+    #[test]
+    fn local_times_are_converted_with_their_offset() {
+        let tz = chrono::FixedOffset::east_opt(3600).unwrap();
+        let midnight = NaiveDate::from_ymd_opt(1970, 1, 2)
+            .unwrap()
+            .and_time(NaiveTime::MIN);
+        assert_eq!(local_millis_in(&tz, midnight), 23 * 3_600_000);
+    }
+
+    #[test]
+    fn app_chart_has_a_bar_for_every_day() {
+        let mut app = test_app();
+        let week_data = app.fetch_app_week_data(&["firefox".to_string()], 30);
+        assert_eq!(week_data.len(), 30);
+        // the session ending right now may have started yesterday, and be split a little
+        // unevenly then
+        let recent = week_data[0].1 + week_data[1].1;
+        assert!((3_599_999..=3_600_000).contains(&recent), "{recent}");
+        assert!(week_data[2..].iter().all(|(_, usage)| *usage == 0));
+
+        app.chart_days = 90;
+        assert_eq!(app.app_chart_days(Rect::new(0, 0, 32, 10)), 10);
+        assert_eq!(app.app_chart_days(Rect::new(0, 0, 2, 2)), 1);
+        for direction in [Direction::Vertical, Direction::Horizontal] {
+            app.bar_direction = direction;
+            for (width, height) in [(80, 24), (80, 60), (200, 80)] {
+                render(&mut app, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn week_data_is_cached_until_refresh() {
+        let mut app = test_app();
+        let today = app.get_week_data()[0].1;
+
+        let start = local_millis(Local::now().date_naive().and_hms_opt(1, 0, 0).unwrap());
+        app.connection
+            .execute(
+                "insert into app_usage (app_name, start_time, end_time, duration) values ('foot', ?1, ?2, 60000)",
+                [start, start + 60_000],
+            )
+            .unwrap();
+        assert_eq!(app.get_week_data()[0].1, today);
+
+        app.cache.invalidate();
+        assert_eq!(app.get_week_data()[0].1, today + 60_000);
+    }
+
+    #[test]
+    fn refresh_shows_new_usage() {
+        let mut app = test_app();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        app.connection
+            .execute(
+                "insert into app_usage (app_name, start_time, end_time, duration) values ('foot', ?1, ?2, 1000)",
+                [now - 1000, now],
+            )
+            .unwrap();
+
+        app.refetch_applist();
+        assert!(!app.app_list.items.iter().any(|x| x.0 == "foot"));
+        app.refresh();
+        assert!(app.app_list.items.iter().any(|x| x.0 == "foot"));
+        // the selection follows the app it was on
+        assert_eq!(app.app_list.items[0].0, "firefox");
+        assert_eq!(app.app_list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn percentage_handles_empty_total() {
+        assert_eq!(format_percentage(0, 0), "0.0%");
+        assert_eq!(format_percentage(1, 3), "33.3%");
+        assert_eq!(format_percentage(5, 5), "100.0%");
+    }
+
+    #[test]
+    fn sorting_keeps_selected_app() {
+        let mut app = test_app();
+        let names = |app: &App| {
+            app.app_list
+                .items
+                .iter()
+                .map(|x| x.0.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&app),
+            ["firefox", "a-very-long-app-id.that.keeps.going"]
+        );
+        assert_eq!(app.app_list.state.selected(), Some(0));
+
+        app.app_list.sort = SortOrder::NameAscending;
+        app.app_list.update_items();
+        assert_eq!(
+            names(&app),
+            ["a-very-long-app-id.that.keeps.going", "firefox"]
+        );
+        assert_eq!(app.app_list.state.selected(), Some(1));
+
+        app.app_list.sort = SortOrder::LeastUsed;
+        app.app_list.update_items();
+        assert_eq!(
+            names(&app),
+            ["a-very-long-app-id.that.keeps.going", "firefox"]
+        );
+
+        app.app_list.sort = SortOrder::NameDescending;
+        app.app_list.update_items();
+        assert_eq!(
+            names(&app),
+            ["firefox", "a-very-long-app-id.that.keeps.going"]
+        );
+        assert_eq!(app.app_list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn desktop_entry_names_are_shown_and_filtered_on() {
+        let mut app = test_app();
+        app.app_list
+            .display_names
+            .insert("firefox".to_string(), "Firefox Web Browser".to_string());
+
+        assert_eq!(app.app_list.display_name("firefox"), "Firefox Web Browser");
+        assert_eq!(app.app_list.display_name("foot"), "foot");
+        app.app_list.show_app_ids = true;
+        assert_eq!(app.app_list.display_name("firefox"), "firefox");
+
+        app.app_list.filter = "browser".to_string();
+        app.app_list.update_items();
+        assert_eq!(app.app_list.items.len(), 1);
+        assert_eq!(app.app_list.items[0].0, "firefox");
+    }
+
+    #[test]
+    fn custom_range_parses_and_validates() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        assert_eq!(
+            AppListTime::parse_custom("2025-01-01 2025-01-31"),
+            Ok(AppListTime::Custom {
+                start: date("2025-01-01"),
+                end: date("2025-01-31"),
+            })
+        );
+        assert_eq!(
+            AppListTime::parse_custom(" 2025-01-01 "),
+            Ok(AppListTime::Custom {
+                start: date("2025-01-01"),
+                end: date("2025-01-01"),
+            })
+        );
+        assert!(AppListTime::parse_custom("").is_err());
+        assert!(AppListTime::parse_custom("2025-13-01").is_err());
+        assert!(AppListTime::parse_custom("2025-01-31 2025-01-01").is_err());
+        assert!(AppListTime::parse_custom("2025-01-01 2025-01-02 2025-01-03").is_err());
+
+        // both ends are included
+        let (start, end) = AppListTime::parse_custom("2025-01-01 2025-01-02")
+            .unwrap()
+            .timestamps()
+            .unwrap();
+        assert_eq!(end - start, 2 * appusage_core::DAY_MS);
+    }
+
+    #[test]
+    fn ranges_compare_with_the_ones_before_them() {
+        use AppListTime::*;
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let previous = |time: AppListTime, today| {
+            let (start, end) = time.previous_days(date(today), Weekday::Mon).unwrap();
+            (start.to_string(), end.to_string())
+        };
+        let range = |start: &str, end: &str| (start.to_string(), end.to_string());
+
+        assert_eq!(
+            previous(Today, "2025-03-01"),
+            range("2025-02-28", "2025-03-01")
+        );
+        // a wednesday, so the monday and tuesday of the week before count too
+        assert_eq!(
+            previous(ThisWeek, "2025-01-01"),
+            range("2024-12-23", "2024-12-26")
+        );
+        assert_eq!(
+            previous(ThisMonth, "2025-03-31"),
+            range("2025-02-01", "2025-03-01")
+        );
+        assert_eq!(
+            previous(
+                Custom {
+                    start: date("2025-05-10"),
+                    end: date("2025-05-11")
+                },
+                "2025-06-01"
+            ),
+            range("2025-05-08", "2025-05-10")
+        );
+        assert_eq!(
+            AllTime.previous_days(date("2025-01-01"), Weekday::Mon),
+            None
+        );
+    }
+
+    #[test]
+    fn calendar_ranges_follow_month_and_year_boundaries() {
+        use AppListTime::*;
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let days = |time: AppListTime, today, week_start| {
+            let (start, end) = time.days(date(today), week_start).unwrap();
+            (start.to_string(), end.to_string())
+        };
+        let range = |start: &str, end: &str| (start.to_string(), end.to_string());
+
+        // the 1st is a month of its own
+        assert_eq!(
+            days(ThisMonth, "2025-03-01", Weekday::Mon),
+            range("2025-03-01", "2025-03-02")
+        );
+        assert_eq!(
+            days(ThisMonth, "2024-02-29", Weekday::Mon),
+            range("2024-02-01", "2024-03-01")
+        );
+        assert_eq!(
+            days(Last28Days, "2025-03-01", Weekday::Mon),
+            range("2025-02-02", "2025-03-02")
+        );
+
+        // 2025-01-01 is a wednesday, its week started in the year before
+        assert_eq!(
+            days(ThisWeek, "2025-01-01", Weekday::Mon),
+            range("2024-12-30", "2025-01-02")
+        );
+        assert_eq!(
+            days(ThisWeek, "2025-01-01", Weekday::Sun),
+            range("2024-12-29", "2025-01-02")
+        );
+        assert_eq!(
+            days(ThisWeek, "2024-12-29", Weekday::Sun),
+            range("2024-12-29", "2024-12-30")
+        );
+        assert_eq!(
+            days(Last7Days, "2025-01-01", Weekday::Mon),
+            range("2024-12-26", "2025-01-02")
+        );
+        assert_eq!(
+            days(ThisMonth, "2025-01-01", Weekday::Mon),
+            range("2025-01-01", "2025-01-02")
+        );
+    }
+
+    #[test]
+    fn invalid_date_range_keeps_prompt_open() {
+        let mut app = test_app();
+        app.input = Some(Input {
+            kind: InputKind::DateRange,
+            text: "2025-02-01 2025-01-01".to_string(),
+            error: None,
+        });
+        app.handle_input_key(KeyCode::Enter);
+        assert!(app.input.as_ref().is_some_and(|x| x.error.is_some()));
+        assert_eq!(app.app_list.time_to_show, AppListTime::Today);
+    }
+
+    #[test]
+    fn app_list_time_steps_between_ranges() {
+        use AppListTime::*;
+
+        for (time, next, prev) in [
+            (Today, ThisWeek, Today),
+            (ThisWeek, Last7Days, Today),
+            (Last7Days, ThisMonth, ThisWeek),
+            (ThisMonth, Last28Days, Last7Days),
+            (Last28Days, AllTime, ThisMonth),
+            (AllTime, AllTime, Last28Days),
+        ] {
+            assert_eq!(time.next(), next, "next of {time}");
+            assert_eq!(time.prev(), prev, "prev of {time}");
+        }
+    }
+
+    #[test]
+    fn weekday_averages_count_days_without_usage() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let totals = [0, 3000, 600, 900, 0, 0, 0];
+
+        // monday to wednesday, so the rest of the week didn't come up yet
+        assert_eq!(
+            weekday_averages(totals, date("2024-01-01"), date("2024-01-03")),
+            [None, Some(3000), Some(600), Some(900), None, None, None]
+        );
+        // two mondays, and every other weekday once
+        assert_eq!(
+            weekday_averages(totals, date("2024-01-01"), date("2024-01-08")),
+            [0, 1500, 600, 900, 0, 0, 0].map(Some)
+        );
     }
 }
@@ -0,0 +1,21 @@
+//! Deletes old sessions on request, like the daemon does on startup when `retention_days` is
+//! configured.
+
+use std::error::Error;
+
+use appusage_core::retention;
+use rusqlite::Connection;
+
+/// Prunes sessions older than `days` from the main database and prints how many there were.
+pub fn run(conn: &Connection, days: u32, archive: bool) -> Result<(), Box<dyn Error>> {
+    let pruned = retention::prune_older_than(conn, retention::cutoff(days), archive)?;
+    println!(
+        "pruned {pruned} sessions older than {days} days{}",
+        if archive {
+            ", their usage was archived"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Prints the raw rows recorded for an app, to see exactly how its usage was split up.
+
+use std::error::Error;
+
+use chrono::{Local, NaiveDate, SecondsFormat, TimeZone};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::{db, local_day_bounds};
+
+/// A session as printed with `--json`.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Session<'a> {
+    app: &'a str,
+    /// Local time in ISO 8601, like the end.
+    start: String,
+    end: String,
+    duration_ms: u64,
+}
+
+/// Prints every session of `app` that started on `date`, as a table or as JSON.
+pub fn print_sessions(
+    conn: &Connection,
+    app: &str,
+    date: NaiveDate,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let bounds = local_day_bounds(date).ok_or("date is out of range")?;
+    let sessions = db::get_app_sessions(conn, app, bounds)?;
+
+    if json {
+        let rows = sessions
+            .iter()
+            .map(|&(start, end, duration)| Session {
+                app,
+                start: format_time(start),
+                end: format_time(end),
+                duration_ms: duration,
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string(&rows)?);
+        return Ok(());
+    }
+
+    if sessions.is_empty() {
+        println!("no sessions of {app} on {date}");
+        return Ok(());
+    }
+    for (start, end, duration) in sessions {
+        println!(
+            "{}  {}  {}",
+            format_time(start),
+            format_time(end),
+            humantime::format_duration(std::time::Duration::from_millis(duration))
+        );
+    }
+    Ok(())
+}
+
+/// Formats ms since the epoch as an ISO 8601 timestamp in local time.
+fn format_time(millis: u64) -> String {
+    Local
+        .timestamp_millis_opt(millis as i64)
+        .single()
+        .map_or_else(
+            || millis.to_string(),
+            |time| time.to_rfc3339_opts(SecondsFormat::Millis, false),
+        )
+}
@@ -0,0 +1,42 @@
+//! Prints a few facts about everything that was recorded, like the busiest day so far.
+
+use std::{error::Error, time};
+
+use chrono::{Local, TimeZone};
+use rusqlite::Connection;
+
+use crate::db;
+
+/// Prints the first recorded time, the busiest day and the longest session.
+pub fn print_stats(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    let stats = db::get_stats(conn)?;
+    let Some(first_seen) = stats.first_seen else {
+        println!("nothing recorded yet");
+        return Ok(());
+    };
+
+    println!("first recorded:  {}", format_time(first_seen));
+    if let Some((day, total)) = stats.busiest_day {
+        println!("busiest day:     {day}, {}", format_duration(total));
+    }
+    if let Some((app, start, duration)) = stats.longest_session {
+        println!(
+            "longest session: {app}, {} from {}",
+            format_duration(duration),
+            format_time(start)
+        );
+    }
+    Ok(())
+}
+
+/// Formats milliseconds since the epoch as local time, down to the minute.
+fn format_time(millis: u64) -> String {
+    Local
+        .timestamp_millis_opt(millis as i64)
+        .single()
+        .map_or_else(|| millis.to_string(), |x| x.format("%F %R").to_string())
+}
+
+fn format_duration(millis: u64) -> String {
+    humantime::format_duration(time::Duration::from_secs(millis / 1000)).to_string()
+}
@@ -0,0 +1,86 @@
+//! Prints a single line summary, meant for status bars like waybar or i3status.
+
+use std::{error::Error, path::PathBuf, time::UNIX_EPOCH};
+
+use appusage_core::config::Config;
+use chrono::Local;
+use rusqlite::Connection;
+
+use crate::{
+    AppListTime, attach_databases, db,
+    focus::{self, Focus},
+};
+
+/// Format used when none is given on the command line.
+pub const DEFAULT_FORMAT: &str = "{app}: {today}";
+
+/// Reads the databases like the tui does and prints the status, without checking their
+/// integrity or upgrading them first, which is too much to do every few seconds.
+pub fn run(paths: &[PathBuf], config: &Config, format: &str) -> Result<(), Box<dyn Error>> {
+    let (conn, sources, _) = attach_databases(paths, config)?;
+    db::create_usage_view(&conn, &sources)?;
+    let focus = focus::Daemon::default().current_focus();
+    print_status(&conn, format, focus.as_ref())
+}
+
+/// Prints `format` with its placeholders filled in for the focused app, or for the app used
+/// the most today if the daemon can't be asked.
+///
+/// Supported placeholders are `{app}` for the app, `{today}` for how long it was used today
+/// and `{total}` for how long all apps were used today combined. Nothing is printed if
+/// nothing is focused and nothing was used yet today.
+pub fn print_status(
+    conn: &Connection,
+    format: &str,
+    focus: Option<&Focus>,
+) -> Result<(), Box<dyn Error>> {
+    let range = AppListTime::Today.timestamps();
+    let mut apps = db::list_apps(conn, range)?;
+
+    // the session of the focused app is only written once it ends
+    let app = match focus {
+        Some(focus) => {
+            let now = Local::now().timestamp_millis() as u64;
+            let start = range.map_or(0, |(start, _)| start);
+            let since = focus
+                .since
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let running = now.saturating_sub(since.max(start));
+            match apps.iter_mut().find(|(app, _)| *app == focus.app_id) {
+                Some((_, today)) => *today += running,
+                None => apps.push((focus.app_id.clone(), running)),
+            }
+            focus.app_id.as_str()
+        }
+        None => match apps.first() {
+            Some((app, _)) => app.as_str(),
+            None => return Ok(()),
+        },
+    };
+    let today = apps
+        .iter()
+        .find(|(name, _)| name == app)
+        .map_or(0, |(_, duration)| *duration);
+    let total = apps.iter().map(|(_, duration)| duration).sum::<u64>();
+
+    println!(
+        "{}",
+        format
+            .replace("{app}", app)
+            .replace("{today}", &format_short(today))
+            .replace("{total}", &format_short(total))
+    );
+
+    Ok(())
+}
+
+/// Formats milliseconds as hours and minutes (`1h 5m`), which is precise enough for a bar.
+fn format_short(millis: u64) -> String {
+    let minutes = millis / 60_000;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
@@ -0,0 +1,165 @@
+//! Counts how many days in a row an app, or all of them together, met a daily target.
+//!
+//! Targets come from the config, `daily_goals` are reached by using an app at least that
+//! long, `daily_limits` and `daily_total_limit` by staying under them.
+
+use std::collections::HashMap;
+
+use appusage_core::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Used for at least this many ms.
+    AtLeast(u64),
+    /// Used for at most this many ms.
+    AtMost(u64),
+}
+
+impl Target {
+    pub fn is_met(self, usage: u64) -> bool {
+        match self {
+            Target::AtLeast(target) => usage >= target,
+            Target::AtMost(target) => usage <= target,
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (kind, ms) = match self {
+            Target::AtLeast(ms) => ("at least", ms),
+            Target::AtMost(ms) => ("at most", ms),
+        };
+        write!(
+            f,
+            "{kind} {} a day",
+            humantime::format_duration(std::time::Duration::from_secs(ms / 1000))
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Targets {
+    apps: HashMap<String, Target>,
+    total: Option<Target>,
+}
+
+impl Targets {
+    /// Reads the targets from the config, an app with both a goal and a limit goes by its
+    /// goal.
+    pub fn from_config(config: &Config) -> Self {
+        let ms = |minutes: &u64| minutes * 60_000;
+        let mut apps = config
+            .daily_limits
+            .iter()
+            .map(|(app_id, minutes)| (app_id.clone(), Target::AtMost(ms(minutes))))
+            .collect::<HashMap<_, _>>();
+        apps.extend(
+            config
+                .daily_goals
+                .iter()
+                .map(|(app_id, minutes)| (app_id.clone(), Target::AtLeast(ms(minutes)))),
+        );
+        Self {
+            apps,
+            total: config
+                .daily_total_limit
+                .as_ref()
+                .map(ms)
+                .map(Target::AtMost),
+        }
+    }
+
+    pub fn of_app(&self, app_id: &str) -> Option<Target> {
+        self.apps.get(app_id).copied()
+    }
+
+    pub fn total(&self) -> Option<Target> {
+        self.total
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Streaks {
+    /// Days in a row up to today the target was met.
+    pub current: usize,
+    pub best: usize,
+}
+
+/// Finds the streaks in the usage of consecutive days, oldest first, the last one being
+/// today.
+///
+/// Today isn't over yet, so a goal not reached yet doesn't end the current streak, it just
+/// doesn't count towards it. A limit that is already exceeded does end it.
+pub fn streaks(days: &[u64], target: Target) -> Streaks {
+    let mut found = Streaks::default();
+    let mut run = 0;
+    for usage in days {
+        if target.is_met(*usage) {
+            run += 1;
+            found.best = found.best.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    found.current = run;
+
+    if let (Some((today, past)), Target::AtLeast(_)) = (days.split_last(), target) {
+        if !target.is_met(*today) {
+            found.current = streaks(past, target).current;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streaks_are_counted() {
+        let limit = Target::AtMost(10);
+        // days without any usage stay under every limit
+        assert_eq!(
+            streaks(&[5, 20, 0, 10, 5, 0, 30, 5, 5], limit),
+            Streaks {
+                current: 2,
+                best: 4
+            }
+        );
+        assert_eq!(
+            streaks(&[5, 5, 20], limit),
+            Streaks {
+                current: 0,
+                best: 2
+            }
+        );
+        assert_eq!(streaks(&[], limit), Streaks::default());
+    }
+
+    #[test]
+    fn todays_goal_can_still_be_reached() {
+        let goal = Target::AtLeast(10);
+        assert_eq!(
+            streaks(&[10, 0, 15, 10, 3], goal),
+            Streaks {
+                current: 2,
+                best: 2
+            }
+        );
+        assert_eq!(
+            streaks(&[10, 0, 15, 10, 12], goal),
+            Streaks {
+                current: 3,
+                best: 3
+            }
+        );
+        assert_eq!(
+            streaks(&[10, 0, 0], goal),
+            Streaks {
+                current: 0,
+                best: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Draws the sessions of a single day as an SVG image, for sharing or keeping a journal.
+
+use std::{collections::HashMap, error::Error, fmt::Write, fs, path::Path};
+
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+use rusqlite::Connection;
+
+use crate::{db, local_day_bounds, local_millis_in};
+
+const WIDTH: u64 = 1200;
+const MARGIN: u64 = 40;
+const BAR_Y: u64 = 40;
+const BAR_HEIGHT: u64 = 60;
+const LEGEND_ROW_HEIGHT: u64 = 22;
+
+/// Writes the timeline of `date` (in local time) to `path` as an SVG.
+pub fn export(conn: &Connection, date: NaiveDate, path: &Path) -> Result<(), Box<dyn Error>> {
+    let (start, end) = local_day_bounds(date).ok_or("date is out of range")?;
+
+    let sessions = db::get_sessions(conn, (start, end))?;
+    let ticks = hour_ticks(&Local, date);
+    fs::write(
+        path,
+        render(&sessions, start, end, &ticks, &date.to_string()),
+    )?;
+    Ok(())
+}
+
+/// Returns when every third hour of `date` starts in `tz`, in ms, along with its label.
+///
+/// Days the clocks change on are an hour shorter or longer, so the hours aren't spread
+/// evenly over those.
+fn hour_ticks<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> Vec<(u64, String)> {
+    (0..=24)
+        .step_by(3)
+        .map(|hour| {
+            let time = date.and_time(NaiveTime::MIN) + chrono::Duration::hours(hour);
+            (local_millis_in(tz, time), format!("{hour:02}:00"))
+        })
+        .collect()
+}
+
+/// Renders `(app_name, start_time, end_time)` sessions between `start` and `end` as a single
+/// bar with one colored slice per session, followed by a legend with the total of each app.
+/// The time axis is marked at `ticks`.
+fn render(
+    sessions: &[(String, u64, u64)],
+    start: u64,
+    end: u64,
+    ticks: &[(u64, String)],
+    title: &str,
+) -> String {
+    let bar_width = WIDTH - 2 * MARGIN;
+    let x_of = |time: u64| {
+        MARGIN + time.clamp(start, end).saturating_sub(start) * bar_width / (end - start).max(1)
+    };
+
+    let mut totals: HashMap<&str, u64> = HashMap::new();
+    for (app, session_start, session_end) in sessions {
+        *totals.entry(app.as_str()).or_default() += session_end.saturating_sub(*session_start);
+    }
+    let mut legend = totals.into_iter().collect::<Vec<_>>();
+    legend.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let legend_y = BAR_Y + BAR_HEIGHT + 50;
+    let height = legend_y + legend.len() as u64 * LEGEND_ROW_HEIGHT + MARGIN / 2;
+
+    // writing to a String can't fail
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" font-family="sans-serif" font-size="13">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(
+        svg,
+        r#"<text x="{MARGIN}" y="25" font-size="16">{}</text>"#,
+        escape(title)
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{MARGIN}" y="{BAR_Y}" width="{bar_width}" height="{BAR_HEIGHT}" fill="#eee"/>"##
+    );
+
+    for (app, session_start, session_end) in sessions {
+        let x = x_of(*session_start);
+        let width = x_of(*session_end).saturating_sub(x).max(1);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{x}" y="{BAR_Y}" width="{width}" height="{BAR_HEIGHT}" fill="{}"><title>{}</title></rect>"#,
+            color_of(app),
+            escape(app)
+        );
+    }
+
+    for (time, label) in ticks {
+        let x = x_of(*time);
+        let tick_y = BAR_Y + BAR_HEIGHT;
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x}" y1="{tick_y}" x2="{x}" y2="{}" stroke="#666"/>"##,
+            tick_y + 6
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{x}" y="{}" text-anchor="middle">{label}</text>"#,
+            tick_y + 20
+        );
+    }
+
+    for (i, (app, total)) in legend.iter().enumerate() {
+        let y = legend_y + i as u64 * LEGEND_ROW_HEIGHT;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{MARGIN}" y="{}" width="14" height="14" fill="{}"/>"#,
+            y - 12,
+            color_of(app)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{y}">{} ({})</text>"#,
+            MARGIN + 22,
+            escape(app),
+            humantime::format_duration(std::time::Duration::from_secs(total / 1000))
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Picks a color from the app name alone, so an app looks the same in every export.
+fn color_of(app: &str) -> String {
+    // fnv-1a, std's hasher may change between releases
+    let hash = app.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("hsl({}, 60%, 55%)", hash % 360)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, LocalResult, NaiveDateTime};
+
+    use super::*;
+
+    /// Central European time around the night of 2024-03-31, when the clocks went from
+    /// 02:00 straight to 03:00.
+    #[derive(Debug, Clone)]
+    struct SpringForward;
+
+    impl SpringForward {
+        fn switch() -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2024, 3, 31)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap()
+        }
+
+        fn offset(summer: bool) -> FixedOffset {
+            FixedOffset::east_opt(if summer { 7200 } else { 3600 }).unwrap()
+        }
+    }
+
+    impl TimeZone for SpringForward {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            SpringForward
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let switch = Self::switch();
+            let hour = chrono::Duration::hours(1);
+            if *local < switch + hour {
+                LocalResult::Single(Self::offset(false))
+            } else if *local < switch + hour * 2 {
+                LocalResult::None
+            } else {
+                LocalResult::Single(Self::offset(true))
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset(*utc >= Self::switch())
+        }
+    }
+
+    #[test]
+    fn hours_follow_the_clocks_on_a_dst_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let ticks = hour_ticks(&SpringForward, date);
+        let hour = 3_600_000;
+        let (start, end) = (ticks[0].0, ticks[ticks.len() - 1].0);
+        assert_eq!(end - start, 23 * hour);
+        // 03:00 comes two hours after midnight, every later tick is an hour early too
+        assert_eq!(ticks[1], (start + 2 * hour, "03:00".to_string()));
+        assert_eq!(ticks[2], (start + 5 * hour, "06:00".to_string()));
+        assert_eq!(ticks[8].1, "24:00");
+
+        // a session from 03:00 to 06:00 lines up with the ticks of those hours
+        let sessions = [("firefox".to_string(), ticks[1].0, ticks[2].0)];
+        let svg = render(&sessions, start, end, &ticks, "2024-03-31");
+        let bar_width = WIDTH - 2 * MARGIN;
+        let x = MARGIN + 2 * hour * bar_width / (23 * hour);
+        assert!(
+            svg.contains(&format!(r#"<rect x="{x}" y="{BAR_Y}""#)),
+            "{svg}"
+        );
+        assert!(svg.contains(&format!(r#"<line x1="{x}""#)), "{svg}");
+    }
+}
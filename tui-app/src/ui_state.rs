@@ -0,0 +1,47 @@
+//! Parts of the ui that are remembered between runs.
+//!
+//! This is stored as simple `key=value` lines in the XDG state directory, unknown keys and
+//! unparsable values are ignored so an old or hand edited file never stops the tui from
+//! starting.
+
+use std::{fs, io, path::PathBuf};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UiState {
+    /// Show each app's share of the total instead of its duration.
+    pub show_percentage: bool,
+}
+
+fn path() -> io::Result<PathBuf> {
+    xdg::BaseDirectories::with_prefix("wayland-appusage")
+        .map_err(io::Error::other)?
+        .place_state_file("tui-state")
+}
+
+impl UiState {
+    /// Loads the saved state, falling back to the defaults if there is none.
+    pub fn load() -> Self {
+        let mut state = Self::default();
+
+        let Ok(contents) = path().and_then(fs::read_to_string) else {
+            return state;
+        };
+
+        for (key, value) in contents.lines().filter_map(|line| line.split_once('=')) {
+            if key.trim() == "show_percentage" {
+                if let Ok(value) = value.trim().parse() {
+                    state.show_percentage = value;
+                }
+            }
+        }
+
+        state
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(
+            path()?,
+            format!("show_percentage={}\n", self.show_percentage),
+        )
+    }
+}
@@ -0,0 +1,16 @@
+//! Compacts the database on request, like the daemon does every `vacuum_interval_days`.
+
+use std::error::Error;
+
+use appusage_core::maintenance;
+use rusqlite::Connection;
+
+/// Vacuums the main database and prints how its size changed.
+pub fn run(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    let sizes = maintenance::run(conn)?;
+    println!(
+        "vacuumed the database from {} to {} bytes",
+        sizes.before, sizes.after
+    );
+    Ok(())
+}